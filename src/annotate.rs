@@ -0,0 +1,79 @@
+use crate::range::parse_number;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("annotation line {line}: {reason}")]
+pub struct AnnotationError {
+    line: usize,
+    reason: &'static str,
+}
+
+/// A labelled region of the hexdump, e.g. one field of a firmware header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    /// offset of the first byte, as shown in the hexdump
+    pub offset: u64,
+    pub len: u64,
+    pub label: String,
+}
+
+impl Annotation {
+    /// One past the last byte of the region
+    pub fn end(&self) -> u64 {
+        self.offset.saturating_add(self.len)
+    }
+}
+
+/// Parse an annotation layout file. Each line is "OFFSET[,LEN] LABEL", where LEN defaults to 1
+/// and the label is the rest of the line. Blank lines and lines starting with '#' are ignored.
+/// The result is sorted by offset.
+pub fn parse(text: &str) -> Result<Vec<Annotation>, AnnotationError> {
+    let mut annotations = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let err = |reason| AnnotationError { line: i + 1, reason };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (region, label) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let label = label.trim();
+        if label.is_empty() {
+            return Err(err("missing label"));
+        }
+        let number = |s: &str| parse_number(s).map_err(|_| err("invalid number"));
+        let (offset, len) = match region.split_once(',') {
+            Some((offset, len)) => (number(offset)?, number(len)?),
+            None => (number(region)?, 1),
+        };
+        if len == 0 {
+            return Err(err("zero length region"));
+        }
+        annotations.push(Annotation { offset, len, label: label.to_owned() });
+    }
+    annotations.sort_by_key(|a| a.offset);
+    Ok(annotations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_layout() {
+        let text = "# image header\n\
+                    0x10,4 load address\n\
+                    0,4  magic\n\
+                    \n\
+                    0x14 flags\n";
+        let ann = |offset, len, label: &str| Annotation { offset, len, label: label.into() };
+        assert_eq!(
+            parse(text).unwrap(),
+            [ann(0, 4, "magic"), ann(0x10, 4, "load address"), ann(0x14, 1, "flags")]
+        );
+
+        let err = |text| parse(text).unwrap_err().to_string();
+        assert_eq!(err("0x10\n"), "annotation line 1: missing label");
+        assert_eq!(err("\nx,4 magic\n"), "annotation line 2: invalid number");
+        assert_eq!(err("0,0 magic\n"), "annotation line 1: zero length region");
+    }
+}
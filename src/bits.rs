@@ -0,0 +1,106 @@
+use std::io::{self, Read};
+
+use crate::range::Range;
+
+/// Convert a range of bits into the range of bytes which holds it, and the number of bits to skip
+/// at the start of the first of those bytes
+pub fn byte_range(bits: &Range) -> (Range, u32) {
+    let shift = (bits.start % 8) as u32;
+    let count = bits.count.map(|count| count.saturating_add(shift.into()).div_ceil(8));
+    (Range { start: bits.start / 8, count }, shift)
+}
+
+/// Reader which extracts a range of bits from the bytes holding it, shifting them to start at a
+/// byte boundary. Bits are numbered from the most significant bit of each byte, like most packed
+/// bitstream formats, and the last byte is padded with zero bits.
+pub struct BitReader<R> {
+    inner: R,
+    /// bits to skip at the start of the first byte
+    shift: u32,
+    /// bits left to output, None to carry on to EOF
+    bits_left: Option<u64>,
+    /// last byte read, whose low bits start the next output byte
+    prev: Option<u8>,
+}
+
+impl<R: Read> BitReader<R> {
+    pub fn new(inner: R, shift: u32, bits: Option<u64>) -> Self {
+        Self { inner, shift, bits_left: bits, prev: None }
+    }
+}
+
+impl<R: Read> Read for BitReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = match self.bits_left {
+            Some(0) => return Ok(0),
+            Some(bits) => bits.div_ceil(8).min(buf.len() as u64) as usize,
+            None => buf.len(),
+        };
+        if max == 0 {
+            return Ok(0);
+        }
+        let buf = &mut buf[..max];
+
+        // each output byte needs the next input byte too, so the first read can come up short
+        let mut count = 0;
+        while count == 0 {
+            let n = self.inner.read(buf)?;
+            if n == 0 {
+                // the last byte only has the bits after the shift
+                let Some(prev) = self.prev.take() else {
+                    return Ok(0);
+                };
+                buf[0] = prev << self.shift;
+                count = 1;
+                break;
+            }
+            // output bytes are never ahead of input bytes, so this can be done in place
+            for i in 0..n {
+                let byte = buf[i];
+                if let Some(prev) = self.prev {
+                    buf[count] =
+                        (prev << self.shift) | byte.checked_shr(8 - self.shift).unwrap_or(0);
+                    count += 1;
+                }
+                self.prev = Some(byte);
+            }
+        }
+
+        if let Some(bits) = self.bits_left.as_mut() {
+            let read = count as u64 * 8;
+            if read >= *bits {
+                // clear the bits past the end of the range
+                buf[count - 1] &= 0xff << (read - *bits);
+                *bits = 0;
+            } else {
+                *bits -= read;
+            }
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_bits() {
+        let extract = |start, count| {
+            let (bytes, shift) = byte_range(&Range { start, count });
+            let data = [0x12u8, 0x34, 0x56, 0x78];
+            let input = &data[bytes.start as usize..];
+            let input = input.take(bytes.count.unwrap_or(u64::MAX));
+            let mut out = Vec::new();
+            BitReader::new(input, shift, count).read_to_end(&mut out).unwrap();
+            out
+        };
+        assert_eq!(extract(0, Some(16)), [0x12, 0x34]);
+        assert_eq!(extract(4, Some(16)), [0x23, 0x45]);
+        assert_eq!(extract(4, Some(12)), [0x23, 0x40]);
+        assert_eq!(extract(9, Some(3)), [0x60]);
+        assert_eq!(extract(12, None), [0x45, 0x67, 0x80]);
+        assert_eq!(extract(8, None), [0x34, 0x56, 0x78]);
+        assert_eq!(extract(30, Some(0)), []);
+    }
+}
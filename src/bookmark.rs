@@ -0,0 +1,140 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::range::RangeSpec;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BookmarkError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("{path} line {line}: bad bookmark")]
+    Parse { path: String, line: usize },
+    #[error("bookmark names can't be empty or contain spaces or '@'")]
+    BadName,
+    #[error("invalid range: {0}")]
+    BadRange(String),
+    #[error("no bookmark named '{0}'")]
+    NotFound(String),
+}
+
+/// A named range of one particular file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bookmark {
+    pub name: String,
+    /// the range as it was given, in the same syntax as the RANGE argument
+    pub range: String,
+}
+
+/// Get the sidecar file which holds the bookmarks for file, e.g. "flash.img.bookmarks"
+pub fn sidecar_path(file: &Path) -> PathBuf {
+    let mut name = OsString::from(file.as_os_str());
+    name.push(".bookmarks");
+    name.into()
+}
+
+/// Load the bookmarks for file. A missing sidecar just means there aren't any.
+pub fn load(file: &Path) -> Result<Vec<Bookmark>, BookmarkError> {
+    let path = sidecar_path(file);
+    match fs::read_to_string(&path) {
+        Ok(text) => parse(&text, &path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Parse the contents of a sidecar file, one "NAME RANGE" per line
+fn parse(text: &str, path: &Path) -> Result<Vec<Bookmark>, BookmarkError> {
+    let mut bookmarks = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [name, range] => bookmarks.push(Bookmark { name: name.into(), range: range.into() }),
+            _ => {
+                return Err(BookmarkError::Parse { path: path.display().to_string(), line: i + 1 })
+            }
+        }
+    }
+    Ok(bookmarks)
+}
+
+fn save(file: &Path, bookmarks: &[Bookmark]) -> Result<(), BookmarkError> {
+    let text: String = bookmarks.iter().map(|b| format!("{} {}\n", b.name, b.range)).collect();
+    fs::write(sidecar_path(file), text)?;
+    Ok(())
+}
+
+/// Check a new bookmark and add it to the list, replacing any existing one with the same name
+fn insert(bookmarks: &mut Vec<Bookmark>, name: &str, range: &str) -> Result<(), BookmarkError> {
+    if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '@') {
+        return Err(BookmarkError::BadName);
+    }
+    range.parse::<RangeSpec>().map_err(|e| BookmarkError::BadRange(e.to_string()))?;
+
+    let bookmark = Bookmark { name: name.into(), range: range.into() };
+    match bookmarks.iter_mut().find(|b| b.name == name) {
+        Some(existing) => *existing = bookmark,
+        None => bookmarks.push(bookmark),
+    }
+    Ok(())
+}
+
+/// Add or replace a bookmark for file
+pub fn add(file: &Path, name: &str, range: &str) -> Result<(), BookmarkError> {
+    let mut bookmarks = load(file)?;
+    insert(&mut bookmarks, name, range)?;
+    save(file, &bookmarks)
+}
+
+/// Remove a bookmark from file
+pub fn remove(file: &Path, name: &str) -> Result<(), BookmarkError> {
+    let mut bookmarks = load(file)?;
+    let len = bookmarks.len();
+    bookmarks.retain(|b| b.name != name);
+    if bookmarks.len() == len {
+        return Err(BookmarkError::NotFound(name.into()));
+    }
+    save(file, &bookmarks)
+}
+
+/// Get the range text of a bookmark of file
+pub fn lookup(file: &Path, name: &str) -> Result<String, BookmarkError> {
+    load(file)?
+        .into_iter()
+        .find(|b| b.name == name)
+        .map(|b| b.range)
+        .ok_or_else(|| BookmarkError::NotFound(name.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bookmarks() {
+        let path = Path::new("flash.img.bookmarks");
+        let text = "# regions of flash.img\nboot 0-0xffff\n\nnvram  0x10000+0x1000\n";
+        let mut bookmarks = parse(text, path).unwrap();
+        let bookmark =
+            |name: &str, range: &str| Bookmark { name: name.into(), range: range.into() };
+        assert_eq!(bookmarks, [bookmark("boot", "0-0xffff"), bookmark("nvram", "0x10000+0x1000")]);
+
+        insert(&mut bookmarks, "boot", "0+0x20000").unwrap();
+        insert(&mut bookmarks, "env", "-0x100").unwrap();
+        assert_eq!(bookmarks[0], bookmark("boot", "0+0x20000"));
+        assert_eq!(bookmarks[2], bookmark("env", "-0x100"));
+        assert!(matches!(insert(&mut bookmarks, "a b", "0-"), Err(BookmarkError::BadName)));
+        assert!(matches!(insert(&mut bookmarks, "x@", "0-"), Err(BookmarkError::BadName)));
+        assert!(matches!(insert(&mut bookmarks, "x", "what"), Err(BookmarkError::BadRange(_))));
+
+        assert_eq!(
+            parse("boot 0-1 extra\n", path).unwrap_err().to_string(),
+            "flash.img.bookmarks line 1: bad bookmark"
+        );
+        assert_eq!(sidecar_path(Path::new("a/b.bin")), Path::new("a/b.bin.bookmarks"));
+    }
+}
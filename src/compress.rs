@@ -0,0 +1,160 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// Compression format of an input or output file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+}
+
+/// Compression to apply to the output, from --compress METHOD[:LEVEL]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    /// None to write the output as it is, even if its name says it's compressed
+    pub method: Option<Method>,
+    /// compression level, or None for the method's default
+    pub level: Option<i32>,
+}
+
+/// Parse a --compress argument like "zstd" or "gzip:9", or "none"
+pub fn parse_compression(input: &str) -> Result<Compression, String> {
+    let (name, level) = match input.split_once(':') {
+        Some((name, level)) => (name, Some(level)),
+        None => (input, None),
+    };
+    if name == "none" {
+        return match level {
+            Some(_) => Err("none doesn't take a level".to_string()),
+            None => Ok(Compression { method: None, level: None }),
+        };
+    }
+    let (method, levels) = match name {
+        "gzip" | "gz" => (Method::Gzip, 0..=9),
+        "zstd" | "zst" => (Method::Zstd, -7..=22),
+        "xz" => (Method::Xz, 0..=9),
+        "bzip2" | "bz2" => (Method::Bzip2, 1..=9),
+        _ => return Err(format!("unknown compression method '{}'", name)),
+    };
+    let level = match level {
+        Some(level) => {
+            let level: i32 = level.parse().map_err(|_| format!("invalid level '{}'", level))?;
+            if !levels.contains(&level) {
+                return Err(format!(
+                    "{} level must be from {} to {}",
+                    name,
+                    levels.start(),
+                    levels.end()
+                ));
+            }
+            Some(level)
+        }
+        None => None,
+    };
+    Ok(Compression { method: Some(method), level })
+}
+
+/// Error for a compression format whose cargo feature wasn't enabled
+#[cfg(not(all(feature = "zstd", feature = "xz", feature = "bzip2")))]
+fn unsupported(feature: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("bcut was built without {} support", feature),
+    )
+}
+
+impl Method {
+    /// Get the compression implied by a file name's extension, if any
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gz" => Some(Self::Gzip),
+            "zst" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Identify the compression of data from its first few bytes, if it's a format we know
+    pub fn from_magic(header: &[u8]) -> Option<Self> {
+        if header.starts_with(b"\x1f\x8b") {
+            Some(Self::Gzip)
+        } else if header.starts_with(b"\x28\xb5\x2f\xfd") {
+            Some(Self::Zstd)
+        } else if header.starts_with(b"\xfd7zXZ\x00") {
+            Some(Self::Xz)
+        } else if header.starts_with(b"BZh") {
+            Some(Self::Bzip2)
+        } else {
+            None
+        }
+    }
+
+    /// Wrap input so that reading from it gives the decompressed data. Concatenated streams are
+    /// read as one, like gzip -d does.
+    pub fn decoder(self, input: Box<dyn Read + Send>) -> io::Result<Box<dyn Read + Send>> {
+        match self {
+            Self::Gzip => Ok(Box::new(flate2::read::MultiGzDecoder::new(input))),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Ok(Box::new(zstd::Decoder::new(input)?)),
+            #[cfg(not(feature = "zstd"))]
+            Self::Zstd => Err(unsupported("zstd")),
+            #[cfg(feature = "xz")]
+            Self::Xz => Ok(Box::new(xz2::read::XzDecoder::new_multi_decoder(input))),
+            #[cfg(not(feature = "xz"))]
+            Self::Xz => Err(unsupported("xz")),
+            #[cfg(feature = "bzip2")]
+            Self::Bzip2 => Ok(Box::new(bzip2::read::MultiBzDecoder::new(input))),
+            #[cfg(not(feature = "bzip2"))]
+            Self::Bzip2 => Err(unsupported("bzip2")),
+        }
+    }
+
+    /// Wrap output so that everything written to it is compressed, at level or the default level
+    /// if it's None. The compressed stream is finished when the returned writer is dropped.
+    pub fn encoder(self, output: Box<dyn Write>, level: Option<i32>) -> io::Result<Box<dyn Write>> {
+        match self {
+            Self::Gzip => {
+                let level = level.map_or_else(flate2::Compression::default, |level| {
+                    flate2::Compression::new(level as u32)
+                });
+                Ok(Box::new(flate2::write::GzEncoder::new(output, level)))
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => {
+                Ok(Box::new(zstd::Encoder::new(output, level.unwrap_or(0))?.auto_finish()))
+            }
+            #[cfg(not(feature = "zstd"))]
+            Self::Zstd => Err(unsupported("zstd")),
+            #[cfg(feature = "xz")]
+            Self::Xz => Ok(Box::new(xz2::write::XzEncoder::new(output, level.unwrap_or(6) as u32))),
+            #[cfg(not(feature = "xz"))]
+            Self::Xz => Err(unsupported("xz")),
+            #[cfg(feature = "bzip2")]
+            Self::Bzip2 => {
+                let level = level.map_or_else(bzip2::Compression::default, |level| {
+                    bzip2::Compression::new(level as u32)
+                });
+                Ok(Box::new(bzip2::write::BzEncoder::new(output, level)))
+            }
+            #[cfg(not(feature = "bzip2"))]
+            Self::Bzip2 => Err(unsupported("bzip2")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let zstd = parse_compression("zstd:19").unwrap();
+        assert_eq!(zstd, Compression { method: Some(Method::Zstd), level: Some(19) });
+        assert_eq!(parse_compression("gzip").unwrap().level, None);
+        assert_eq!(parse_compression("none").unwrap().method, None);
+        assert!(parse_compression("none:1").is_err());
+        assert!(parse_compression("gzip:10").is_err());
+        assert!(parse_compression("lz4").is_err());
+    }
+}
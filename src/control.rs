@@ -0,0 +1,217 @@
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::range::parse_size;
+
+/// Job state which the control socket can inspect and change
+#[derive(Debug)]
+struct State {
+    /// bytes read from the input so far
+    bytes: u64,
+    paused: bool,
+    aborted: bool,
+    /// input rate limit in bytes per second, 0 for no limit
+    rate: u64,
+    /// when the rate limit was last (re)started, and the byte count at that time
+    rate_start: (Instant, u64),
+}
+
+/// State shared between the reader and the socket threads. The condvar is signalled whenever the
+/// socket changes something, so that a paused or rate limited reader notices right away.
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+    start: Instant,
+}
+
+impl Shared {
+    fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            state: Mutex::new(State {
+                bytes: 0,
+                paused: false,
+                aborted: false,
+                rate: 0,
+                rate_start: (now, 0),
+            }),
+            changed: Condvar::new(),
+            start: now,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Run one command line from the socket and return the response line
+    fn handle(&self, line: &str) -> String {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let mut state = self.lock();
+        match words[..] {
+            ["status"] => {
+                return format!(
+                    "bytes {} elapsed {:.3} {} rate {}",
+                    state.bytes,
+                    self.start.elapsed().as_secs_f64(),
+                    if state.paused { "paused" } else { "running" },
+                    state.rate,
+                );
+            }
+            ["pause"] => state.paused = true,
+            ["resume"] => {
+                state.paused = false;
+                // don't let the time spent paused turn into a burst over the rate limit
+                state.rate_start = (Instant::now(), state.bytes);
+            }
+            ["abort"] => state.aborted = true,
+            ["rate", rate] => match parse_size(rate) {
+                Ok(rate) => {
+                    state.rate = rate;
+                    state.rate_start = (Instant::now(), state.bytes);
+                }
+                Err(e) => return format!("error {}", e),
+            },
+            _ => return "error unknown command".into(),
+        }
+        drop(state);
+        self.changed.notify_all();
+        "ok".into()
+    }
+}
+
+/// A unix socket which lets other programs check on and control a running job, using a
+/// line-based protocol. Each command gets a one line response, "ok", "error MESSAGE", or for
+/// status, "bytes N elapsed SECONDS running|paused rate N". The commands are:
+///
+///   status    show progress
+///   pause     stop reading input until resumed
+///   resume    continue after a pause
+///   abort     fail the job at the next read
+///   rate N    limit input to N bytes per second (K/M/G suffixes allowed), 0 for no limit
+///
+/// The socket file is removed when this is dropped.
+#[derive(Debug)]
+pub struct ControlSocket {
+    shared: Arc<Shared>,
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    /// Create the socket at path and start serving connections on a background thread
+    #[cfg(unix)]
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::{UnixListener, UnixStream};
+        use std::thread;
+
+        fn serve(shared: &Shared, stream: UnixStream) -> io::Result<()> {
+            let mut writer = stream.try_clone()?;
+            for line in BufReader::new(stream).lines() {
+                writeln!(writer, "{}", shared.handle(&line?))?;
+            }
+            Ok(())
+        }
+
+        let listener = UnixListener::bind(path)?;
+        let shared = Arc::new(Shared::new());
+        let server = shared.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let shared = server.clone();
+                thread::spawn(move || serve(&shared, stream));
+            }
+        });
+        Ok(Self { shared, path: path.to_owned() })
+    }
+
+    #[cfg(not(unix))]
+    pub fn bind(_path: &Path) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "control sockets need unix sockets"))
+    }
+
+    /// Wrap a reader so that it's paused, rate limited, and aborted by this socket
+    pub fn wrap<R: Read>(&self, inner: R) -> ControlledReader<R> {
+        ControlledReader { inner, shared: self.shared.clone() }
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A reader controlled through a ControlSocket
+pub struct ControlledReader<R> {
+    inner: R,
+    shared: Arc<Shared>,
+}
+
+impl<R: Read> Read for ControlledReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.shared.lock();
+        let max = loop {
+            if state.aborted {
+                return Err(io::Error::other("aborted from the control socket"));
+            }
+            if state.paused {
+                state = self.shared.changed.wait(state).unwrap();
+                continue;
+            }
+            if state.rate == 0 {
+                break buf.len();
+            }
+
+            // wait until we're no longer ahead of the rate limit
+            let (since, base) = state.rate_start;
+            let due = Duration::from_secs_f64((state.bytes - base) as f64 / state.rate as f64);
+            let elapsed = since.elapsed();
+            if due > elapsed {
+                state = self.shared.changed.wait_timeout(state, due - elapsed).unwrap().0;
+                continue;
+            }
+            // read at most a tenth of a second's worth at once so the rate stays smooth
+            break buf.len().min((state.rate / 10).max(1) as usize);
+        };
+        drop(state);
+
+        let n = self.inner.read(&mut buf[..max])?;
+        self.shared.lock().bytes += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        let shared = Arc::new(Shared::new());
+        let mut reader = ControlledReader { inner: &[0u8; 100][..], shared: shared.clone() };
+        let mut buf = [0u8; 30];
+        assert_eq!(reader.read(&mut buf).unwrap(), 30);
+
+        assert!(shared.handle("status").starts_with("bytes 30 elapsed "));
+        assert!(shared.handle("status").ends_with(" running rate 0"));
+        assert_eq!(shared.handle("pause"), "ok");
+        assert!(shared.handle("status").ends_with(" paused rate 0"));
+        assert_eq!(shared.handle("resume"), "ok");
+        assert_eq!(shared.handle(" rate  1M "), "ok");
+        assert!(shared.handle("status").ends_with(" rate 1048576"));
+        assert!(shared.handle("rate fast").starts_with("error "));
+        assert_eq!(shared.handle("stop"), "error unknown command");
+
+        // a tenth of a second's worth per read
+        assert_eq!(shared.handle("rate 100"), "ok");
+        assert_eq!(reader.read(&mut buf).unwrap(), 10);
+        assert_eq!(shared.handle("rate 0"), "ok");
+
+        assert_eq!(shared.handle("abort"), "ok");
+        assert!(reader.read(&mut buf).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use std::io::{self, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+
+/// Build a Command which runs cmd through the system shell
+pub fn shell_command(cmd: &str) -> Command {
+    #[cfg(unix)]
+    {
+        let mut c = Command::new("/bin/sh");
+        c.arg("-c").arg(cmd);
+        c
+    }
+    #[cfg(not(unix))]
+    {
+        let mut c = Command::new("cmd");
+        c.arg("/C").arg(cmd);
+        c
+    }
+}
+
+/// A reader which pipes its input through an external command and yields the command's output.
+///
+/// A background thread copies the input into the child's stdin while we read from its stdout.
+/// Once stdout hits EOF, the child is waited on and a nonzero exit status is reported as an
+/// error from read() so that it propagates like any other I/O error.
+pub struct FilterReader {
+    cmd: String,
+    child: Child,
+    stdout: ChildStdout,
+    feeder: Option<JoinHandle<io::Result<()>>>,
+    done: bool,
+}
+
+impl FilterReader {
+    pub fn spawn(cmd: &str, mut input: Box<dyn Read + Send>) -> io::Result<Self> {
+        let mut child = shell_command(cmd).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        let mut stdin = child.stdin.take().expect("child stdin is piped");
+        let stdout = child.stdout.take().expect("child stdout is piped");
+
+        let feeder = thread::spawn(move || match crate::io_copy(&mut input, &mut stdin) {
+            // The command doesn't have to consume all its input (e.g. head), if it stopped early
+            // that's for its exit status to decide.
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            Err(e) => Err(e),
+            Ok(_) => Ok(()),
+        });
+
+        Ok(Self { cmd: cmd.to_owned(), child, stdout, feeder: Some(feeder), done: false })
+    }
+
+    /// Wait for the input thread and child process to finish, and check the exit status
+    fn finish(&mut self) -> io::Result<()> {
+        self.done = true;
+        if let Some(feeder) = self.feeder.take() {
+            feeder.join().expect("filter input thread panicked")?;
+        }
+        let status = self.child.wait()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!("filter command '{}' failed: {}", self.cmd, status)))
+        }
+    }
+}
+
+impl Read for FilterReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done {
+            return Ok(0);
+        }
+        match self.stdout.read(buf)? {
+            0 => self.finish().map(|_| 0),
+            count => Ok(count),
+        }
+    }
+}
+
+impl Drop for FilterReader {
+    fn drop(&mut self) {
+        // if we bailed out early, don't leave the child running
+        if !self.done {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// A command run with --exec, which gets the output on its stdin
+pub struct Exec {
+    child: Child,
+}
+
+impl Exec {
+    pub fn spawn(cmd: &str) -> io::Result<Self> {
+        Ok(Self { child: shell_command(cmd).stdin(Stdio::piped()).spawn()? })
+    }
+
+    /// Get the writer for the command's stdin. Closing it lets the command see EOF.
+    pub fn stdin(&mut self) -> ExecStdin {
+        ExecStdin(self.child.stdin.take())
+    }
+
+    /// Wait for the command to exit
+    pub fn wait(mut self) -> io::Result<ExitStatus> {
+        self.child.wait()
+    }
+}
+
+/// Writer for an --exec command's stdin. The command doesn't have to read everything, so once it
+/// closes stdin the rest of the output is thrown away, and its exit status decides whether that
+/// was a problem.
+pub struct ExecStdin(Option<ChildStdin>);
+
+impl Write for ExecStdin {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(stdin) = &mut self.0 {
+            match stdin.write(buf) {
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => self.0 = None,
+                result => return result,
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            Some(stdin) => stdin.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn run_filter(cmd: &str, data: &'static [u8]) -> io::Result<Vec<u8>> {
+        let mut reader = FilterReader::spawn(cmd, Box::new(data))?;
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn filter() {
+        assert_eq!(run_filter("tr a-z A-Z", b"hello").unwrap(), b"HELLO");
+        assert_eq!(run_filter("head -c 2", b"hello").unwrap(), b"he");
+        assert!(run_filter("cat >/dev/null; exit 3", b"hello").is_err());
+    }
+
+    #[test]
+    fn exec() {
+        let mut exec = Exec::spawn("head -c 1 >/dev/null; exit 4").unwrap();
+        let mut stdin = exec.stdin();
+        // the command stops reading early, which isn't an error on our side
+        for _ in 0..100 {
+            stdin.write_all(&[0u8; 65536]).unwrap();
+        }
+        drop(stdin);
+        assert_eq!(exec.wait().unwrap().code(), Some(4));
+    }
+}
@@ -0,0 +1,551 @@
+use std::io::{self, Read, Write};
+
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+
+/// Output encodings for the selected bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Raw binary bytes
+    #[default]
+    Raw,
+    /// Rust array constant, `pub const DATA: [u8; N] = [...];`
+    Rust,
+    /// Rust slice constant, `pub const DATA: &[u8] = &[...];`
+    RustSlice,
+    /// Python bytes literal, `DATA = (b"\x.." ...)`
+    Python,
+    /// Intel HEX records
+    Ihex,
+    /// Motorola S-records
+    Srec,
+    /// C/shell escaped string, `\x7f\x45\x4c\x46`
+    Escaped,
+    /// JSON object with the input name, offsets, SHA-256 hash, and encoded data
+    Json,
+    /// Binary digits, 8 bytes per line, `01111111 01000101 ...`
+    Bits,
+    /// Plain hex digits, 32 bytes per line, `7f454c46...`
+    Hex,
+    /// Standard base64, 76 characters per line
+    Base64,
+}
+
+impl Format {
+    /// Get the format implied by a file extension, for the ones which unambiguously name a format
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext {
+            "hex" | "ihex" | "ihx" => Some(Self::Ihex),
+            "srec" | "s19" | "s28" | "s37" | "mot" => Some(Self::Srec),
+            "b64" => Some(Self::Base64),
+            _ => None,
+        }
+    }
+}
+
+/// Text encodings for binary data embedded in other formats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DataEncoding {
+    #[default]
+    Base64,
+    Hex,
+}
+
+/// Motorola S-record variants, named for their data and termination record types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SrecType {
+    /// 16-bit addresses (S1/S9)
+    S19,
+    /// 24-bit addresses (S2/S8)
+    S28,
+    /// 32-bit addresses (S3/S7)
+    S37,
+}
+
+impl SrecType {
+    fn addr_bytes(self) -> usize {
+        match self {
+            Self::S19 => 2,
+            Self::S28 => 3,
+            Self::S37 => 4,
+        }
+    }
+
+    /// The record type number used for data records. The termination record is 10 minus this.
+    fn data_record(self) -> u8 {
+        self.addr_bytes() as u8 - 1
+    }
+
+    /// Whether addresses up to end (exclusive) can be represented by this type
+    fn fits(self, end: u64) -> bool {
+        end <= 1 << (8 * self.addr_bytes())
+    }
+}
+
+/// Settings which control how the output is formatted
+#[derive(Debug, Default)]
+pub struct FormatOptions {
+    pub format: Format,
+    /// load address of the first byte, for record formats like Intel HEX
+    pub base: u64,
+    /// S-record type to use, or None to pick the smallest one that fits
+    pub srec_type: Option<SrecType>,
+    /// leave printable ASCII characters unescaped in the escaped format
+    pub literal_printable: bool,
+    /// input file name and offset of the first byte, for self-describing formats like JSON
+    pub input_name: Option<String>,
+    pub start: u64,
+    /// how data is encoded in JSON output
+    pub data_encoding: DataEncoding,
+    /// use uppercase hex digits in the plain hex format
+    pub upper: bool,
+}
+
+/// How many bytes to print on each line of source-code style output
+const BYTES_PER_LINE: usize = 12;
+
+/// Write the body of a Rust/C style array literal, one indented line per BYTES_PER_LINE bytes,
+/// each byte followed by a comma.
+fn write_array_body(data: &[u8], output: &mut dyn Write) -> io::Result<()> {
+    for line in data.chunks(BYTES_PER_LINE) {
+        write!(output, "   ")?;
+        for byte in line {
+            write!(output, " 0x{:02x},", byte)?;
+        }
+        writeln!(output)?;
+    }
+    Ok(())
+}
+
+fn write_rust(data: &[u8], slice: bool, output: &mut dyn Write) -> io::Result<()> {
+    if slice {
+        writeln!(output, "pub const DATA: &[u8] = &[")?;
+    } else {
+        writeln!(output, "pub const DATA: [u8; {}] = [", data.len())?;
+    }
+    write_array_body(data, output)?;
+    writeln!(output, "];")
+}
+
+fn write_python(data: &[u8], output: &mut dyn Write) -> io::Result<()> {
+    if data.is_empty() {
+        return writeln!(output, "DATA = b\"\"");
+    }
+    writeln!(output, "DATA = (")?;
+    for line in data.chunks(BYTES_PER_LINE) {
+        write!(output, "    b\"")?;
+        for byte in line {
+            write!(output, "\\x{:02x}", byte)?;
+        }
+        writeln!(output, "\"")?;
+    }
+    writeln!(output, ")")
+}
+
+fn write_escaped(data: &[u8], literal_printable: bool, output: &mut dyn Write) -> io::Result<()> {
+    let mut prev_escaped = false;
+    for &byte in data {
+        // Quotes and backslashes are always escaped so the result can be pasted inside any kind of
+        // quotes. In C, \x escapes consume as many hex digits as they can, so a hex digit after an
+        // escape has to be escaped too.
+        let literal = literal_printable
+            && (byte.is_ascii_graphic() || byte == b' ')
+            && !matches!(byte, b'\\' | b'"' | b'\'')
+            && !(prev_escaped && byte.is_ascii_hexdigit());
+        if literal {
+            output.write_all(&[byte])?;
+        } else {
+            write!(output, "\\x{:02x}", byte)?;
+        }
+        prev_escaped = !literal;
+    }
+    writeln!(output)
+}
+
+fn write_bits(data: &[u8], output: &mut dyn Write) -> io::Result<()> {
+    for line in data.chunks(8) {
+        let bytes: Vec<String> = line.iter().map(|b| format!("{:08b}", b)).collect();
+        writeln!(output, "{}", bytes.join(" "))?;
+    }
+    Ok(())
+}
+
+/// Write data as plain hex digits, 32 bytes per line
+fn write_hex(data: &[u8], upper: bool, output: &mut dyn Write) -> io::Result<()> {
+    for line in data.chunks(32) {
+        let line = hex_string(line);
+        writeln!(output, "{}", if upper { line.to_ascii_uppercase() } else { line })?;
+    }
+    Ok(())
+}
+
+/// Write data as base64, wrapped to 76 characters per line like MIME
+fn write_base64(data: &[u8], output: &mut dyn Write) -> io::Result<()> {
+    for line in data.chunks(57) {
+        writeln!(output, "{}", base64_string(line))?;
+    }
+    Ok(())
+}
+
+/// Encode data as a lowercase hex string
+pub fn hex_string(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Encode data as standard padded base64
+pub fn base64_string(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = u32::from_be_bytes([0, b[0], b[1], b[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3f] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Quote and escape a string for JSON
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn write_json(data: &[u8], opts: &FormatOptions, output: &mut dyn Write) -> io::Result<()> {
+    let input = match &opts.input_name {
+        Some(name) => json_string(name),
+        None => "null".into(),
+    };
+    let (encoding, encoded) = match opts.data_encoding {
+        DataEncoding::Base64 => ("base64", base64_string(data)),
+        DataEncoding::Hex => ("hex", hex_string(data)),
+    };
+    writeln!(
+        output,
+        r#"{{"input":{},"start":{},"length":{},"sha256":"{}","encoding":"{}","data":"{}"}}"#,
+        input,
+        opts.start,
+        data.len(),
+        hex_string(&Sha256::digest(data)),
+        encoding,
+        encoded,
+    )
+}
+
+/// Write a single Intel HEX record, including the checksum
+fn write_ihex_record(output: &mut dyn Write, kind: u8, addr: u16, data: &[u8]) -> io::Result<()> {
+    let [addr_hi, addr_lo] = addr.to_be_bytes();
+    let mut sum = (data.len() as u8).wrapping_add(addr_hi).wrapping_add(addr_lo).wrapping_add(kind);
+    write!(output, ":{:02X}{:04X}{:02X}", data.len(), addr, kind)?;
+    for &byte in data {
+        sum = sum.wrapping_add(byte);
+        write!(output, "{:02X}", byte)?;
+    }
+    writeln!(output, "{:02X}", sum.wrapping_neg())
+}
+
+fn write_ihex(data: &[u8], base: u64, output: &mut dyn Write) -> io::Result<()> {
+    const RECORD_LEN: u64 = 16;
+    let fits = matches!(base.checked_add(data.len() as u64), Some(end) if end <= (1 << 32));
+    if !fits {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "data doesn't fit in the 32-bit Intel HEX address space",
+        ));
+    }
+
+    // upper 16 bits of the address, as set by the last extended linear address record
+    let mut upper = 0;
+    let mut pos = 0;
+    while pos < data.len() {
+        let addr = base + pos as u64;
+        if (addr >> 16) != upper {
+            upper = addr >> 16;
+            write_ihex_record(output, 0x04, 0, &(upper as u16).to_be_bytes())?;
+        }
+
+        // data records can't cross a 64K boundary
+        let len = RECORD_LEN.min(0x10000 - (addr & 0xffff)).min((data.len() - pos) as u64);
+        let end = pos + len as usize;
+        write_ihex_record(output, 0x00, addr as u16, &data[pos..end])?;
+        pos = end;
+    }
+
+    write_ihex_record(output, 0x01, 0, &[])
+}
+
+/// Write a single S-record, including the byte count and checksum
+fn write_srec_record(
+    output: &mut dyn Write,
+    kind: u8,
+    addr: u64,
+    addr_bytes: usize,
+    data: &[u8],
+) -> io::Result<()> {
+    let count = (addr_bytes + data.len() + 1) as u8;
+    let addr = addr.to_be_bytes();
+    let mut sum = count;
+    write!(output, "S{}{:02X}", kind, count)?;
+    for &byte in addr[addr.len() - addr_bytes..].iter().chain(data) {
+        sum = sum.wrapping_add(byte);
+        write!(output, "{:02X}", byte)?;
+    }
+    writeln!(output, "{:02X}", !sum)
+}
+
+fn write_srec(
+    data: &[u8],
+    base: u64,
+    srec_type: Option<SrecType>,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    const RECORD_LEN: usize = 16;
+    let end = base.saturating_add(data.len() as u64);
+    let srec_type = match srec_type {
+        Some(t) if t.fits(end) => t,
+        Some(t) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("data doesn't fit in the {:?} address space", t),
+            ))
+        }
+        None => [SrecType::S19, SrecType::S28, SrecType::S37]
+            .into_iter()
+            .find(|t| t.fits(end))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "data doesn't fit in the 32-bit S-record address space",
+                )
+            })?,
+    };
+    let addr_bytes = srec_type.addr_bytes();
+    let kind = srec_type.data_record();
+
+    // empty header record
+    write_srec_record(output, 0, 0, 2, &[])?;
+
+    let mut records = 0u64;
+    for (i, chunk) in data.chunks(RECORD_LEN).enumerate() {
+        write_srec_record(output, kind, base + (i * RECORD_LEN) as u64, addr_bytes, chunk)?;
+        records += 1;
+    }
+
+    // record count, which is optional so skip it if there's too many
+    if records <= 0xffff {
+        write_srec_record(output, 5, records, 2, &[])?;
+    } else if records <= 0xff_ffff {
+        write_srec_record(output, 6, records, 3, &[])?;
+    }
+
+    // termination record with the start address
+    write_srec_record(output, 10 - kind, base, addr_bytes, &[])
+}
+
+/// Read all of input and write it to output encoded as the given format. Formats other than Raw
+/// buffer the whole input in memory, they're meant for generating source code or firmware images
+/// so the data shouldn't be huge anyway.
+pub fn write_formatted(
+    opts: &FormatOptions,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    match opts.format {
+        Format::Raw => output.write_all(&data),
+        Format::Rust => write_rust(&data, false, output),
+        Format::RustSlice => write_rust(&data, true, output),
+        Format::Python => write_python(&data, output),
+        Format::Ihex => write_ihex(&data, opts.base, output),
+        Format::Srec => write_srec(&data, opts.base, opts.srec_type, output),
+        Format::Escaped => write_escaped(&data, opts.literal_printable, output),
+        Format::Json => write_json(&data, opts, output),
+        Format::Bits => write_bits(&data, output),
+        Format::Hex => write_hex(&data, opts.upper, output),
+        Format::Base64 => write_base64(&data, output),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_str(format: Format, data: &[u8]) -> String {
+        format_str_base(format, 0, data)
+    }
+
+    fn format_str_base(format: Format, base: u64, data: &[u8]) -> String {
+        let mut out = Vec::new();
+        let opts = FormatOptions { format, base, ..Default::default() };
+        write_formatted(&opts, &mut &data[..], &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(format_str(Format::Hex, b""), "");
+        assert_eq!(format_str(Format::Hex, b"\x7fELF\xab"), "7f454c46ab\n");
+        assert_eq!(format_str(Format::Hex, &[0xee; 33]), format!("{}\nee\n", "ee".repeat(32)));
+
+        let mut out = Vec::new();
+        let opts = FormatOptions { format: Format::Hex, upper: true, ..Default::default() };
+        write_formatted(&opts, &mut &b"\xab\xcd"[..], &mut out).unwrap();
+        assert_eq!(out, b"ABCD\n");
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(format_str(Format::Base64, b""), "");
+        assert_eq!(format_str(Format::Base64, b"\x7fELF\x02"), "f0VMRgI=\n");
+        assert_eq!(format_str(Format::Base64, &[0; 58]), format!("{}\nAA==\n", "A".repeat(76)));
+    }
+
+    #[test]
+    fn rust() {
+        assert_eq!(format_str(Format::Rust, b""), "pub const DATA: [u8; 0] = [\n];\n");
+        assert_eq!(
+            format_str(Format::Rust, b"\x7fELF"),
+            "pub const DATA: [u8; 4] = [\n    0x7f, 0x45, 0x4c, 0x46,\n];\n"
+        );
+        assert_eq!(
+            format_str(Format::RustSlice, &[0u8; 13]),
+            "pub const DATA: &[u8] = &[\n    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, \
+             0x00, 0x00, 0x00,\n    0x00,\n];\n"
+        );
+    }
+
+    #[test]
+    fn python() {
+        assert_eq!(format_str(Format::Python, b""), "DATA = b\"\"\n");
+        assert_eq!(
+            format_str(Format::Python, b"\x7fELF"),
+            "DATA = (\n    b\"\\x7f\\x45\\x4c\\x46\"\n)\n"
+        );
+    }
+
+    #[test]
+    fn ihex() {
+        assert_eq!(format_str(Format::Ihex, b""), ":00000001FF\n");
+        assert_eq!(
+            format_str_base(Format::Ihex, 0x30, b"\x02\x33\x7a"),
+            ":0300300002337A1E\n:00000001FF\n"
+        );
+        // crossing a 64K boundary splits the record and emits an extended linear address
+        assert_eq!(
+            format_str_base(Format::Ihex, 0x1fffe, b"\x01\x02\x03"),
+            ":020000040001F9\n:02FFFE000102FE\n:020000040002F8\n:0100000003FC\n:00000001FF\n"
+        );
+
+        let mut out = Vec::new();
+        let opts = FormatOptions { format: Format::Ihex, base: 0xffff_ffff, ..Default::default() };
+        assert!(write_formatted(&opts, &mut &b"ab"[..], &mut out).is_err());
+    }
+
+    #[test]
+    fn srec() {
+        assert_eq!(format_str(Format::Srec, b""), "S0030000FC\nS5030000FC\nS9030000FC\n");
+        assert_eq!(
+            format_str_base(Format::Srec, 0x7af0, b"\x0a\x0a\x0d\0\0\0\0\0\0\0\0\0\0\0\0\0"),
+            concat!(
+                "S0030000FC\n",
+                "S1137AF00A0A0D0000000000000000000000000061\n",
+                "S5030001FB\n",
+                "S9037AF092\n",
+            )
+        );
+        // automatically switches to 24-bit addresses
+        assert_eq!(
+            format_str_base(Format::Srec, 0xffff, b"\xaa\xbb"),
+            "S0030000FC\nS20600FFFFAABB96\nS5030001FB\nS80400FFFFFD\n"
+        );
+
+        let mut out = Vec::new();
+        let opts = FormatOptions {
+            format: Format::Srec,
+            base: 0xffff,
+            srec_type: Some(SrecType::S19),
+            ..Default::default()
+        };
+        assert!(write_formatted(&opts, &mut &b"ab"[..], &mut out).is_err());
+    }
+
+    #[test]
+    fn escaped() {
+        assert_eq!(format_str(Format::Escaped, b"\x7fELF\n"), "\\x7f\\x45\\x4c\\x46\\x0a\n");
+
+        let mut out = Vec::new();
+        let opts = FormatOptions {
+            format: Format::Escaped,
+            literal_printable: true,
+            ..Default::default()
+        };
+        // hex digits after an escape are escaped too
+        write_formatted(&opts, &mut &b"\x7fELF\"a\\b\x01cd z"[..], &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\\x7f\\x45LF\\x22\\x61\\x5c\\x62\\x01\\x63\\x64 z\n"
+        );
+    }
+
+    #[test]
+    fn encodings() {
+        assert_eq!(hex_string(b"\x00\xabz"), "00ab7a");
+        assert_eq!(base64_string(b""), "");
+        assert_eq!(base64_string(b"f"), "Zg==");
+        assert_eq!(base64_string(b"fo"), "Zm8=");
+        assert_eq!(base64_string(b"foo"), "Zm9v");
+        assert_eq!(base64_string(b"foobar\xff"), "Zm9vYmFy/w==");
+        assert_eq!(json_string("a\"b\\c\n\x01"), r#""a\"b\\c\n\u0001""#);
+    }
+
+    #[test]
+    fn json() {
+        let mut out = Vec::new();
+        let opts = FormatOptions {
+            format: Format::Json,
+            input_name: Some("in.bin".into()),
+            start: 16,
+            data_encoding: DataEncoding::Hex,
+            ..Default::default()
+        };
+        write_formatted(&opts, &mut &b"abc"[..], &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                r#"{"input":"in.bin","start":16,"length":3,"#,
+                r#""sha256":"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad","#,
+                r#""encoding":"hex","data":"616263"}"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn bits() {
+        assert_eq!(format_str(Format::Bits, b""), "");
+        assert_eq!(format_str(Format::Bits, b"\x7fE\x01"), "01111111 01000101 00000001\n");
+        assert_eq!(
+            format_str(Format::Bits, &[0xff; 9]),
+            format!("{}\n11111111\n", ["11111111"; 8].join(" "))
+        );
+    }
+}
@@ -0,0 +1,669 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Maximum directory nesting depth we'll descend into, to stop loops in corrupt images
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FsError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("no FAT or ext2/3/4 filesystem found")]
+    Unknown,
+    #[error("corrupt filesystem: {0}")]
+    Corrupt(&'static str),
+    #[error("filesystem read at offset {0:#x} is outside the selected range")]
+    OutOfRange(u64),
+    #[error("filesystem structure of {0} bytes is larger than the parser limit")]
+    TooLarge(u64),
+}
+
+type Result<T> = std::result::Result<T, FsError>;
+
+/// One file or directory found in a filesystem image
+#[derive(Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// full path within the filesystem, directories end with '/'
+    pub path: String,
+    /// file size in bytes, zero for directories
+    pub size: u64,
+    /// absolute offset in the input of the first byte of file data, if any
+    pub offset: Option<u64>,
+}
+
+fn le16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes(buf[off..off + 2].try_into().unwrap())
+}
+
+fn le32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+/// Positioned reads within a region of a seekable input
+struct Region<'a, R> {
+    inner: &'a mut R,
+    base: u64,
+    len: Option<u64>,
+    /// the most memory any one structure read from the filesystem may take up
+    limit: u64,
+}
+
+impl<R: Read + Seek> Region<'_, R> {
+    /// Fail if a structure of size bytes would be over the parser limit
+    fn check_size(&self, size: u64) -> Result<()> {
+        if size > self.limit {
+            return Err(FsError::TooLarge(size));
+        }
+        Ok(())
+    }
+
+    /// Get the input offset of off, which is relative to the start of the region
+    fn absolute(&self, off: u64) -> Result<u64> {
+        self.base.checked_add(off).ok_or(FsError::Corrupt("offset past the end of the input"))
+    }
+
+    /// Read len bytes at offset off, relative to the start of the region
+    fn read_at(&mut self, off: u64, len: usize) -> Result<Vec<u8>> {
+        self.check_size(len as u64)?;
+        let start = self.absolute(off)?;
+        if let Some(limit) = self.len {
+            if !matches!(off.checked_add(len as u64), Some(end) if end <= limit) {
+                return Err(FsError::OutOfRange(start));
+            }
+        }
+        let mut buf = vec![0u8; len];
+        self.inner.seek(SeekFrom::Start(start))?;
+        self.inner.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// List all files and directories in the FAT or ext2/3/4 filesystem which starts at offset base
+/// of the input. If len is given, the filesystem must fit within that many bytes.
+///
+/// Sizes in a corrupt or malicious image can be anything, so no single directory, cluster chain,
+/// or block list is allowed to take more than limit bytes of memory.
+pub fn list<R: Read + Seek>(
+    input: &mut R,
+    base: u64,
+    len: Option<u64>,
+    limit: u64,
+) -> Result<Vec<Entry>> {
+    let mut region = Region { inner: input, base, len, limit };
+    let mut entries = Vec::new();
+
+    let ext_super = region.read_at(1024, 1024).ok();
+    if let Some(sb) = ext_super.filter(|sb| le16(sb, 56) == 0xef53) {
+        Ext::new(&mut region, &sb)?.list_dir(EXT_ROOT_INO, "/", 0, &mut entries)?;
+        return Ok(entries);
+    }
+
+    let boot = region.read_at(0, 512)?;
+    if boot[510..512] == [0x55, 0xaa] && le16(&boot, 11).is_power_of_two() && boot[13] != 0 {
+        let fat = Fat::new(&boot)?;
+        let root = fat.root_dir(&mut region)?;
+        fat.list_dir(&mut region, &root, "/", 0, &mut entries)?;
+        return Ok(entries);
+    }
+
+    Err(FsError::Unknown)
+}
+
+/// Layout information from a FAT12/16/32 boot sector
+struct Fat {
+    cluster_size: u64,
+    /// offset of the first FAT
+    fat_offset: u64,
+    /// offset and size of the fixed root directory, for FAT12/16
+    root_offset: u64,
+    root_size: u64,
+    /// root directory first cluster, for FAT32
+    root_cluster: u32,
+    /// offset of cluster 2, the first data cluster
+    data_offset: u64,
+    cluster_count: u32,
+    /// 12, 16, or 32
+    bits: u8,
+}
+
+impl Fat {
+    fn new(boot: &[u8]) -> Result<Self> {
+        let bytes_per_sector = le16(boot, 11) as u64;
+        let sectors_per_cluster = boot[13] as u64;
+        let reserved = le16(boot, 14) as u64;
+        let num_fats = boot[16] as u64;
+        let root_entries = le16(boot, 17) as u64;
+        let total_sectors = match le16(boot, 19) {
+            0 => le32(boot, 32) as u64,
+            n => n as u64,
+        };
+        let fat_sectors = match le16(boot, 22) {
+            0 => le32(boot, 36) as u64,
+            n => n as u64,
+        };
+
+        let root_sectors = (root_entries * 32).div_ceil(bytes_per_sector);
+        let root_start = reserved + num_fats * fat_sectors;
+        let data_start = root_start + root_sectors;
+        let data_sectors =
+            total_sectors.checked_sub(data_start).ok_or(FsError::Corrupt("bad FAT geometry"))?;
+        let cluster_count = (data_sectors / sectors_per_cluster) as u32;
+        let bits = match cluster_count {
+            0..=4084 => 12,
+            4085..=65524 => 16,
+            _ => 32,
+        };
+
+        Ok(Self {
+            cluster_size: bytes_per_sector * sectors_per_cluster,
+            fat_offset: reserved * bytes_per_sector,
+            root_offset: root_start * bytes_per_sector,
+            root_size: root_sectors * bytes_per_sector,
+            root_cluster: if bits == 32 { le32(boot, 44) } else { 0 },
+            data_offset: data_start * bytes_per_sector,
+            cluster_count,
+            bits,
+        })
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.data_offset + (cluster as u64 - 2) * self.cluster_size
+    }
+
+    /// Look up the next cluster in a chain, or None at the end of the chain
+    fn next_cluster<R: Read + Seek>(
+        &self,
+        region: &mut Region<R>,
+        cluster: u32,
+    ) -> Result<Option<u32>> {
+        let cluster = cluster as u64;
+        let (next, eoc) = match self.bits {
+            12 => {
+                // 12-bit entries are packed into 3 bytes per pair
+                let raw = le16(&region.read_at(self.fat_offset + cluster + cluster / 2, 2)?, 0);
+                let next = if cluster & 1 == 1 { raw >> 4 } else { raw & 0xfff };
+                (next as u32, 0xff8)
+            }
+            16 => {
+                let raw = le16(&region.read_at(self.fat_offset + cluster * 2, 2)?, 0);
+                (raw as u32, 0xfff8)
+            }
+            _ => {
+                let raw = le32(&region.read_at(self.fat_offset + cluster * 4, 4)?, 0);
+                (raw & 0x0fff_ffff, 0x0fff_fff8)
+            }
+        };
+        Ok((2..eoc).contains(&next).then_some(next))
+    }
+
+    /// Read the whole cluster chain starting at cluster
+    fn read_chain<R: Read + Seek>(&self, region: &mut Region<R>, first: u32) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut cluster = Some(first);
+        let mut remaining = self.cluster_count;
+        while let Some(c) = cluster {
+            if c < 2 || c - 2 >= self.cluster_count || remaining == 0 {
+                return Err(FsError::Corrupt("bad FAT cluster chain"));
+            }
+            remaining -= 1;
+            region.check_size(data.len() as u64 + self.cluster_size)?;
+            data.extend(region.read_at(self.cluster_offset(c), self.cluster_size as usize)?);
+            cluster = self.next_cluster(region, c)?;
+        }
+        Ok(data)
+    }
+
+    fn root_dir<R: Read + Seek>(&self, region: &mut Region<R>) -> Result<Vec<u8>> {
+        if self.bits == 32 {
+            self.read_chain(region, self.root_cluster)
+        } else {
+            region.read_at(self.root_offset, self.root_size as usize)
+        }
+    }
+
+    fn list_dir<R: Read + Seek>(
+        &self,
+        region: &mut Region<R>,
+        dir: &[u8],
+        path: &str,
+        depth: usize,
+        entries: &mut Vec<Entry>,
+    ) -> Result<()> {
+        if depth > MAX_DEPTH {
+            return Err(FsError::Corrupt("directories nested too deeply"));
+        }
+
+        // long filename pieces, which come before the short entry they belong to
+        let mut long_name: Vec<u16> = Vec::new();
+        for ent in dir.chunks_exact(32) {
+            match ent[0] {
+                0x00 => break,
+                0xe5 => {
+                    long_name.clear();
+                    continue;
+                }
+                _ => (),
+            }
+
+            let attr = ent[11];
+            if attr == 0x0f {
+                // LFN entries are stored last piece first, so each one gets prepended
+                let mut piece: Vec<u16> = [(1, 5), (14, 6), (28, 2)]
+                    .iter()
+                    .flat_map(|&(off, count)| (0..count).map(move |i| off + i * 2))
+                    .map(|off| le16(ent, off))
+                    .take_while(|&c| c != 0 && c != 0xffff)
+                    .collect();
+                piece.append(&mut long_name);
+                long_name = piece;
+                continue;
+            }
+            if attr & 0x08 != 0 {
+                // volume label
+                long_name.clear();
+                continue;
+            }
+
+            let name = if long_name.is_empty() {
+                let base = String::from_utf8_lossy(&ent[0..8]).trim_end().to_owned();
+                let ext = String::from_utf8_lossy(&ent[8..11]).trim_end().to_owned();
+                if ext.is_empty() {
+                    base
+                } else {
+                    format!("{}.{}", base, ext)
+                }
+            } else {
+                String::from_utf16_lossy(&long_name)
+            };
+            long_name.clear();
+            if name == "." || name == ".." {
+                continue;
+            }
+
+            let cluster = ((le16(ent, 20) as u32) << 16) | le16(ent, 26) as u32;
+            let offset = match cluster {
+                0 | 1 => None,
+                _ => Some(region.absolute(self.cluster_offset(cluster))?),
+            };
+            if attr & 0x10 != 0 {
+                let child_path = format!("{}{}/", path, name);
+                entries.push(Entry { path: child_path.clone(), size: 0, offset });
+                if cluster >= 2 {
+                    let child = self.read_chain(region, cluster)?;
+                    self.list_dir(region, &child, &child_path, depth + 1, entries)?;
+                }
+            } else {
+                entries.push(Entry {
+                    path: format!("{}{}", path, name),
+                    size: le32(ent, 28) as u64,
+                    offset,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Inode number of the root directory in ext filesystems
+const EXT_ROOT_INO: u32 = 2;
+
+/// Layout information from an ext2/3/4 superblock
+struct Ext<'r, 'a, R> {
+    region: &'r mut Region<'a, R>,
+    block_size: u64,
+    inodes_per_group: u32,
+    inode_size: u64,
+    desc_offset: u64,
+    desc_size: u64,
+    is_64bit: bool,
+}
+
+/// Parsed fields of an ext inode
+struct Inode {
+    mode: u16,
+    size: u64,
+    flags: u32,
+    block: Vec<u8>,
+}
+
+impl Inode {
+    fn is_dir(&self) -> bool {
+        self.mode & 0xf000 == 0x4000
+    }
+}
+
+impl<'r, 'a, R: Read + Seek> Ext<'r, 'a, R> {
+    fn new(region: &'r mut Region<'a, R>, sb: &[u8]) -> Result<Self> {
+        let log_block_size = le32(sb, 24);
+        if log_block_size > 6 {
+            return Err(FsError::Corrupt("bad ext block size"));
+        }
+        let block_size = 1024u64 << log_block_size;
+        let first_data_block = le32(sb, 20) as u64;
+        let inode_size = if le32(sb, 76) >= 1 { le16(sb, 88) as u64 } else { 128 };
+        let is_64bit = le32(sb, 96) & 0x80 != 0;
+        let desc_size = if is_64bit { le16(sb, 254) as u64 } else { 32 };
+        let inodes_per_group = le32(sb, 40);
+        if inodes_per_group == 0 || inode_size < 128 || desc_size < 32 {
+            return Err(FsError::Corrupt("bad ext superblock"));
+        }
+
+        Ok(Self {
+            region,
+            block_size,
+            inodes_per_group,
+            inode_size,
+            desc_offset: (first_data_block + 1) * block_size,
+            desc_size,
+            is_64bit,
+        })
+    }
+
+    /// Get the offset of a block in the region
+    fn block_offset(&self, block: u64) -> Result<u64> {
+        block.checked_mul(self.block_size).ok_or(FsError::Corrupt("bad ext block number"))
+    }
+
+    fn read_block(&mut self, block: u64) -> Result<Vec<u8>> {
+        let offset = self.block_offset(block)?;
+        self.region.read_at(offset, self.block_size as usize)
+    }
+
+    fn read_inode(&mut self, ino: u32) -> Result<Inode> {
+        let group = (ino - 1) / self.inodes_per_group;
+        let index = (ino - 1) % self.inodes_per_group;
+        let desc = self
+            .region
+            .read_at(self.desc_offset + group as u64 * self.desc_size, self.desc_size as usize)?;
+        let mut table = le32(&desc, 8) as u64;
+        if self.is_64bit && desc.len() >= 0x2c {
+            table |= (le32(&desc, 0x28) as u64) << 32;
+        }
+
+        let offset = self
+            .block_offset(table)?
+            .checked_add(index as u64 * self.inode_size)
+            .ok_or(FsError::Corrupt("bad ext inode table"))?;
+        let raw = self.region.read_at(offset, 128)?;
+        Ok(Inode {
+            mode: le16(&raw, 0),
+            size: (le32(&raw, 4) as u64) | ((le32(&raw, 108) as u64) << 32),
+            flags: le32(&raw, 32),
+            block: raw[40..100].to_vec(),
+        })
+    }
+
+    /// Collect the physical block numbers of a file's data, in logical order, stopping after max
+    /// blocks
+    fn file_blocks(&mut self, inode: &Inode, max: u64) -> Result<Vec<u64>> {
+        const EXTENTS_FL: u32 = 0x80000;
+        const INLINE_DATA_FL: u32 = 0x1000_0000;
+        let mut blocks = Vec::new();
+        if inode.flags & INLINE_DATA_FL != 0 {
+            // data lives inside the inode, there are no blocks to point at
+        } else if inode.flags & EXTENTS_FL != 0 {
+            self.extent_blocks(&inode.block, 0, max, &mut blocks)?;
+        } else {
+            let max_blocks = inode.size.div_ceil(self.block_size).min(max);
+            for i in 0..15 {
+                let block = le32(&inode.block, i * 4) as u64;
+                let level = i.saturating_sub(11);
+                self.indirect_blocks(block, level, max_blocks, &mut blocks)?;
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Walk an extent tree node, appending the blocks it maps until there are max of them
+    fn extent_blocks(
+        &mut self,
+        node: &[u8],
+        depth: usize,
+        max: u64,
+        blocks: &mut Vec<u64>,
+    ) -> Result<()> {
+        if le16(node, 0) != 0xf30a || depth > 5 {
+            return Err(FsError::Corrupt("bad ext extent tree"));
+        }
+        let count = le16(node, 2) as usize;
+        let is_leaf = le16(node, 6) == 0;
+        for i in 0..count {
+            if blocks.len() as u64 >= max {
+                break;
+            }
+            let ent = node
+                .get(12 + i * 12..24 + i * 12)
+                .ok_or(FsError::Corrupt("bad ext extent tree"))?;
+            if is_leaf {
+                let len = match le16(ent, 4) {
+                    // uninitialized extent
+                    n if n > 32768 => n - 32768,
+                    n => n,
+                } as u64;
+                let len = len.min(max - blocks.len() as u64);
+                let start = ((le16(ent, 6) as u64) << 32) | (le32(ent, 8) as u64);
+                self.region.check_size((blocks.len() as u64 + len) * 8)?;
+                blocks.extend(start..start + len);
+            } else {
+                let leaf = ((le16(ent, 8) as u64) << 32) | (le32(ent, 4) as u64);
+                let child = self.read_block(leaf)?;
+                self.extent_blocks(&child, depth + 1, max, blocks)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Append the blocks mapped by a classic ext2/3 block pointer. level is 0 for a direct data
+    /// block, 1 for an indirect block, 2 for double-indirect, etc.
+    fn indirect_blocks(
+        &mut self,
+        block: u64,
+        level: usize,
+        max: u64,
+        blocks: &mut Vec<u64>,
+    ) -> Result<()> {
+        if block == 0 || blocks.len() as u64 >= max {
+            return Ok(());
+        }
+        if level == 0 {
+            self.region.check_size((blocks.len() as u64 + 1) * 8)?;
+            blocks.push(block);
+            return Ok(());
+        }
+        let table = self.read_block(block)?;
+        for ptr in table.chunks_exact(4) {
+            self.indirect_blocks(le32(ptr, 0) as u64, level - 1, max, blocks)?;
+        }
+        Ok(())
+    }
+
+    fn list_dir(
+        &mut self,
+        ino: u32,
+        path: &str,
+        depth: usize,
+        entries: &mut Vec<Entry>,
+    ) -> Result<()> {
+        if depth > MAX_DEPTH {
+            return Err(FsError::Corrupt("directories nested too deeply"));
+        }
+        let dir = self.read_inode(ino)?;
+        for block in self.file_blocks(&dir, u64::MAX)? {
+            let data = self.read_block(block)?;
+            let mut pos = 0;
+            while pos + 8 <= data.len() {
+                let child_ino = le32(&data, pos);
+                let rec_len = le16(&data, pos + 4) as usize;
+                let name_len = data[pos + 6] as usize;
+                if rec_len < 8 || pos + 8 + name_len > data.len() {
+                    break;
+                }
+                let name = String::from_utf8_lossy(&data[pos + 8..pos + 8 + name_len]).into_owned();
+                pos += rec_len;
+                if child_ino == 0 || name == "." || name == ".." {
+                    continue;
+                }
+
+                // only the first block is needed, so a big file doesn't have its whole block list
+                // built just for this
+                let child = self.read_inode(child_ino)?;
+                let offset = match self.file_blocks(&child, 1)?.first() {
+                    Some(&block) => Some(self.region.absolute(self.block_offset(block)?)?),
+                    None => None,
+                };
+                if child.is_dir() {
+                    let child_path = format!("{}{}/", path, name);
+                    entries.push(Entry { path: child_path.clone(), size: 0, offset });
+                    self.list_dir(child_ino, &child_path, depth + 1, entries)?;
+                } else {
+                    entries.push(Entry {
+                        path: format!("{}{}", path, name),
+                        size: child.size,
+                        offset,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Build a tiny FAT12 image: 512 byte sectors, 1 sector clusters, 1 reserved sector, one FAT,
+    /// 16 root entries, 64 sectors total. Data starts at sector 3.
+    fn fat12_image() -> Vec<u8> {
+        let mut img = vec![0u8; 64 * 512];
+        img[11..13].copy_from_slice(&512u16.to_le_bytes());
+        img[13] = 1;
+        img[14..16].copy_from_slice(&1u16.to_le_bytes());
+        img[16] = 1;
+        img[17..19].copy_from_slice(&16u16.to_le_bytes());
+        img[19..21].copy_from_slice(&64u16.to_le_bytes());
+        img[22..24].copy_from_slice(&1u16.to_le_bytes());
+        img[510] = 0x55;
+        img[511] = 0xaa;
+
+        // FAT entries 0-4: media, reserved, EOC (hello.txt), EOC (sub dir), EOC (data.bin)
+        img[512..518].copy_from_slice(&[0xf8, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        img[518..520].copy_from_slice(&[0xff, 0x0f]);
+
+        let dirent = |name: &[u8; 11], attr: u8, cluster: u16, size: u32| {
+            let mut e = [0u8; 32];
+            e[..11].copy_from_slice(name);
+            e[11] = attr;
+            e[26..28].copy_from_slice(&cluster.to_le_bytes());
+            e[28..32].copy_from_slice(&size.to_le_bytes());
+            e
+        };
+
+        // root directory at sector 2
+        let root = 2 * 512;
+        img[root..root + 32].copy_from_slice(&dirent(b"HELLO   TXT", 0x20, 2, 5));
+        img[root + 32..root + 64].copy_from_slice(&dirent(b"SUB        ", 0x10, 3, 0));
+
+        // a long filename entry for data.bin
+        let sub = 4 * 512;
+        let mut lfn = [0xffu8; 32];
+        lfn[0] = 0x41;
+        lfn[11] = 0x0f;
+        lfn[12] = 0;
+        lfn[26] = 0;
+        lfn[27] = 0;
+        for (i, c) in "data.bin".encode_utf16().chain([0]).enumerate() {
+            let off = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30][i];
+            lfn[off..off + 2].copy_from_slice(&c.to_le_bytes());
+        }
+        img[sub..sub + 32].copy_from_slice(&dirent(b".          ", 0x10, 3, 0));
+        img[sub + 32..sub + 64].copy_from_slice(&lfn);
+        img[sub + 64..sub + 96].copy_from_slice(&dirent(b"DATA    BIN", 0x20, 4, 1000));
+        img
+    }
+
+    #[test]
+    fn fat12() {
+        let mut img = vec![0u8; 0x100];
+        img.extend(fat12_image());
+        let entries = list(&mut Cursor::new(img), 0x100, None, 1 << 20).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                Entry { path: "/HELLO.TXT".into(), size: 5, offset: Some(0x100 + 3 * 512) },
+                Entry { path: "/SUB/".into(), size: 0, offset: Some(0x100 + 4 * 512) },
+                Entry { path: "/SUB/data.bin".into(), size: 1000, offset: Some(0x100 + 5 * 512) },
+            ]
+        );
+    }
+
+    /// Build a tiny ext2 image with 1K blocks and 16 inodes. The group descriptor is in block 2,
+    /// the inode table in blocks 4-5, and directory and file data from block 8.
+    fn ext_image() -> Vec<u8> {
+        let mut img = vec![0u8; 12 * 1024];
+        let sb = 1024;
+        img[sb + 20..sb + 24].copy_from_slice(&1u32.to_le_bytes());
+        img[sb + 40..sb + 44].copy_from_slice(&16u32.to_le_bytes());
+        img[sb + 56..sb + 58].copy_from_slice(&0xef53u16.to_le_bytes());
+        img[2048 + 8..2048 + 12].copy_from_slice(&4u32.to_le_bytes());
+
+        let mut inode = |ino: usize, mode: u16, size: u32, flags: u32, block: &[u8]| {
+            let raw = 4 * 1024 + (ino - 1) * 128;
+            img[raw..raw + 2].copy_from_slice(&mode.to_le_bytes());
+            img[raw + 4..raw + 8].copy_from_slice(&size.to_le_bytes());
+            img[raw + 32..raw + 36].copy_from_slice(&flags.to_le_bytes());
+            img[raw + 40..raw + 40 + block.len()].copy_from_slice(block);
+        };
+        inode(2, 0x41ed, 1024, 0, &8u32.to_le_bytes());
+        inode(11, 0x41ed, 1024, 0, &9u32.to_le_bytes());
+        inode(13, 0x81a4, 5, 0, &10u32.to_le_bytes());
+        // 32M in one extent starting at block 20, whose block list would be 256K
+        let mut extents = [0u8; 24];
+        extents[0..2].copy_from_slice(&0xf30au16.to_le_bytes());
+        extents[2..4].copy_from_slice(&1u16.to_le_bytes());
+        extents[4..6].copy_from_slice(&4u16.to_le_bytes());
+        extents[16..18].copy_from_slice(&32768u16.to_le_bytes());
+        extents[20..24].copy_from_slice(&20u32.to_le_bytes());
+        inode(12, 0x81a4, 32 << 20, 0x80000, &extents);
+
+        let mut dir = |block: usize, entries: &[(u32, &str)]| {
+            let mut pos = block * 1024;
+            for (i, &(ino, name)) in entries.iter().enumerate() {
+                let rec_len = if i + 1 == entries.len() { (block + 1) * 1024 - pos } else { 16 };
+                img[pos..pos + 4].copy_from_slice(&ino.to_le_bytes());
+                img[pos + 4..pos + 6].copy_from_slice(&(rec_len as u16).to_le_bytes());
+                img[pos + 6] = name.len() as u8;
+                img[pos + 8..pos + 8 + name.len()].copy_from_slice(name.as_bytes());
+                pos += rec_len;
+            }
+        };
+        dir(8, &[(2, "."), (2, ".."), (12, "big.bin"), (11, "sub")]);
+        dir(9, &[(11, "."), (2, ".."), (13, "a.txt")]);
+        img
+    }
+
+    #[test]
+    fn ext() {
+        let mut img = vec![0u8; 0x1000];
+        img.extend(ext_image());
+        let entries = list(&mut Cursor::new(img), 0x1000, None, 1 << 16).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                Entry { path: "/big.bin".into(), size: 32 << 20, offset: Some(0x1000 + 20 * 1024) },
+                Entry { path: "/sub/".into(), size: 0, offset: Some(0x1000 + 9 * 1024) },
+                Entry { path: "/sub/a.txt".into(), size: 5, offset: Some(0x1000 + 10 * 1024) },
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown() {
+        let mut img = Cursor::new(vec![0u8; 4096]);
+        assert!(matches!(list(&mut img, 0, None, 1 << 20), Err(FsError::Unknown)));
+        assert!(matches!(list(&mut img, 0, Some(100), 1 << 20), Err(FsError::OutOfRange(0))));
+        assert!(matches!(list(&mut img, 0, None, 256), Err(FsError::TooLarge(512))));
+        // offsets past the end of a u64 are an error rather than an overflow
+        assert!(list(&mut img, u64::MAX - 100, None, 1 << 20).is_err());
+    }
+}
@@ -0,0 +1,945 @@
+use std::io::{self, IoSlice, Read, Write};
+
+use clap::ValueEnum;
+
+use crate::annotate::Annotation;
+
+/// When to use colors in hexdump output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Use colors when writing to a terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// How to print the offset column of hexdump output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OffsetBase {
+    #[default]
+    Hex,
+    Dec,
+    Oct,
+    /// Don't print offsets
+    None,
+}
+
+impl OffsetBase {
+    /// Width of the offset column, enough for any 32-bit offset
+    fn width(self) -> usize {
+        match self {
+            Self::Hex => 8,
+            Self::Dec => 10,
+            Self::Oct => 11,
+            Self::None => 0,
+        }
+    }
+
+    fn format(self, offset: u64, upper: bool) -> String {
+        match self {
+            Self::Hex if upper => format!("{:08X}", offset),
+            Self::Hex => format!("{:08x}", offset),
+            Self::Dec => format!("{:010}", offset),
+            Self::Oct => format!("{:011o}", offset),
+            Self::None => String::new(),
+        }
+    }
+}
+
+/// How each byte is shown in the main column of hexdump output, like `od -t`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ByteFormat {
+    /// Two hex digits
+    #[default]
+    Hex,
+    /// Three octal digits
+    Oct,
+    /// Three decimal digits
+    Dec,
+    /// Eight binary digits
+    Bin,
+}
+
+impl ByteFormat {
+    /// Number of characters used for each byte
+    fn width(self) -> usize {
+        match self {
+            Self::Hex => 2,
+            Self::Oct | Self::Dec => 3,
+            Self::Bin => 8,
+        }
+    }
+
+    fn format(self, byte: u8, upper: bool) -> String {
+        match self {
+            Self::Hex if upper => format!("{:02X}", byte),
+            Self::Hex => format!("{:02x}", byte),
+            Self::Oct => format!("{:03o}", byte),
+            Self::Dec => format!("{:03}", byte),
+            Self::Bin => format!("{:08b}", byte),
+        }
+    }
+
+    fn hexyl_base(self) -> hexyl::Base {
+        match self {
+            Self::Hex => hexyl::Base::Hexadecimal,
+            Self::Oct => hexyl::Base::Octal,
+            Self::Dec => hexyl::Base::Decimal,
+            Self::Bin => hexyl::Base::Binary,
+        }
+    }
+}
+
+/// How bytes are shown in the character column of hexdump output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CharTable {
+    /// Printable ASCII, with hexyl's symbols for other classes of bytes with --pretty or '.'
+    /// otherwise
+    #[default]
+    Default,
+    /// Printable ASCII, with '.' for everything else
+    Ascii,
+    /// IBM PC codepage 437 glyphs for every byte
+    Cp437,
+    /// ASCII with caret notation for control characters and M- for high bytes, like `cat -v`
+    /// (not supported with --pretty)
+    Caret,
+}
+
+/// CP437 glyphs for bytes 0x00-0x1f, with NUL shown as a space
+const CP437_LOW: &str = " ☺☻♥♦♣♠•◘○◙♂♀♪♫☼►◄↕‼¶§▬↨↑↓→←∟↔▲▼";
+
+/// CP437 glyphs for bytes 0x80-0xff, the last one is a non-breaking space
+const CP437_HIGH: &str =
+    "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒáíóúñÑªº¿⌐¬½¼¡«»░▒▓│┤╡╢╖╕╣║╗╝╜╛┐└┴┬├─┼╞╟╚╔╩╦\
+                          ╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}";
+
+impl CharTable {
+    /// Append the representation of a byte to s
+    fn push_char(self, s: &mut String, byte: u8) {
+        match self {
+            Self::Default | Self::Ascii => {
+                s.push(if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+            }
+            Self::Cp437 => s.push(match byte {
+                0x00..=0x1f => CP437_LOW.chars().nth(byte as usize).unwrap(),
+                0x7f => '⌂',
+                0x80..=0xff => CP437_HIGH.chars().nth(byte as usize - 0x80).unwrap(),
+                _ => byte as char,
+            }),
+            Self::Caret => {
+                if byte >= 0x80 {
+                    s.push_str("M-");
+                }
+                match byte & 0x7f {
+                    c @ 0x00..=0x1f => {
+                        s.push('^');
+                        s.push((c + 0x40) as char);
+                    }
+                    0x7f => s.push_str("^?"),
+                    c => s.push(c as char),
+                }
+            }
+        }
+    }
+
+    fn hexyl_table(self) -> hexyl::CharacterTable {
+        match self {
+            Self::Default => hexyl::CharacterTable::Default,
+            Self::Ascii => hexyl::CharacterTable::Ascii,
+            Self::Cp437 => hexyl::CharacterTable::CP437,
+            Self::Caret => unreachable!("hexyl doesn't do caret notation"),
+        }
+    }
+}
+
+/// Which columns of the hexdump to print, besides the offsets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Columns {
+    #[default]
+    Both,
+    HexOnly,
+    CharsOnly,
+}
+
+/// Settings for hexdump output
+#[derive(Debug, Default)]
+pub struct HexdumpOptions {
+    pub color: bool,
+    /// number of 8-byte panels per line
+    pub panels: u64,
+    /// offset shown for the first byte
+    pub display_offset: u64,
+    pub offset_base: OffsetBase,
+    /// number of bytes shown together without spaces between them
+    pub group_size: u8,
+    pub columns: Columns,
+    /// use uppercase hex digits
+    pub upper: bool,
+    pub byte_format: ByteFormat,
+    pub char_table: CharTable,
+    /// use hexyl's boxed and colored layout rather than our own plain one
+    pub pretty: bool,
+    /// labelled regions to show in the margin, sorted by offset. Not supported with pretty.
+    pub annotations: Vec<Annotation>,
+}
+
+/// hexyl's default number of panels
+pub const DEFAULT_PANELS: u64 = 2;
+
+/// Parse a hexdump byte group size, which must be 1, 2, 4, or 8
+pub fn parse_group_size(input: &str) -> Result<u8, String> {
+    match input.parse() {
+        Ok(size @ (1 | 2 | 4 | 8)) => Ok(size),
+        _ => Err("group size must be 1, 2, 4, or 8".into()),
+    }
+}
+
+/// Hexdump all of input to output, highlighting bytes which differ from the same position in
+/// other. Diffing isn't supported in pretty mode.
+pub fn hexdump_diff(
+    opts: &HexdumpOptions,
+    input: &mut dyn Read,
+    other: Option<&mut dyn Read>,
+    output: Box<dyn Write>,
+) -> io::Result<()> {
+    if !opts.pretty || opts.columns == Columns::CharsOnly {
+        let mut output = output;
+        return Dumper::new(opts).dump(input, other, &mut output);
+    }
+
+    let output: Box<dyn Write> = match (opts.offset_base, opts.upper) {
+        (OffsetBase::Hex | OffsetBase::None, false) => output,
+        (base, upper) => Box::new(LineRewriter::new(output, base, upper)),
+    };
+    let mut printer = hexyl::PrinterBuilder::new(output)
+        .show_color(opts.color)
+        .show_position_panel(opts.offset_base != OffsetBase::None)
+        .num_panels(opts.panels)
+        .group_size(opts.group_size)
+        .with_base(opts.byte_format.hexyl_base())
+        .show_char_panel(opts.columns != Columns::HexOnly)
+        .character_table(opts.char_table.hexyl_table())
+        .build();
+    printer.display_offset(opts.display_offset);
+    printer.print_all(input)
+}
+
+/// Size of the output buffer that the plain hexdump is formatted into, the same as io_copy's
+const BUF_SIZE: usize = 1024 * 1024;
+
+/// Colors cycled through for the bytes and labels of annotated regions, so that neighbouring
+/// fields are easy to tell apart
+const ANNOTATION_COLORS: [&[u8]; 6] =
+    [b"\x1b[31m", b"\x1b[32m", b"\x1b[33m", b"\x1b[34m", b"\x1b[35m", b"\x1b[36m"];
+const COLOR_RESET: &[u8] = b"\x1b[0m";
+/// Reverse video for bytes which differ from the --diff file
+const DIFF_COLOR: &[u8] = b"\x1b[7m";
+
+const HEX_LOWER: &[u8; 16] = b"0123456789abcdef";
+const HEX_UPPER: &[u8; 16] = b"0123456789ABCDEF";
+
+/// Our own hexdump formatter, which prints lines in the style of `hexdump -C`.
+///
+/// hexyl writes each line separately and does a lot of work per byte for colors and its borders,
+/// which makes it far slower than the I/O for big inputs. Here each line is formatted straight
+/// into one large buffer using lookup tables, and the buffer is only written out when it fills
+/// up or when the input is arriving slower than we can print it.
+struct Dumper<'a> {
+    opts: &'a HexdumpOptions,
+    /// bytes per line
+    width: usize,
+    /// digits for hex offsets
+    digits: &'static [u8; 16],
+    /// text of each byte value in the hex column, byte_width characters each
+    bytes: Vec<u8>,
+    byte_width: usize,
+    /// text shown in the character column for each byte value
+    chars: Vec<Vec<u8>>,
+    /// length of the hex column of a full line, so that short lines can be padded to it
+    hex_len: usize,
+    /// end of the last annotated region, nothing past here needs to check the annotations
+    annotations_end: u64,
+}
+
+impl<'a> Dumper<'a> {
+    fn new(opts: &'a HexdumpOptions) -> Self {
+        let width = (opts.panels * 8) as usize;
+        let chars = (0..=255u8)
+            .map(|byte| {
+                let mut s = String::new();
+                opts.char_table.push_char(&mut s, byte);
+                s.into_bytes()
+            })
+            .collect();
+        let mut dumper = Self {
+            opts,
+            width,
+            digits: if opts.upper { HEX_UPPER } else { HEX_LOWER },
+            bytes: (0..=255u8)
+                .flat_map(|b| opts.byte_format.format(b, opts.upper).into_bytes())
+                .collect(),
+            byte_width: opts.byte_format.width(),
+            chars,
+            hex_len: 0,
+            annotations_end: opts.annotations.iter().map(Annotation::end).max().unwrap_or(0),
+        };
+        dumper.hex_len = dumper.hex_width(width);
+        dumper
+    }
+
+    /// Printed width of the hex column for len bytes, not counting color escapes
+    fn hex_width(&self, len: usize) -> usize {
+        let group_size = (self.opts.group_size as usize).max(1);
+        let spaces: usize =
+            (1..len).map(|i| if i % 8 == 0 { 2 } else { usize::from(i % group_size == 0) }).sum();
+        len * self.byte_width + spaces
+    }
+
+    /// Get the annotations which overlap the len bytes at offset, along with their indexes
+    fn overlapping(&self, offset: u64, len: usize) -> Vec<(usize, &Annotation)> {
+        if offset >= self.annotations_end {
+            return Vec::new();
+        }
+        let end = offset + len as u64;
+        self.opts
+            .annotations
+            .iter()
+            .enumerate()
+            .take_while(|(_, a)| a.offset < end)
+            .filter(|(_, a)| a.end() > offset)
+            .collect()
+    }
+
+    fn push_offset(&self, out: &mut Vec<u8>, offset: u64) {
+        match self.opts.offset_base {
+            OffsetBase::Hex => {
+                let len = ((64 - offset.leading_zeros() as usize).div_ceil(4)).max(8);
+                for i in (0..len).rev() {
+                    out.push(self.digits[(offset >> (i * 4)) as usize & 0xf]);
+                }
+            }
+            // these are rare enough to not be worth doing by hand, writing to a Vec can't fail
+            base => out.extend_from_slice(base.format(offset, self.opts.upper).as_bytes()),
+        }
+    }
+
+    /// Append the hex bytes of line, with a space between groups and two between panels. colors
+    /// is either empty or has the color of each byte.
+    fn push_hex(&self, out: &mut Vec<u8>, line: &[u8], colors: &[Option<&[u8]>]) {
+        let group_size = (self.opts.group_size as usize).max(1);
+        for (i, &byte) in line.iter().enumerate() {
+            if i != 0 {
+                if i % 8 == 0 {
+                    out.extend_from_slice(b"  ");
+                } else if i % group_size == 0 {
+                    out.push(b' ');
+                }
+            }
+            let color = colors.get(i).copied().flatten();
+            if let Some(color) = color {
+                out.extend_from_slice(color);
+            }
+            let start = byte as usize * self.byte_width;
+            out.extend_from_slice(&self.bytes[start..start + self.byte_width]);
+            if color.is_some() {
+                out.extend_from_slice(COLOR_RESET);
+            }
+        }
+    }
+
+    /// Append the character column text of line, colors is the same as for push_hex
+    fn push_chars(&self, out: &mut Vec<u8>, line: &[u8], colors: &[Option<&[u8]>]) {
+        for (i, &byte) in line.iter().enumerate() {
+            let color = colors.get(i).copied().flatten();
+            if let Some(color) = color {
+                out.extend_from_slice(color);
+            }
+            out.extend_from_slice(&self.chars[byte as usize]);
+            if color.is_some() {
+                out.extend_from_slice(COLOR_RESET);
+            }
+        }
+    }
+
+    /// Append one line of output. other is the same part of the file being diffed against, if
+    /// any, which may be shorter than line if that file ended.
+    fn push_line(&self, out: &mut Vec<u8>, offset: u64, line: &[u8], other: Option<&[u8]>) {
+        let annotations = self.overlapping(offset, line.len());
+        let differs: Vec<bool> = match other {
+            Some(other) => (0..line.len()).map(|i| other.get(i) != Some(&line[i])).collect(),
+            None => Vec::new(),
+        };
+        let diff_count = differs.iter().filter(|&&d| d).count();
+
+        // the color of each byte, differences win over annotations and the last annotation
+        // covering a byte wins over earlier ones
+        let mut colors = Vec::new();
+        if self.opts.color && (!annotations.is_empty() || diff_count != 0) {
+            colors = (0..line.len())
+                .map(|i| {
+                    if differs.get(i) == Some(&true) {
+                        return Some(DIFF_COLOR);
+                    }
+                    let pos = offset + i as u64;
+                    annotations
+                        .iter()
+                        .rev()
+                        .find(|(_, a)| (a.offset..a.end()).contains(&pos))
+                        .map(|(i, _)| ANNOTATION_COLORS[i % ANNOTATION_COLORS.len()])
+                })
+                .collect();
+        }
+
+        if self.opts.offset_base != OffsetBase::None {
+            self.push_offset(out, offset);
+            out.extend_from_slice(b"  ");
+        }
+        match self.opts.columns {
+            Columns::Both => {
+                self.push_hex(out, line, &colors);
+                out.resize(out.len() + self.hex_len - self.hex_width(line.len()), b' ');
+                out.extend_from_slice(b"  |");
+                self.push_chars(out, line, &colors);
+                out.push(b'|');
+            }
+            Columns::HexOnly => {
+                self.push_hex(out, line, &colors);
+                if diff_count != 0 || annotations.iter().any(|(_, a)| a.offset >= offset) {
+                    // line up the labels
+                    out.resize(out.len() + self.hex_len - self.hex_width(line.len()), b' ');
+                }
+            }
+            Columns::CharsOnly => self.push_chars(out, line, &colors),
+        }
+
+        // labels of the regions which start on this line go in the margin, along with a count of
+        // differing bytes when they can't be highlighted
+        let mut first = true;
+        if diff_count != 0 && !self.opts.color {
+            out.extend_from_slice(format!("  [{} differ]", diff_count).as_bytes());
+            first = false;
+        }
+        for (i, annotation) in annotations.iter().filter(|(_, a)| a.offset >= offset) {
+            out.extend_from_slice(if first { b"  " } else { b", " });
+            first = false;
+            if self.opts.color {
+                out.extend_from_slice(ANNOTATION_COLORS[i % ANNOTATION_COLORS.len()]);
+                out.extend_from_slice(annotation.label.as_bytes());
+                out.extend_from_slice(COLOR_RESET);
+            } else {
+                out.extend_from_slice(annotation.label.as_bytes());
+            }
+        }
+        out.push(b'\n');
+    }
+
+    /// Hexdump all of input to output. Runs of identical full lines are squeezed into a single
+    /// "*" line, and if the dump ends with one of those the end offset is printed.
+    fn dump(
+        &self,
+        input: &mut dyn Read,
+        mut other: Option<&mut dyn Read>,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        // read whole lines at a time when we can
+        let mut data = vec![0u8; self.width * (BUF_SIZE / self.width).max(1)];
+        let mut filled = 0;
+        let mut other_data = match other {
+            Some(_) => vec![0u8; data.len()],
+            None => Vec::new(),
+        };
+        let mut out = Vec::with_capacity(BUF_SIZE + 4096);
+        let mut offset = self.opts.display_offset;
+        // the last line printed, reused to avoid allocating for every line. It's empty to start
+        // with, which never matches a full line.
+        let mut prev: Vec<u8> = Vec::with_capacity(self.width);
+        let mut squeezing = false;
+
+        loop {
+            let n = match input.read(&mut data[filled..]) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            let short_read = filled + n < data.len();
+            filled += n;
+            let end = if n == 0 { filled } else { filled - filled % self.width };
+
+            // read the same amount of the other file, as much of it as there is
+            let other_len = match other.as_mut() {
+                Some(other) => read_full(&mut **other, &mut other_data[..end])?,
+                None => 0,
+            };
+
+            for (i, line) in data[..end].chunks(self.width).enumerate() {
+                let other_line = other.is_some().then(|| {
+                    let start = (i * self.width).min(other_len);
+                    &other_data[start..(start + line.len()).min(other_len)]
+                });
+                if line.len() == self.width
+                    && prev == line
+                    && other_line.is_none_or(|o| o == line)
+                    && self.overlapping(offset, line.len()).is_empty()
+                {
+                    if !squeezing {
+                        out.extend_from_slice(b"*\n");
+                        squeezing = true;
+                    }
+                } else {
+                    self.push_line(&mut out, offset, line, other_line);
+                    squeezing = false;
+                    prev.clear();
+                    prev.extend_from_slice(line);
+                }
+                offset += line.len() as u64;
+            }
+            data.copy_within(end..filled, 0);
+            filled -= end;
+
+            if n == 0 {
+                if squeezing && self.opts.offset_base != OffsetBase::None {
+                    self.push_offset(&mut out, offset);
+                    out.push(b'\n');
+                }
+                output.write_all(&out)?;
+                return output.flush();
+            }
+            if short_read || out.len() >= BUF_SIZE {
+                output.write_all(&out)?;
+                output.flush()?;
+                out.clear();
+            }
+        }
+    }
+}
+
+/// Read until buf is full or EOF, returning the number of bytes read
+fn read_full(input: &mut dyn Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match input.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+/// Write all of bufs, like the unstable Write::write_all_vectored
+fn write_all_vectored(output: &mut impl Write, mut bufs: &mut [IoSlice]) -> io::Result<()> {
+    // skip any empty slices at the start so that an empty write isn't mistaken for WriteZero
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        match output.write_vectored(bufs) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => IoSlice::advance_slices(&mut bufs, n),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+const BORDER: &str = "│";
+const HORIZONTAL: &str = "─";
+
+/// Convert the hex digits in s to uppercase, leaving everything else (like the 'm' at the end of
+/// color escape sequences) alone.
+fn upper_hex(s: &str) -> String {
+    s.chars().map(|c| if matches!(c, 'a'..='f') { c.to_ascii_uppercase() } else { c }).collect()
+}
+
+/// hexyl only prints lowercase hex offsets and bytes, this writer rewrites each line it prints to
+/// convert the position panel to another base, widening the header and footer borders to match,
+/// and optionally make the hex digits uppercase.
+struct LineRewriter<W: Write> {
+    inner: W,
+    base: OffsetBase,
+    upper: bool,
+    /// incomplete line which hasn't been rewritten yet
+    line: Vec<u8>,
+    /// rewritten lines which haven't been written yet
+    pending: Vec<String>,
+    /// total length of the pending lines
+    pending_len: usize,
+}
+
+impl<W: Write> LineRewriter<W> {
+    fn new(inner: W, base: OffsetBase, upper: bool) -> Self {
+        Self { inner, base, upper, line: Vec::new(), pending: Vec::new(), pending_len: 0 }
+    }
+
+    /// Rewrite the contents of the position panel
+    fn rewrite_offset(&self, panel: &str) -> String {
+        // split the panel into the text and any color escape sequences around it
+        let mut text = String::new();
+        let mut prefix = String::new();
+        let mut suffix = String::new();
+        let mut chars = panel.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                let escape = if text.is_empty() { &mut prefix } else { &mut suffix };
+                escape.push(c);
+                for c in chars.by_ref() {
+                    escape.push(c);
+                    if c == 'm' {
+                        break;
+                    }
+                }
+            } else {
+                text.push(c);
+            }
+        }
+
+        let text = match u64::from_str_radix(text.trim(), 16) {
+            Ok(offset) => self.base.format(offset, self.upper),
+            // squeezed lines have a "*" rather than an offset
+            Err(_) => format!("{:width$}", text.trim(), width = self.base.width()),
+        };
+        format!("{prefix}{text}{suffix}")
+    }
+
+    /// Rewrite one complete line of hexyl output
+    fn rewrite_line(&self, line: &str) -> String {
+        let Some(rest) = line.strip_prefix(BORDER) else {
+            // the top and bottom borders, make room for the wider offsets
+            let hex_border = HORIZONTAL.repeat(OffsetBase::Hex.width());
+            return match self.base {
+                OffsetBase::Dec | OffsetBase::Oct => {
+                    line.replacen(&hex_border, &HORIZONTAL.repeat(self.base.width()), 1)
+                }
+                OffsetBase::Hex | OffsetBase::None => line.to_owned(),
+            };
+        };
+
+        let mut out = BORDER.to_owned();
+        let rest = if self.base == OffsetBase::None {
+            rest
+        } else {
+            let Some((panel, rest)) = rest.split_once(BORDER) else {
+                return line.to_owned();
+            };
+            out += &self.rewrite_offset(panel);
+            out += BORDER;
+            rest
+        };
+
+        // the hex panels come next, followed by the character panels if there are any
+        let (hex, rest) = match rest.split_once(BORDER) {
+            Some((hex, rest)) => (hex, Some(rest)),
+            None => (rest, None),
+        };
+        out += &if self.upper { upper_hex(hex) } else { hex.to_owned() };
+        if let Some(rest) = rest {
+            out += BORDER;
+            out += rest;
+        }
+        out
+    }
+
+    /// Rewrite all the complete lines received so far, adding them to the pending lines
+    fn rewrite_lines(&mut self) {
+        let Some(end) = self.line.iter().rposition(|&b| b == b'\n') else {
+            return;
+        };
+        let lines: Vec<String> = String::from_utf8_lossy(&self.line[..=end])
+            .split_inclusive('\n')
+            .map(|line| self.rewrite_line(line))
+            .collect();
+        self.line.drain(..=end);
+        self.pending_len += lines.iter().map(String::len).sum::<usize>();
+        self.pending.extend(lines);
+    }
+
+    /// Write out all the pending lines together, since hexyl writes a line at a time and writing
+    /// each one separately takes longer than everything else for big dumps
+    fn write_pending(&mut self) -> io::Result<()> {
+        let mut slices: Vec<IoSlice> =
+            self.pending.iter().map(|line| IoSlice::new(line.as_bytes())).collect();
+        write_all_vectored(&mut self.inner, &mut slices)?;
+        self.pending.clear();
+        self.pending_len = 0;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for LineRewriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        self.rewrite_lines();
+        if self.pending_len >= BUF_SIZE {
+            self.write_pending()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.rewrite_lines();
+        self.write_pending()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for LineRewriter<W> {
+    fn drop(&mut self) {
+        // hexyl always ends with a newline, but don't lose anything if it didn't
+        let _ = self.write_pending();
+        let _ = self.inner.write_all(&self.line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct SharedVec(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedVec {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn chars() {
+        let opts = HexdumpOptions {
+            panels: 1,
+            display_offset: 0x10,
+            columns: Columns::CharsOnly,
+            ..Default::default()
+        };
+        let out = Rc::new(RefCell::new(Vec::new()));
+        hexdump_diff(&opts, &mut &b"hello\0world\n12"[..], None, Box::new(SharedVec(out.clone())))
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out.take()).unwrap(),
+            "00000010  hello.wo\n00000018  rld.12\n"
+        );
+    }
+
+    #[test]
+    fn plain() {
+        let dump = |opts: &HexdumpOptions, data: &[u8]| {
+            let out = Rc::new(RefCell::new(Vec::new()));
+            hexdump_diff(opts, &mut &data[..], None, Box::new(SharedVec(out.clone()))).unwrap();
+            String::from_utf8(out.take()).unwrap()
+        };
+        let mut data = b"0123456789abcdef".repeat(3);
+        data.extend_from_slice(b"\0\xff");
+
+        let opts = HexdumpOptions { panels: 2, display_offset: 0x100, ..Default::default() };
+        assert_eq!(
+            dump(&opts, &data),
+            concat!(
+                "00000100  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|\n",
+                "*\n",
+                "00000130  00 ff                                             |..|\n",
+            )
+        );
+        assert_eq!(
+            dump(&opts, &data[..32]),
+            concat!(
+                "00000100  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|\n",
+                "*\n",
+                "00000120\n",
+            )
+        );
+
+        let opts = HexdumpOptions {
+            panels: 1,
+            offset_base: OffsetBase::Dec,
+            group_size: 4,
+            upper: true,
+            columns: Columns::HexOnly,
+            ..Default::default()
+        };
+        assert_eq!(
+            dump(&opts, &data[8..20]),
+            "0000000000  38396162 63646566\n0000000008  30313233\n"
+        );
+
+        let opts = HexdumpOptions {
+            panels: 1,
+            offset_base: OffsetBase::None,
+            char_table: CharTable::Caret,
+            ..Default::default()
+        };
+        assert_eq!(dump(&opts, b"\x01\x81"), "01 81                    |^AM-^A|\n");
+
+        // diffs against a file which is different in two places and ends early
+        let mut other = data.clone();
+        other[1] = 0;
+        other[20] = 0;
+        other.truncate(33);
+        let opts = HexdumpOptions { panels: 2, ..Default::default() };
+        let diff = |opts: &HexdumpOptions| {
+            let out = Rc::new(RefCell::new(Vec::new()));
+            let other: &mut dyn Read = &mut &other[..];
+            let output = Box::new(SharedVec(out.clone()));
+            hexdump_diff(opts, &mut &data[..], Some(other), output).unwrap();
+            String::from_utf8(out.take()).unwrap()
+        };
+        assert_eq!(
+            diff(&opts),
+            concat!(
+                "00000000  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|",
+                "  [1 differ]\n",
+                "00000010  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|",
+                "  [1 differ]\n",
+                "00000020  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|",
+                "  [15 differ]\n",
+                "00000030  00 ff                                             |..|  [2 differ]\n",
+            )
+        );
+        let opts = HexdumpOptions { panels: 1, columns: Columns::HexOnly, color: true, ..opts };
+        assert!(diff(&opts).starts_with("00000000  30 \x1b[7m31\x1b[0m 32 33 34 35 36 37\n"));
+
+        let opts = HexdumpOptions {
+            panels: 1,
+            offset_base: OffsetBase::None,
+            columns: Columns::HexOnly,
+            byte_format: ByteFormat::Bin,
+            ..Default::default()
+        };
+        assert_eq!(dump(&opts, b"\x05\xa0"), "00000101 10100000\n");
+        let opts = HexdumpOptions { byte_format: ByteFormat::Oct, group_size: 2, ..opts };
+        assert_eq!(dump(&opts, b"\x08\xff\x40"), "010377 100\n");
+        let opts = HexdumpOptions { byte_format: ByteFormat::Dec, columns: Columns::Both, ..opts };
+        assert_eq!(dump(&opts, b"A\n"), "065010                       |A.|\n");
+
+        let ann = |offset, len, label: &str| Annotation { offset, len, label: label.into() };
+        let mut opts = HexdumpOptions {
+            panels: 1,
+            columns: Columns::HexOnly,
+            annotations: vec![ann(0, 4, "magic"), ann(4, 2, "version"), ann(6, 4, "size")],
+            ..Default::default()
+        };
+        assert_eq!(
+            dump(&opts, &data[..18]),
+            concat!(
+                "00000000  30 31 32 33 34 35 36 37  magic, version, size\n",
+                "00000008  38 39 61 62 63 64 65 66\n",
+                "00000010  30 31\n",
+            )
+        );
+        opts.color = true;
+        opts.panels = 2;
+        opts.columns = Columns::Both;
+        opts.annotations = vec![ann(0x13, 1, "x")];
+        assert_eq!(
+            dump(&opts, &data[..20]),
+            concat!(
+                "00000000  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|\n",
+                "00000010  30 31 32 \x1b[31m33\x1b[0m",
+                "                                       |012\x1b[31m3\x1b[0m|  \x1b[31mx\x1b[0m\n",
+            )
+        );
+    }
+
+    #[test]
+    fn char_tables() {
+        let show = |table: CharTable, data: &[u8]| {
+            let mut s = String::new();
+            for &b in data {
+                table.push_char(&mut s, b);
+            }
+            s
+        };
+        let data = b"A\0\x1b\x7f\x80\xe1\xff";
+        assert_eq!(show(CharTable::Ascii, data), "A......");
+        assert_eq!(show(CharTable::Cp437, data), "A ←⌂Çß\u{a0}");
+        assert_eq!(show(CharTable::Caret, data), "A^@^[^?M-^@M-aM-^?");
+        assert_eq!(CP437_LOW.chars().count(), 0x20);
+        assert_eq!(CP437_HIGH.chars().count(), 0x80);
+    }
+
+    #[test]
+    fn rewrite_offsets() {
+        let dec = LineRewriter::new(io::sink(), OffsetBase::Dec, false);
+        assert_eq!(dec.rewrite_line("┌────────┬─\n"), "┌──────────┬─\n");
+        assert_eq!(dec.rewrite_line("│00001000│ 00 01│\n"), "│0000004096│ 00 01│\n");
+        assert_eq!(
+            dec.rewrite_line("│\x1b[38;5;242m00000010\x1b[0m│ 00\n"),
+            "│\x1b[38;5;242m0000000016\x1b[0m│ 00\n"
+        );
+        assert_eq!(dec.rewrite_line("│*       │ x\n"), "│*         │ x\n");
+
+        let oct = LineRewriter::new(io::sink(), OffsetBase::Oct, false);
+        assert_eq!(oct.rewrite_line("│00000010│\n"), "│00000000020│\n");
+
+        let upper = LineRewriter::new(io::sink(), OffsetBase::Hex, true);
+        assert_eq!(
+            upper.rewrite_line(
+                "│\x1b[38;5;242m0000abcd\x1b[0m│ \x1b[1;32mab\x1b[0m 01 ┊ fe │ab┊cd│\n"
+            ),
+            "│\x1b[38;5;242m0000ABCD\x1b[0m│ \x1b[1;32mAB\x1b[0m 01 ┊ FE │ab┊cd│\n"
+        );
+        let upper = LineRewriter::new(io::sink(), OffsetBase::None, true);
+        assert_eq!(upper.rewrite_line("│ ab cd │ab│\n"), "│ AB CD │ab│\n");
+
+        // lines can arrive in pieces, and several at once
+        let out = Rc::new(RefCell::new(Vec::new()));
+        let mut rewriter = LineRewriter::new(SharedVec(out.clone()), OffsetBase::Dec, false);
+        rewriter.write_all("│00000010│ 00│\n│000".as_bytes()).unwrap();
+        rewriter.write_all("00020│ 01│\n│*       │ x│\n".as_bytes()).unwrap();
+        rewriter.flush().unwrap();
+        assert_eq!(
+            String::from_utf8(out.take()).unwrap(),
+            "│0000000016│ 00│\n│0000000032│ 01│\n│*         │ x│\n"
+        );
+    }
+
+    /// Counts write calls, taking up to 1024 slices per vectored write like writev does
+    #[derive(Default)]
+    struct CountWrites {
+        calls: usize,
+        len: usize,
+    }
+
+    impl Write for CountWrites {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            self.len += buf.len();
+            Ok(buf.len())
+        }
+
+        fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+            self.calls += 1;
+            let n = bufs.iter().take(1024).map(|buf| buf.len()).sum();
+            self.len += n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rewrite_batches_lines() {
+        // about 5MiB of lines, written one at a time like hexyl does
+        const LINES: usize = 50_000;
+        let line =
+            "│00000010│ 00 01 02 03 04 05 06 07 ┊ 08 09 0a 0b 0c 0d 0e 0f │........┊........│\n";
+        let mut rewriter = LineRewriter::new(CountWrites::default(), OffsetBase::Dec, false);
+        for _ in 0..LINES {
+            rewriter.write_all(line.as_bytes()).unwrap();
+        }
+        rewriter.flush().unwrap();
+        let expected = rewriter.rewrite_line(line).len() * LINES;
+        assert_eq!(rewriter.inner.len, expected);
+        // about one write per 1024 lines (the writev limit) rather than one per line
+        assert!(rewriter.inner.calls < LINES / 500, "{}", rewriter.inner.calls);
+    }
+}
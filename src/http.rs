@@ -0,0 +1,70 @@
+use std::io::{self, Read};
+
+use crate::range::Range;
+
+/// Whether an input path is really an HTTP(S) URL
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Format the Range header value for a byte range. count must be nonzero. A range whose end is
+/// past u64::MAX is sent open-ended, since nothing is that big anyway.
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+fn range_header(range: &Range) -> String {
+    match range.count.and_then(|count| range.start.checked_add(count - 1)) {
+        Some(last) => format!("bytes={}-{}", range.start, last),
+        None => format!("bytes={}-", range.start),
+    }
+}
+
+/// Start downloading the range of a URL, asking the server to only send those bytes. The returned
+/// reader begins at the range start, but can run past the end of the range, so it still needs to
+/// be limited to the range's count.
+#[cfg(feature = "http")]
+pub fn open(url: &str, range: &Range) -> io::Result<Box<dyn Read + Send>> {
+    if range.count == Some(0) {
+        return Ok(Box::new(io::empty()));
+    }
+
+    let response = match ureq::get(url).set("Range", &range_header(range)).call() {
+        Ok(response) => response,
+        // the range starts past the end of the file, which is an empty read like for files
+        Err(ureq::Error::Status(416, _)) => return Ok(Box::new(io::empty())),
+        Err(ureq::Error::Status(code, response)) => {
+            return Err(io::Error::other(format!("HTTP error {} {}", code, response.status_text())))
+        }
+        Err(err) => return Err(io::Error::other(err)),
+    };
+
+    let partial = response.status() == 206;
+    let mut reader = response.into_reader();
+    if partial {
+        return Ok(reader);
+    }
+    // the server ignored the Range header and sent the whole thing, so skip to the start
+    crate::io_copy(&mut (&mut reader).take(range.start), &mut io::sink())?;
+    Ok(reader)
+}
+
+#[cfg(not(feature = "http"))]
+pub fn open(_url: &str, _range: &Range) -> io::Result<Box<dyn Read + Send>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "bcut was built without http support"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header() {
+        assert_eq!(range_header(&Range { start: 0x10, count: Some(0x10) }), "bytes=16-31");
+        assert_eq!(range_header(&Range { start: 0, count: None }), "bytes=0-");
+        assert_eq!(range_header(&Range { start: 0x10, count: Some(u64::MAX) }), "bytes=16-");
+        assert_eq!(
+            range_header(&Range { start: 0, count: Some(u64::MAX) }),
+            format!("bytes=0-{}", u64::MAX - 1)
+        );
+        assert!(is_url("https://example.com/image.bin"));
+        assert!(!is_url("./http:/file"));
+    }
+}
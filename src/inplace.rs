@@ -0,0 +1,65 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::range::Range;
+
+/// Size of each chunk moved at a time
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Rewrite a regular file to hold only the range of itself, by moving the range down to the start
+/// and truncating the rest. Returns the new size. The move goes front to back, and the
+/// destination never passes the source, so no data is overwritten before it's been read; but the
+/// file is left in a mixed state if this is interrupted.
+pub fn shrink(path: &Path, range: &Range) -> io::Result<u64> {
+    let mut file = File::options().read(true).write(true).open(path)?;
+    let meta = file.metadata()?;
+    if !meta.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "only regular files can be cut in place",
+        ));
+    }
+    let size = meta.len();
+    let start = range.start.min(size);
+    let end = match range.count {
+        Some(count) => start.saturating_add(count).min(size),
+        None => size,
+    };
+
+    if start != 0 {
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        let mut pos = 0;
+        while start + pos < end {
+            let len = ((end - start - pos) as usize).min(buf.len());
+            file.seek(SeekFrom::Start(start + pos))?;
+            file.read_exact(&mut buf[..len])?;
+            file.seek(SeekFrom::Start(pos))?;
+            file.write_all(&buf[..len])?;
+            pos += len as u64;
+        }
+    }
+    file.set_len(end - start)?;
+    file.sync_all()?;
+    Ok(end - start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    #[test]
+    fn shrink_file() {
+        let path = TempPath::new("inplace");
+        let data: Vec<u8> = (0..3 * CHUNK_SIZE as u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let range = Range { start: 1000, count: Some(2 * CHUNK_SIZE as u64) };
+        assert_eq!(shrink(&path, &range).unwrap(), 2 * CHUNK_SIZE as u64);
+        assert_eq!(std::fs::read(&path).unwrap(), &data[1000..1000 + 2 * CHUNK_SIZE]);
+
+        assert_eq!(shrink(&path, &Range { start: 0, count: Some(10) }).unwrap(), 10);
+        assert_eq!(std::fs::read(&path).unwrap(), &data[1000..1010]);
+    }
+}
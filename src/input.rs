@@ -0,0 +1,1022 @@
+#[cfg(target_os = "linux")]
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+#[cfg(target_os = "linux")]
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::compress::Method;
+use crate::http;
+use crate::io_copy;
+use crate::range::Range;
+use crate::skipmap::SkipBad;
+use crate::tar::{self, Skip, TarError};
+use crate::zip::{self, ZipError};
+
+/// Settings which control how the input is opened and read
+#[derive(Debug, Default)]
+pub struct InputOptions {
+    /// keep waiting for more data at EOF, like `tail -f`
+    pub follow: bool,
+    /// read from this inherited file descriptor rather than the path
+    pub fd: Option<i32>,
+    /// drop the range from the page cache once it's been read
+    pub drop_cache: bool,
+    /// ask for this much of the range past the current position to be read in ahead of time
+    pub readahead: Option<u64>,
+    /// fail once more than this many bytes have been read from the input, counting any read and
+    /// thrown away to get to the start of the range
+    pub max_input: Option<u64>,
+}
+
+/// Wrap an input which has already been positioned at the range start so that it stops at the end
+/// of the range.
+fn finish_input<R: Read + Send + 'static>(
+    reader: R,
+    range: &Range,
+    opts: &InputOptions,
+) -> Box<dyn Read + Send> {
+    finish_skipped(reader, 0, range, opts)
+}
+
+/// Like finish_input, for an input which had skipped bytes read from it to get to the range start
+fn finish_skipped<R: Read + Send + 'static>(
+    reader: R,
+    skipped: u64,
+    range: &Range,
+    opts: &InputOptions,
+) -> Box<dyn Read + Send> {
+    let reader: Box<dyn Read + Send> = match opts.max_input {
+        Some(limit) => Box::new(MaxInput { inner: reader, limit, remaining: limit - skipped }),
+        None => Box::new(reader),
+    };
+    let reader: Box<dyn Read + Send> =
+        if opts.follow { Box::new(Follow::new(reader)) } else { reader };
+    match range.count {
+        Some(count) => Box::new(reader.take(count)),
+        None => reader,
+    }
+}
+
+/// Read and throw away the first count bytes of an input which can't seek, counting them against
+/// any --max-input limit. Returns how many bytes were skipped.
+fn skip_stream(reader: &mut impl Read, count: u64, opts: &InputOptions) -> io::Result<u64> {
+    match opts.max_input {
+        Some(limit) => io_copy(&mut MaxInput::new(reader, limit).take(count), &mut io::sink()),
+        None => io_copy(&mut reader.take(count), &mut io::sink()),
+    }
+}
+
+/// Size of a block device in bytes, or None if the file isn't one. `stat` reports size 0 for
+/// block devices, so Linux needs an ioctl to get the real size.
+#[cfg(target_os = "linux")]
+pub fn block_device_size(file: &File) -> io::Result<Option<u64>> {
+    use rustix::ioctl::{ioctl, Getter, ReadOpcode};
+    use std::os::unix::fs::FileTypeExt;
+
+    if !file.metadata()?.file_type().is_block_device() {
+        return Ok(None);
+    }
+    // BLKGETSIZE64 is declared with size_t in the kernel headers (which determines the opcode on
+    // 32-bit platforms), but it always writes a u64.
+    type BlkGetSize64 = ReadOpcode<0x12, 114, usize>;
+    // SAFETY: BLKGETSIZE64 takes a pointer to a u64, which is what Getter provides
+    let size = unsafe { ioctl(file, Getter::<BlkGetSize64, u64>::new())? };
+    Ok(Some(size))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn block_device_size(_file: &File) -> io::Result<Option<u64>> {
+    Ok(None)
+}
+
+/// Tell the kernel that len bytes of a file (None for the rest of it) from offset are about to be
+/// read once, in order, so it reads ahead further and frees the pages sooner. This is only a hint,
+/// so errors like ESPIPE for a pipe are ignored.
+pub fn advise_sequential(file: &File, offset: u64, len: Option<u64>) {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        use rustix::fs::{fadvise, Advice};
+        let _ = fadvise(file, offset, len.unwrap_or(0), Advice::Sequential);
+    }
+    // macOS has no ranges, only a flag to turn on read ahead for the whole file
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    {
+        use std::os::fd::AsRawFd;
+        // SAFETY: F_RDAHEAD takes an int and only sets a flag on the open file
+        let _ = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_RDAHEAD, 1) };
+        let _ = (offset, len);
+    }
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios"
+    )))]
+    let _ = (file, offset, len);
+}
+
+/// Ask for reads of a file not to be cached at all, for --drop-cache on macOS, where there's no way
+/// to drop pages from the cache after reading them. This has to be done before reading, and does
+/// nothing on other platforms. Errors are ignored like advise_sequential.
+pub fn bypass_cache(file: &File) {
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    let _ = rustix::fs::fcntl_nocache(file, true);
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    let _ = file;
+}
+
+/// Drop len bytes of a file (None for the rest of it) from offset out of the page cache, so that
+/// reading a slice of a huge file doesn't push out everything else. Errors are ignored like
+/// advise_sequential. This only works on Linux, see bypass_cache for macOS.
+pub fn drop_cache(file: &File, offset: u64, len: Option<u64>) {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        use rustix::fs::{fadvise, Advice};
+        let _ = fadvise(file, offset, len.unwrap_or(0), Advice::DontNeed);
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let _ = (file, offset, len);
+}
+
+/// Ask for len bytes of a file from offset to be read into the page cache in the background,
+/// ignoring errors like advise_sequential
+fn will_need(file: &File, offset: u64, len: u64) {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        use rustix::fs::{fadvise, Advice};
+        let _ = fadvise(file, offset, len, Advice::WillNeed);
+    }
+    // F_RDADVISE takes an int length
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    let _ = rustix::fs::fcntl_rdadvise(file, offset, len.min(i32::MAX as u64));
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios"
+    )))]
+    let _ = (file, offset, len);
+}
+
+/// File reader which gives the kernel hints about the range as it's read: asking for the part
+/// after the current position to be read in ahead of time for --readahead, and dropping the range
+/// from the page cache when it's done for --drop-cache
+struct HintedFile {
+    file: File,
+    /// where the range starts in the file, and its length if it's known
+    offset: u64,
+    len: Option<u64>,
+    /// current position in the file
+    pos: u64,
+    readahead: Option<u64>,
+    /// end of the part of the file which readahead has asked for so far
+    advised: u64,
+    drop_cache: bool,
+}
+
+impl HintedFile {
+    fn new(file: File, offset: u64, len: Option<u64>, opts: &InputOptions) -> Self {
+        if opts.drop_cache {
+            bypass_cache(&file);
+        }
+        Self {
+            file,
+            offset,
+            len,
+            pos: offset,
+            readahead: opts.readahead,
+            advised: offset,
+            drop_cache: opts.drop_cache,
+        }
+    }
+}
+
+impl Read for HintedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(size) = self.readahead {
+            // top the window back up once half of it has been read, rather than on every read
+            if self.advised < self.pos.saturating_add(size / 2) {
+                let range_end = self.len.map_or(u64::MAX, |len| self.offset.saturating_add(len));
+                let end = self.pos.saturating_add(size).min(range_end);
+                let start = self.advised.max(self.pos);
+                if end > start {
+                    will_need(&self.file, start, end - start);
+                }
+                self.advised = end;
+            }
+        }
+        let count = self.file.read(buf)?;
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl Drop for HintedFile {
+    fn drop(&mut self) {
+        if self.drop_cache {
+            drop_cache(&self.file, self.offset, self.len);
+        }
+    }
+}
+
+/// Prefix of inputs which are a TCP address to connect to and read from
+const TCP_PREFIX: &str = "tcp://";
+
+/// Whether an input path is really a URL or network address to read from
+pub fn is_remote(path: &str) -> bool {
+    http::is_url(path) || path.starts_with(TCP_PREFIX)
+}
+
+pub fn prepare_input(
+    path: &Option<PathBuf>,
+    range: &Range,
+    opts: &InputOptions,
+) -> io::Result<Box<dyn Read + Send>> {
+    let is_stdin = match path {
+        Some(ref path) => matches!(path.to_str(), Some("-")),
+        None => true,
+    };
+
+    let remote = path.as_ref().and_then(|p| p.to_str()).filter(|p| is_remote(p));
+    if let (Some(remote), None) = (remote, opts.fd) {
+        if opts.follow {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "can't follow a network input"));
+        }
+        let (reader, skipped): (Box<dyn Read + Send>, u64) = match remote.strip_prefix(TCP_PREFIX) {
+            Some(addr) => {
+                // a stream can only be skipped through
+                let mut stream = TcpStream::connect(addr)?;
+                let skipped = skip_stream(&mut stream, range.start, opts)?;
+                (Box::new(stream), skipped)
+            }
+            None => (http::open(remote, range)?, 0),
+        };
+        return Ok(finish_skipped(reader, skipped, range, opts));
+    }
+
+    #[cfg(unix)]
+    {
+        use rustix::{
+            fs::{seek, SeekFrom},
+            io::{dup, Errno},
+            stdio::stdin,
+        };
+
+        // treat everything including stdin as a File so that we bypass std's buffering
+        let mut file = match opts.fd {
+            Some(fd) => {
+                if fd < 0 {
+                    return Err(rustix::io::Errno::BADF.into());
+                }
+                // SAFETY: the descriptor was inherited from our parent, so nothing else in bcut
+                // owns or closes it. If it isn't actually open, dup fails with EBADF.
+                let fd = unsafe { std::os::fd::BorrowedFd::borrow_raw(fd) };
+                File::from(dup(fd)?)
+            }
+            None if is_stdin => File::from(dup(stdin())?),
+            None => File::open(path.as_ref().unwrap())?,
+        };
+
+        // seek forward into the input if needed. Block devices refuse to seek past their end, so
+        // clamp to the size to get an empty read like a regular file would.
+        let start = match block_device_size(&file)? {
+            Some(size) => range.start.min(size),
+            None => range.start,
+        };
+        let mut skipped = 0;
+        if start != 0 {
+            match seek(&file, SeekFrom::Current(start.try_into().unwrap())) {
+                Ok(_) => (),
+                Err(Errno::SPIPE) => {
+                    // Failed to seek because this File is a pipe, so just read the first N bytes and
+                    // throw them away.
+                    skipped = skip_stream(&mut file, range.start, opts)?;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        // pipes have no position, and nothing to advise about either
+        if let Ok(offset) = file.stream_position() {
+            advise_sequential(&file, offset, range.count);
+            if opts.drop_cache || opts.readahead.is_some() {
+                let reader = HintedFile::new(file, offset, range.count, opts);
+                return Ok(finish_input(reader, range, opts));
+            }
+        }
+
+        Ok(finish_skipped(file, skipped, range, opts))
+    }
+
+    #[cfg(not(unix))]
+    {
+        if opts.fd.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "--input-fd isn't supported on this platform",
+            ));
+        }
+        if is_stdin {
+            let stdin = io::stdin();
+            let skipped = skip_stream(&mut stdin.lock(), range.start, opts)?;
+            Ok(finish_skipped(stdin, skipped, range, opts))
+        } else {
+            let mut file = File::open(path.as_ref().unwrap())?;
+            let mut skipped = 0;
+            if range.start != 0 {
+                match file.seek(SeekFrom::Current(range.start.try_into().unwrap())) {
+                    Ok(_) => (),
+                    Err(e) => {
+                        // failed to seek, probably a pipe? Not sure about Windows semantics...
+                        skipped = skip_stream(&mut file, range.start, opts)?;
+                    }
+                }
+            }
+            Ok(finish_skipped(file, skipped, range, opts))
+        }
+    }
+}
+
+/// Open several files as one logical stream, as if they had been concatenated. Only the files
+/// which overlap the range are read, and the first one is seeked into directly, so that slicing
+/// a large image which was split into parts doesn't read the parts before the range.
+pub fn prepare_concat(
+    paths: &[PathBuf],
+    range: &Range,
+    opts: &InputOptions,
+) -> io::Result<Box<dyn Read + Send>> {
+    use std::io::{Seek, SeekFrom};
+
+    let mut files = Vec::new();
+    for path in paths {
+        let file = File::open(path)?;
+        let size = match block_device_size(&file)? {
+            Some(size) => size,
+            None => {
+                let meta = file.metadata()?;
+                if !meta.is_file() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "{} isn't a regular file or block device, it can't be concatenated",
+                            path.display()
+                        ),
+                    ));
+                }
+                meta.len()
+            }
+        };
+        files.push((file, size));
+    }
+
+    // skip whole files before the start of the range, then seek within the first one we need
+    let mut skip = range.start;
+    let mut files = files.into_iter();
+    let mut reader: Box<dyn Read + Send> = loop {
+        match files.next() {
+            Some((_, len)) if skip >= len => skip -= len,
+            Some((mut file, _)) => {
+                file.seek(SeekFrom::Start(skip))?;
+                break Box::new(file);
+            }
+            // the range starts at or past the end of everything
+            None => break Box::new(io::empty()),
+        }
+    };
+    for (file, _) in files {
+        reader = Box::new(reader.chain(file));
+    }
+    Ok(finish_input(reader, range, opts))
+}
+
+/// Open a whole input and decompress it with method, or whatever compression its first few bytes
+/// show it has. If it doesn't look compressed, it's an error if required is set, or else the raw
+/// input is returned.
+fn open_compressed(
+    path: &Option<PathBuf>,
+    method: Option<Method>,
+    required: bool,
+    opts: &InputOptions,
+) -> io::Result<Box<dyn Read + Send>> {
+    // the limit applies to the compressed data, which is what's actually read
+    let raw_opts = InputOptions { fd: opts.fd, max_input: opts.max_input, ..Default::default() };
+    let raw = prepare_input(path, &Range { start: 0, count: None }, &raw_opts)?;
+    let mut raw = BufReader::new(raw);
+    let method = match method {
+        Some(method) => Some(method),
+        None => Method::from_magic(raw.fill_buf()?),
+    };
+    match method {
+        Some(method) => method.decoder(Box::new(raw)),
+        None if required => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "input isn't in a known compression format",
+        )),
+        None => Ok(Box::new(raw)),
+    }
+}
+
+/// Apply the range within an archive member of size bytes, which the input is positioned at the
+/// start of.
+fn member_input<R: Skip + Send + 'static>(
+    mut input: R,
+    size: u64,
+    range: &Range,
+    opts: &InputOptions,
+) -> io::Result<Box<dyn Read + Send>> {
+    let start = range.start.min(size);
+    input.skip(start)?;
+    Ok(finish_input(input.take(size - start), range, opts))
+}
+
+/// Open a file as the input for --skip-map, dropping or filling its bad regions. The file is kept
+/// seekable underneath, so that the bad regions are seeked over rather than read, since reading
+/// them on failing media just returns EIO.
+pub fn prepare_skip_bad(
+    path: &Path,
+    regions: Vec<(u64, u64)>,
+    fill: Option<u8>,
+    range: &Range,
+) -> io::Result<Box<dyn Read + Send>> {
+    let mut file = File::open(path)?;
+    // block devices refuse to seek past their end
+    let start = match block_device_size(&file)? {
+        Some(size) => range.start.min(size),
+        None => range.start,
+    };
+    file.seek(io::SeekFrom::Start(start))?;
+    let file = file.take(range.count.unwrap_or(u64::MAX));
+    Ok(Box::new(SkipBad::new(file, regions, range.start, fill)))
+}
+
+/// Open a member of a tar archive as the input, applying the range within its data. The archive
+/// may be compressed, which is detected automatically unless method is given. Uncompressed
+/// archive files seek over the other members rather than reading them.
+pub fn prepare_tar_member(
+    path: &Option<PathBuf>,
+    member: &str,
+    method: Option<Method>,
+    range: &Range,
+    opts: &InputOptions,
+    limit: u64,
+) -> io::Result<Box<dyn Read + Send>> {
+    let tar_error = |err| match err {
+        TarError::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    };
+
+    let file_path = path.as_ref().filter(|p| p.to_str().is_none_or(|p| p != "-" && !is_remote(p)));
+    if let (Some(file_path), None) = (file_path, method) {
+        let mut file = File::open(file_path)?;
+        let mut magic = Vec::new();
+        (&mut file).take(8).read_to_end(&mut magic)?;
+        file.rewind()?;
+        if Method::from_magic(&magic).is_none() {
+            let size = tar::find_member(&mut file, member, limit).map_err(tar_error)?;
+            return member_input(file, size, range, opts);
+        }
+    }
+
+    let mut input = open_compressed(path, method, false, opts)?;
+    let size = tar::find_member(&mut input, member, limit).map_err(tar_error)?;
+    member_input(input, size, range, &InputOptions { max_input: None, ..*opts })
+}
+
+/// Open a member of a zip archive as the input, applying the range within its data. Stored
+/// members are seeked into directly, and deflated ones are only decompressed as far as the end of
+/// the range.
+pub fn prepare_zip_member(
+    path: &Path,
+    member: &str,
+    range: &Range,
+    opts: &InputOptions,
+    limit: u64,
+) -> io::Result<Box<dyn Read + Send>> {
+    let mut file = File::open(path)?;
+    let found = zip::find_member(&mut file, member, limit).map_err(|err| match err {
+        ZipError::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    })?;
+    file.seek(io::SeekFrom::Start(found.offset))?;
+    match found.method {
+        zip::Method::Stored => member_input(file, found.size, range, opts),
+        zip::Method::Deflated => {
+            let data = file.take(found.compressed_size);
+            let input: Box<dyn Read + Send> = Box::new(flate2::read::DeflateDecoder::new(data));
+            member_input(input, found.size, range, opts)
+        }
+    }
+}
+
+/// Open an input which is compressed, applying the range to the decompressed data. If method is
+/// None, the compression format is detected from the first few bytes, and it's an error if it
+/// isn't one we know.
+pub fn prepare_decompressed(
+    path: &Option<PathBuf>,
+    method: Option<Method>,
+    range: &Range,
+    opts: &InputOptions,
+) -> io::Result<Box<dyn Read + Send>> {
+    let mut reader = open_compressed(path, method, true, opts)?;
+
+    // the decompressed stream can't seek, so read through to the start of the range. The raw
+    // input is already limited by --max-input.
+    io_copy(&mut (&mut reader).take(range.start), &mut io::sink())?;
+    Ok(finish_input(reader, range, &InputOptions { max_input: None, ..*opts }))
+}
+
+/// Open an extended attribute of a file, or an NTFS alternate data stream on Windows, as the
+/// input. Attributes are small, so on Unix the whole value is read into memory up front.
+pub fn prepare_xattr(
+    path: &Path,
+    name: &str,
+    range: &Range,
+    opts: &InputOptions,
+) -> io::Result<Box<dyn Read + Send>> {
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+    {
+        use rustix::fs::getxattr;
+
+        // the attribute could change size between the two calls, so retry until the value fits
+        let mut value = Vec::new();
+        loop {
+            let size = getxattr(path, name, &mut [])?;
+            value.resize(size, 0);
+            match getxattr(path, name, &mut value) {
+                Ok(len) => {
+                    value.truncate(len);
+                    break;
+                }
+                Err(rustix::io::Errno::RANGE) => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let start = range.start.min(value.len() as u64) as usize;
+        value.drain(..start);
+        Ok(finish_input(io::Cursor::new(value), range, opts))
+    }
+
+    #[cfg(windows)]
+    {
+        use std::io::{Seek, SeekFrom};
+
+        // alternate data streams are opened with a "file:stream" path
+        let mut stream_path = path.as_os_str().to_owned();
+        stream_path.push(":");
+        stream_path.push(name);
+        let mut file = File::open(stream_path)?;
+        file.seek(SeekFrom::Start(range.start))?;
+        Ok(finish_input(file, range, opts))
+    }
+
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        windows
+    )))]
+    {
+        let _ = (path, name, range, opts);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "extended attributes aren't supported on this platform",
+        ))
+    }
+}
+
+/// Open the memory of a running process as the input, so the range selects virtual addresses.
+/// This needs permission to ptrace the process.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn prepare_process(
+    pid: u32,
+    range: &Range,
+    opts: &InputOptions,
+) -> io::Result<Box<dyn Read + Send>> {
+    let mut mem = File::open(format!("/proc/{}/mem", pid))?;
+    mem.seek(io::SeekFrom::Start(range.start))?;
+    let reader = ProcessMemory { mem, address: range.start };
+    Ok(finish_input(reader, range, opts))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn prepare_process(
+    _pid: u32,
+    _range: &Range,
+    _opts: &InputOptions,
+) -> io::Result<Box<dyn Read + Send>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "--pid isn't supported on this platform"))
+}
+
+/// Reader for /proc/PID/mem which says which address failed when reading unmapped memory
+#[cfg(any(target_os = "linux", target_os = "android"))]
+struct ProcessMemory {
+    mem: File,
+    address: u64,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Read for ProcessMemory {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.mem.read(buf) {
+            Ok(count) => {
+                self.address += count as u64;
+                Ok(count)
+            }
+            // reading unmapped memory is EIO
+            Err(e) if e.raw_os_error() == Some(rustix::io::Errno::IO.raw_os_error()) => {
+                Err(io::Error::other(format!("address {:#x} isn't mapped", self.address)))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Wait for a path to exist, polling until it appears or the timeout passes
+pub fn wait_for(path: &Path, timeout: Option<Duration>) -> io::Result<()> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    let start = std::time::Instant::now();
+    loop {
+        match path.try_exists() {
+            Ok(true) => return Ok(()),
+            Ok(false) => (),
+            Err(e) => return Err(e),
+        }
+        if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("timed out waiting for {} to exist", path.display()),
+            ));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Reader which polls for more data at EOF rather than ending, like `tail -f`
+pub struct Follow<R> {
+    inner: R,
+}
+
+impl<R: Read> Follow<R> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    pub fn new(inner: R) -> Self {
+        Self { inner }
+    }
+}
+
+impl<R: Read> Read for Follow<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.inner.read(buf)? {
+                0 if !buf.is_empty() => thread::sleep(Self::POLL_INTERVAL),
+                count => return Ok(count),
+            }
+        }
+    }
+}
+
+/// Alignment of reads with O_DIRECT. Devices need at most their logical block size, and 4k covers
+/// everything common.
+const DIRECT_ALIGN: usize = 4096;
+
+/// Size of each read with O_DIRECT
+const DIRECT_BUF_SIZE: usize = 1024 * 1024;
+
+/// Something an input can free while the job is paused, like the O_DIRECT read buffer
+pub type Release = Box<dyn Fn() + Send + Sync>;
+
+/// Open a file input with O_DIRECT so that reading it doesn't fill up the page cache. Every read
+/// has to be aligned, so this reads whole blocks into an aligned buffer starting from the block
+/// containing the range start. The buffer can be freed while paused with the returned Release.
+#[cfg(target_os = "linux")]
+pub fn prepare_direct(
+    path: &Path,
+    range: &Range,
+    opts: &InputOptions,
+) -> io::Result<(Box<dyn Read + Send>, Release)> {
+    use rustix::fs::{open, Mode, OFlags};
+
+    let mut file = File::from(open(path, OFlags::RDONLY | OFlags::DIRECT, Mode::empty())?);
+    let start = match block_device_size(&file)? {
+        Some(size) => range.start.min(size),
+        None => range.start,
+    };
+    let aligned = start - start % DIRECT_ALIGN as u64;
+    file.seek(io::SeekFrom::Start(aligned))?;
+    let buffer = Arc::new(Mutex::new(DirectBuffer {
+        buf: Vec::new(),
+        pos: 0,
+        len: 0,
+        skip: (start - aligned) as usize,
+        saved: VecDeque::new(),
+    }));
+    let release = buffer.clone();
+    // the reader holds the lock while it's blocked reading, and then it's not a good time anyway
+    let release: Release = Box::new(move || {
+        if let Ok(mut buffer) = release.try_lock() {
+            buffer.release();
+        }
+    });
+    Ok((finish_input(DirectReader { file, buffer }, range, opts), release))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn prepare_direct(
+    _path: &Path,
+    _range: &Range,
+    _opts: &InputOptions,
+) -> io::Result<(Box<dyn Read + Send>, Release)> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "--direct isn't supported on this platform"))
+}
+
+/// Reader for a file opened with O_DIRECT, which reads whole aligned blocks
+#[cfg(target_os = "linux")]
+struct DirectReader {
+    file: File,
+    buffer: Arc<Mutex<DirectBuffer>>,
+}
+
+#[cfg(target_os = "linux")]
+struct DirectBuffer {
+    /// buffer with room to align its start, empty until the first read and after being released
+    buf: Vec<u8>,
+    /// position of the next unread byte in the aligned part of buf
+    pos: usize,
+    /// bytes of data in the aligned part of buf
+    len: usize,
+    /// bytes to throw away at the start of the first read, to get from the block boundary to the
+    /// range start
+    skip: usize,
+    /// unread data kept when buf was released, which is read before anything else
+    saved: VecDeque<u8>,
+}
+
+#[cfg(target_os = "linux")]
+impl DirectBuffer {
+    /// Free buf, keeping only the data in it which hasn't been read yet
+    fn release(&mut self) {
+        let offset = self.buf.as_ptr().align_offset(DIRECT_ALIGN);
+        if self.pos < self.len {
+            self.saved.extend(&self.buf[offset + self.pos..offset + self.len]);
+        }
+        self.pos = 0;
+        self.len = 0;
+        self.buf = Vec::new();
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Read for DirectReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let mut buffer = self.buffer.lock().unwrap();
+        let buffer = &mut *buffer;
+        if !buffer.saved.is_empty() {
+            return buffer.saved.read(out);
+        }
+        if buffer.buf.is_empty() {
+            buffer.buf = vec![0u8; DIRECT_BUF_SIZE + DIRECT_ALIGN];
+        }
+        let offset = buffer.buf.as_ptr().align_offset(DIRECT_ALIGN);
+        let aligned = &mut buffer.buf[offset..offset + DIRECT_BUF_SIZE];
+        while buffer.pos >= buffer.len {
+            // the last read of the file can be short, after that we get 0 for EOF
+            let count = self.file.read(aligned)?;
+            if count == 0 {
+                return Ok(0);
+            }
+            buffer.pos = std::mem::take(&mut buffer.skip);
+            buffer.len = count;
+        }
+        let count = out.len().min(buffer.len - buffer.pos);
+        out[..count].copy_from_slice(&aligned[buffer.pos..buffer.pos + count]);
+        buffer.pos += count;
+        Ok(count)
+    }
+}
+
+/// Function which opens an input again from the start
+pub type Reopen = Box<dyn FnMut() -> io::Result<Box<dyn Read + Send>> + Send>;
+
+/// Reader which reads its input through a number of times (forever if it's u64::MAX). Each pass
+/// after the first opens the input again if that's possible, or else replays a copy of the first
+/// pass kept in memory.
+pub struct Repeat {
+    inner: Box<dyn Read + Send>,
+    reopen: Option<Reopen>,
+    /// copy of the first pass, when the input can't be reopened
+    copy: Vec<u8>,
+    /// position in copy when replaying it, None while reading inner
+    replay: Option<usize>,
+    /// passes left after the current one
+    remaining: u64,
+    /// bytes read so far in the current pass
+    pass_len: u64,
+}
+
+impl Repeat {
+    pub fn new(inner: Box<dyn Read + Send>, times: u64, reopen: Option<Reopen>) -> Self {
+        let inner = if times == 0 { Box::new(io::empty()) } else { inner };
+        Self {
+            inner,
+            reopen,
+            copy: Vec::new(),
+            replay: None,
+            remaining: times.saturating_sub(1),
+            pass_len: 0,
+        }
+    }
+}
+
+impl Read for Repeat {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let count = match &mut self.replay {
+                Some(pos) => {
+                    let count = buf.len().min(self.copy.len() - *pos);
+                    buf[..count].copy_from_slice(&self.copy[*pos..*pos + count]);
+                    *pos += count;
+                    count
+                }
+                None => {
+                    let count = self.inner.read(buf)?;
+                    if self.reopen.is_none() {
+                        self.copy.extend_from_slice(&buf[..count]);
+                    }
+                    count
+                }
+            };
+            // an empty pass would repeat forever without making progress
+            if count > 0 || buf.is_empty() || self.remaining == 0 || self.pass_len == 0 {
+                self.pass_len += count as u64;
+                return Ok(count);
+            }
+
+            // start the next pass
+            if self.remaining != u64::MAX {
+                self.remaining -= 1;
+            }
+            self.pass_len = 0;
+            match &mut self.reopen {
+                Some(reopen) => self.inner = reopen()?,
+                None => self.replay = Some(0),
+            }
+        }
+    }
+}
+
+/// Reader which fails once more than a given number of bytes have been read through it, to guard
+/// against input streams which never end.
+pub struct MaxInput<R> {
+    inner: R,
+    limit: u64,
+    remaining: u64,
+}
+
+impl<R: Read> MaxInput<R> {
+    pub fn new(inner: R, limit: u64) -> Self {
+        Self { inner, limit, remaining: limit }
+    }
+}
+
+impl<R: Read> Read for MaxInput<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        if count as u64 > self.remaining {
+            return Err(io::Error::other(format!(
+                "input exceeded the maximum size of {} bytes",
+                self.limit
+            )));
+        }
+        self.remaining -= count as u64;
+        Ok(count)
+    }
+}
+
+/// Reader which pads the input with a fill byte up to a fixed size. Reading more than the size
+/// is an error, since the result wouldn't fit.
+pub struct PadTo<R> {
+    inner: R,
+    size: u64,
+    fill: u8,
+    pos: u64,
+    /// whether the input has ended and the rest is padding
+    padding: bool,
+}
+
+impl<R: Read> PadTo<R> {
+    pub fn new(inner: R, size: u64, fill: u8) -> Self {
+        Self { inner, size, fill, pos: 0, padding: false }
+    }
+}
+
+impl<R: Read> Read for PadTo<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.padding {
+            let count = self.inner.read(buf)?;
+            if count != 0 {
+                self.pos += count as u64;
+                if self.pos > self.size {
+                    return Err(io::Error::other(format!(
+                        "input is longer than the padded size of {} bytes",
+                        self.size
+                    )));
+                }
+                return Ok(count);
+            }
+            self.padding = true;
+        }
+        let count = buf.len().min((self.size - self.pos).try_into().unwrap_or(usize::MAX));
+        buf[..count].fill(self.fill);
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    fn repeat(times: u64, reopen: Option<Reopen>) -> Vec<u8> {
+        let mut out = Vec::new();
+        let input = Box::new(io::Cursor::new(b"abc".to_vec()));
+        Repeat::new(input, times, reopen).read_to_end(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn repeat_passes() {
+        assert_eq!(repeat(0, None), b"");
+        assert_eq!(repeat(1, None), b"abc");
+        assert_eq!(repeat(3, None), b"abcabcabc");
+        let reopen = || Ok(Box::new(io::Cursor::new(b"xyz".to_vec())) as Box<dyn Read + Send>);
+        assert_eq!(repeat(3, Some(Box::new(reopen))), b"abcxyzxyz");
+
+        // an empty input doesn't repeat forever
+        let mut out = Vec::new();
+        Repeat::new(Box::new(io::empty()), u64::MAX, None).read_to_end(&mut out).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn pad_to() {
+        let mut out = Vec::new();
+        PadTo::new(&b"abc"[..], 6, 0xff).read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"abc\xff\xff\xff");
+        out.clear();
+        PadTo::new(&b"abc"[..], 3, 0xff).read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"abc");
+        assert!(PadTo::new(&b"abc"[..], 2, 0).read_to_end(&mut out).is_err());
+    }
+
+    #[test]
+    fn max_input_counts_skipped() {
+        let opts = InputOptions { max_input: Some(10), ..Default::default() };
+        let range = Range { start: 8, count: None };
+        // bytes skipped on the way to the range count against the limit
+        let mut data = &b"0123456789abcdef"[..];
+        let skipped = skip_stream(&mut data, range.start, &opts).unwrap();
+        assert_eq!(skipped, 8);
+        let mut input = finish_skipped(data, skipped, &range, &opts);
+        let mut buf = [0; 2];
+        input.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"89");
+        assert!(input.read(&mut buf).is_err());
+        // and a skip which goes over it fails before getting to the range
+        let mut data = &b"0123456789abcdef"[..];
+        assert!(skip_stream(&mut data, 12, &opts).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn direct_release() {
+        // O_DIRECT itself doesn't matter here, and isn't supported by every filesystem
+        let path = TempPath::new("direct");
+        let data: Vec<u8> = (0..10000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+        let buffer = Arc::new(Mutex::new(DirectBuffer {
+            buf: Vec::new(),
+            pos: 0,
+            len: 0,
+            skip: 10,
+            saved: VecDeque::new(),
+        }));
+        let mut reader = DirectReader { file: File::open(&path).unwrap(), buffer: buffer.clone() };
+
+        let mut out = vec![0u8; 100];
+        reader.read_exact(&mut out).unwrap();
+        buffer.lock().unwrap().release();
+        assert!(buffer.lock().unwrap().buf.is_empty());
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, &data[10..]);
+    }
+}
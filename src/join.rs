@@ -0,0 +1,505 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::manifest::{Chunk, Hash, Manifest, ManifestError};
+use crate::split::{volume_header, VOLUME_HEADER_SIZE};
+
+#[derive(Debug, thiserror::Error)]
+pub enum JoinError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Manifest(#[from] ManifestError),
+    #[error("{0}: {1}")]
+    Open(String, io::Error),
+    #[error("unsupported volume header size {0}")]
+    HeaderSize(u64),
+    #[error("{0}: volume header doesn't match its place in the set")]
+    BadHeader(String),
+    #[error("{0}: size doesn't match the manifest")]
+    Size(String),
+    #[error("{0}: hash doesn't match the manifest")]
+    Hash(String),
+    #[error("{0}: {1}")]
+    Transform(String, io::Error),
+    #[error("{0}: data is larger than its pad size")]
+    PadOverflow(String),
+    #[error("{0}: region overlaps the previous one")]
+    Overlap(String),
+    #[error("size of the joined data doesn't match the manifest")]
+    TotalSize,
+    #[error("hash of the joined data doesn't match the manifest")]
+    TotalHash,
+}
+
+/// The joined output, tracking its position and hash
+struct Output<'a> {
+    inner: &'a mut dyn Write,
+    pos: u64,
+    total: Sha256,
+}
+
+impl Output<'_> {
+    /// Write data, adding it to the total hash and the given region's hash
+    fn write(&mut self, data: &[u8], region: &mut Sha256) -> io::Result<()> {
+        self.inner.write_all(data)?;
+        self.pos += data.len() as u64;
+        self.total.update(data);
+        region.update(data);
+        Ok(())
+    }
+
+    /// Write count copies of byte
+    fn fill(&mut self, byte: u8, count: u64, region: &mut Sha256) -> io::Result<()> {
+        let buf = [byte; 64 * 1024];
+        let mut remaining = count;
+        while remaining > 0 {
+            let n = remaining.min(buf.len() as u64) as usize;
+            self.write(&buf[..n], region)?;
+            remaining -= n as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Copy the region of one chunk to output, checking its header, size, and hash. Returns the hash
+/// of the region.
+fn join_chunk<R: Read>(
+    manifest: &Manifest,
+    index: usize,
+    input: &mut R,
+    output: &mut Output,
+) -> Result<Hash, JoinError> {
+    let chunk = &manifest.chunks[index];
+    let name = || chunk.name.clone();
+    let start = output.pos;
+    if manifest.header_size != 0 {
+        let mut header = [0u8; VOLUME_HEADER_SIZE as usize];
+        input.read_exact(&mut header).map_err(|_| JoinError::BadHeader(name()))?;
+        if header != volume_header(index as u32, start) {
+            return Err(JoinError::BadHeader(name()));
+        }
+    }
+
+    let mut hasher = Sha256::new();
+    if chunk.transforms.is_empty() {
+        // stream it, spanned volumes may be too big to hold in memory
+        let mut buf = vec![0u8; 1024 * 1024];
+        loop {
+            let n = match input.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            // stop before writing more than the region should hold
+            if chunk.size.is_some_and(|size| output.pos - start + n as u64 > size) {
+                return Err(JoinError::Size(name()));
+            }
+            output.write(&buf[..n], &mut hasher)?;
+        }
+    } else {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        for transform in &chunk.transforms {
+            data = transform.apply(data).map_err(|e| JoinError::Transform(name(), e))?;
+        }
+        output.write(&data, &mut hasher)?;
+    }
+
+    if let Some(pad) = chunk.pad {
+        let len = output.pos - start;
+        if len > pad {
+            return Err(JoinError::PadOverflow(name()));
+        }
+        output.fill(chunk.fill.unwrap_or(manifest.fill), pad - len, &mut hasher)?;
+    }
+
+    if chunk.size.is_some_and(|size| size != output.pos - start) {
+        return Err(JoinError::Size(name()));
+    }
+    let hash = hasher.finalize().into();
+    if chunk.sha256.is_some_and(|expected| expected != hash) {
+        return Err(JoinError::Hash(name()));
+    }
+    Ok(hash)
+}
+
+/// Write the region of every chunk in the manifest to output in order, verifying each one as it
+/// goes, then verify the size and hash of the whole thing. open gets a reader for each chunk's
+/// file. Stops at the first chunk which fails verification, though some of its data may have
+/// already been written.
+pub fn join_stream<R, F>(
+    manifest: &Manifest,
+    mut open: F,
+    output: &mut dyn Write,
+) -> Result<(), JoinError>
+where
+    R: Read,
+    F: FnMut(&Chunk) -> io::Result<R>,
+{
+    if manifest.header_size != 0 && manifest.header_size != VOLUME_HEADER_SIZE {
+        return Err(JoinError::HeaderSize(manifest.header_size));
+    }
+
+    let mut output = Output { inner: output, pos: 0, total: Sha256::new() };
+    for (i, chunk) in manifest.chunks.iter().enumerate() {
+        if let Some(offset) = chunk.offset {
+            if offset < output.pos {
+                return Err(JoinError::Overlap(chunk.name.clone()));
+            }
+            output.fill(manifest.fill, offset - output.pos, &mut Sha256::new())?;
+        }
+        let mut input = open(chunk).map_err(|e| JoinError::Open(chunk.name.clone(), e))?;
+        join_chunk(manifest, i, &mut input, &mut output)?;
+    }
+
+    if manifest.size.is_some_and(|size| size != output.pos) {
+        return Err(JoinError::TotalSize);
+    }
+    if manifest.sha256.is_some_and(|hash| hash != <[u8; 32]>::from(output.total.finalize())) {
+        return Err(JoinError::TotalHash);
+    }
+    Ok(())
+}
+
+/// A way in which an image doesn't match its manifest
+#[derive(Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    /// a region's contents are wrong
+    Region { name: String, offset: u64, size: u64 },
+    /// the image ends before the end of a region
+    Truncated { name: String, offset: u64, size: u64 },
+    /// a gap between regions contains something other than the fill byte
+    Fill { start: u64, end: u64, first_bad: u64 },
+    /// the image is the wrong size
+    Size { actual: u64, expected: u64 },
+    /// the whole image hash is wrong
+    TotalHash,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Region { name, offset, size } => {
+                write!(f, "{:#010x}+{:#x} {}: contents don't match", offset, size, name)
+            }
+            Self::Truncated { name, offset, size } => {
+                write!(f, "{:#010x}+{:#x} {}: image ends inside the region", offset, size, name)
+            }
+            Self::Fill { start, end, first_bad } => write!(
+                f,
+                "{:#010x}+{:#x} fill: unexpected byte at {:#x}",
+                start,
+                end - start,
+                first_bad
+            ),
+            Self::Size { actual, expected } => {
+                write!(f, "image is {:#x} bytes, expected {:#x}", actual, expected)
+            }
+            Self::TotalHash => write!(f, "image hash doesn't match"),
+        }
+    }
+}
+
+/// Reader which hashes everything read through it
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Read up to len bytes of image into hasher, returning how many bytes there were and the index
+/// of the first one which isn't fill, if fill is given.
+fn read_image<R: Read>(
+    image: &mut R,
+    len: u64,
+    hasher: &mut Sha256,
+    fill: Option<u8>,
+) -> io::Result<(u64, Option<u64>)> {
+    let mut buf = vec![0u8; 1024 * 1024];
+    let mut total = 0;
+    let mut first_bad = None;
+    while total < len {
+        let want = (len - total).min(buf.len() as u64) as usize;
+        let n = match image.read(&mut buf[..want]) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if let (Some(fill), None) = (fill, first_bad) {
+            first_bad = buf[..n].iter().position(|&b| b != fill).map(|i| total + i as u64);
+        }
+        hasher.update(&buf[..n]);
+        total += n as u64;
+    }
+    Ok((total, first_bad))
+}
+
+/// Check an existing image against a manifest, returning every mismatch found. Regions whose size
+/// or hash isn't in the manifest are built from their files (using open) to find out.
+pub fn check_stream<R, I, F>(
+    manifest: &Manifest,
+    mut open: F,
+    image: &mut I,
+) -> Result<Vec<Mismatch>, JoinError>
+where
+    R: Read,
+    I: Read,
+    F: FnMut(&Chunk) -> io::Result<R>,
+{
+    let mut mismatches = Vec::new();
+    let mut image = HashingReader { inner: image, hasher: Sha256::new() };
+    let mut pos = 0;
+    // where the image ended, if we've found that
+    let mut image_end = None;
+
+    for (i, chunk) in manifest.chunks.iter().enumerate() {
+        if let Some(offset) = chunk.offset {
+            if offset < pos {
+                return Err(JoinError::Overlap(chunk.name.clone()));
+            }
+            if image_end.is_none() {
+                let (n, first_bad) =
+                    read_image(&mut image, offset - pos, &mut Sha256::new(), Some(manifest.fill))?;
+                if let Some(first_bad) = first_bad {
+                    mismatches.push(Mismatch::Fill {
+                        start: pos,
+                        end: offset,
+                        first_bad: pos + first_bad,
+                    });
+                }
+                if n < offset - pos {
+                    image_end = Some(pos + n);
+                }
+            }
+            pos = offset;
+        }
+
+        let (size, hash) = match (chunk.size, chunk.sha256) {
+            (Some(size), Some(hash)) => (size, hash),
+            _ => {
+                let mut sink = io::sink();
+                let mut output = Output { inner: &mut sink, pos, total: Sha256::new() };
+                let mut input = open(chunk).map_err(|e| JoinError::Open(chunk.name.clone(), e))?;
+                let hash = join_chunk(manifest, i, &mut input, &mut output)?;
+                (output.pos - pos, hash)
+            }
+        };
+
+        if image_end.is_none() {
+            let mut hasher = Sha256::new();
+            let (n, _) = read_image(&mut image, size, &mut hasher, None)?;
+            let name = chunk.name.clone();
+            if n < size {
+                image_end = Some(pos + n);
+                mismatches.push(Mismatch::Truncated { name, offset: pos, size });
+            } else if <[u8; 32]>::from(hasher.finalize()) != hash {
+                mismatches.push(Mismatch::Region { name, offset: pos, size });
+            }
+        } else {
+            let name = chunk.name.clone();
+            mismatches.push(Mismatch::Truncated { name, offset: pos, size });
+        }
+        pos += size;
+    }
+
+    // whatever is left over is too much, unless the manifest expects more
+    let expected = manifest.size.unwrap_or(pos);
+    let actual = match image_end {
+        Some(end) => end,
+        None => pos + io::copy(&mut image, &mut io::sink())?,
+    };
+    if actual != expected {
+        mismatches.push(Mismatch::Size { actual, expected });
+    }
+    if manifest.sha256.is_some_and(|hash| hash != <[u8; 32]>::from(image.hasher.finalize())) {
+        mismatches.push(Mismatch::TotalHash);
+    }
+    Ok(mismatches)
+}
+
+/// Read a manifest file and join the files it lists, which are in the same directory
+pub fn join(manifest_path: &Path, output: &mut dyn Write) -> Result<(), JoinError> {
+    let text = fs::read_to_string(manifest_path)
+        .map_err(|e| JoinError::Open(manifest_path.display().to_string(), e))?;
+    let manifest = Manifest::parse(&text)?;
+    let dir = manifest_path.parent().unwrap_or(Path::new(""));
+    join_stream(&manifest, |chunk| File::open(dir.join(&chunk.name)), output)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Read a manifest file and check an image against it, see check_stream
+pub fn check(manifest_path: &Path, image_path: &Path) -> Result<Vec<Mismatch>, JoinError> {
+    let text = fs::read_to_string(manifest_path)
+        .map_err(|e| JoinError::Open(manifest_path.display().to_string(), e))?;
+    let manifest = Manifest::parse(&text)?;
+    let dir = manifest_path.parent().unwrap_or(Path::new(""));
+    let mut image =
+        File::open(image_path).map_err(|e| JoinError::Open(image_path.display().to_string(), e))?;
+    check_stream(&manifest, |chunk| File::open(dir.join(&chunk.name)), &mut image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::split::span_stream;
+    use crate::testutil::TempPath;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    type Volumes = Rc<RefCell<HashMap<String, Vec<u8>>>>;
+
+    struct VolumeWriter(Volumes, String);
+
+    impl Write for VolumeWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().entry(self.1.clone()).or_default().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn span(data: &[u8]) -> (Manifest, HashMap<String, Vec<u8>>) {
+        let volumes = Volumes::default();
+        let manifest = span_stream(&mut &data[..], VOLUME_HEADER_SIZE + 4, |n| {
+            let name = format!("v{}", n);
+            Ok((name.clone(), VolumeWriter(volumes.clone(), name)))
+        })
+        .unwrap();
+        (manifest, volumes.take())
+    }
+
+    fn join(manifest: &Manifest, volumes: &HashMap<String, Vec<u8>>) -> Result<Vec<u8>, JoinError> {
+        let mut output = Vec::new();
+        join_stream(manifest, |chunk| Ok(&volumes[&chunk.name][..]), &mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn join_volumes() {
+        let data = b"0123456789";
+        let (manifest, volumes) = span(data);
+        assert_eq!(volumes.len(), 3);
+        assert_eq!(join(&manifest, &volumes).unwrap(), data);
+
+        let mut bad = volumes.clone();
+        bad.get_mut("v1").unwrap()[VOLUME_HEADER_SIZE as usize] ^= 1;
+        assert!(matches!(join(&manifest, &bad), Err(JoinError::Hash(name)) if name == "v1"));
+
+        let mut bad = volumes.clone();
+        bad.get_mut("v2").unwrap().pop();
+        assert!(matches!(join(&manifest, &bad), Err(JoinError::Size(name)) if name == "v2"));
+
+        // volumes in the wrong order are caught by their headers
+        let mut bad = volumes.clone();
+        let v0 = bad.remove("v0").unwrap();
+        let v1 = bad.insert("v1".into(), v0).unwrap();
+        bad.insert("v0".into(), v1);
+        assert!(matches!(join(&manifest, &bad), Err(JoinError::BadHeader(name)) if name == "v0"));
+
+        let mut bad_manifest = manifest.clone();
+        bad_manifest.sha256.as_mut().unwrap()[0] ^= 1;
+        assert!(matches!(join(&bad_manifest, &volumes), Err(JoinError::TotalHash)));
+    }
+
+    #[test]
+    fn join_files_with_spaces() {
+        let dir = TempPath::new("join");
+        fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("my out.bin");
+        let data = b"0123456789";
+        let manifest =
+            crate::split::span_to_files(&mut &data[..], VOLUME_HEADER_SIZE + 4, &base).unwrap();
+        let manifest_path = crate::split::manifest_path(&base);
+        manifest.write(&mut File::create(&manifest_path).unwrap()).unwrap();
+
+        let mut output = Vec::new();
+        super::join(&manifest_path, &mut output).unwrap();
+        assert_eq!(output, data);
+    }
+
+    #[test]
+    fn join_regions() {
+        let manifest = Manifest::parse(
+            "bcut-manifest 1\n\
+             fill 0\n\
+             chunk a - - pad=4 fill=0xee\n\
+             chunk b - - at=6 swap=2 xor=ff\n\
+             chunk a 2 - at=0xc\n",
+        )
+        .unwrap();
+        let files =
+            HashMap::from([("a".to_owned(), b"AB".to_vec()), ("b".to_owned(), b"1234".to_vec())]);
+        assert_eq!(join(&manifest, &files).unwrap(), b"AB\xee\xee\0\0\xcd\xce\xcb\xcc\0\0AB");
+
+        let mut bad = manifest.clone();
+        bad.chunks[2].offset = Some(9);
+        assert!(matches!(join(&bad, &files), Err(JoinError::Overlap(name)) if name == "a"));
+
+        let mut bad = manifest.clone();
+        bad.chunks[0].pad = Some(1);
+        assert!(matches!(join(&bad, &files), Err(JoinError::PadOverflow(_))));
+
+        let mut bad = manifest;
+        bad.size = Some(13);
+        assert!(matches!(join(&bad, &files), Err(JoinError::TotalSize)));
+    }
+
+    #[test]
+    fn check_image() {
+        let manifest = Manifest::parse(
+            "bcut-manifest 1\n\
+             fill 0\n\
+             chunk a - - pad=4 fill=0xee\n\
+             chunk b - - at=6 swap=2 xor=ff\n",
+        )
+        .unwrap();
+        let files =
+            HashMap::from([("a".to_owned(), b"AB".to_vec()), ("b".to_owned(), b"1234".to_vec())]);
+        let check = |image: &[u8]| {
+            check_stream(&manifest, |chunk| Ok(&files[&chunk.name][..]), &mut &image[..]).unwrap()
+        };
+
+        let good = b"AB\xee\xee\0\0\xcd\xce\xcb\xcc";
+        assert_eq!(check(good), []);
+
+        let mut bad = good.to_vec();
+        bad[1] = b'X';
+        bad[5] = 1;
+        bad.push(0);
+        assert_eq!(
+            check(&bad),
+            [
+                Mismatch::Region { name: "a".into(), offset: 0, size: 4 },
+                Mismatch::Fill { start: 4, end: 6, first_bad: 5 },
+                Mismatch::Size { actual: 11, expected: 10 },
+            ]
+        );
+
+        assert_eq!(
+            check(&good[..7]),
+            [
+                Mismatch::Truncated { name: "b".into(), offset: 6, size: 4 },
+                Mismatch::Size { actual: 7, expected: 10 },
+            ]
+        );
+    }
+}
@@ -1,26 +1,831 @@
-use std::convert::TryInto;
 use std::fs::File;
-use std::io::{self, Read, Write};
-use std::path::PathBuf;
+use std::io::{self, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
-use anyhow::{Context, Result};
-use clap::Parser;
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
 
+mod annotate;
+mod bits;
+mod bookmark;
+mod compress;
+mod control;
+mod filter;
+mod format;
+mod fs;
+mod hexdump;
+mod http;
+mod inplace;
+mod input;
+mod join;
+mod manifest;
+mod metadata;
+mod mmap;
+mod monitor;
+mod mutate;
+mod pager;
+#[cfg(unix)]
+mod parallel;
+mod patch;
+#[cfg(unix)]
+mod pause;
+mod placeholder;
+mod profile;
 mod range;
-use range::Range;
+mod records;
+mod redact;
+mod repro;
+mod sandbox;
+mod scan;
+mod skipmap;
+mod sparse;
+#[cfg(unix)]
+mod splice;
+mod split;
+mod spool;
+mod synth;
+mod tar;
+mod tee;
+#[cfg(test)]
+mod testutil;
+mod transform;
+mod zip;
+use bits::BitReader;
+use compress::Compression;
+use control::ControlSocket;
+use filter::FilterReader;
+use format::{DataEncoding, Format, FormatOptions, SrecType};
+use hexdump::{ByteFormat, CharTable, ColorChoice, Columns, HexdumpOptions, OffsetBase};
+use input::{
+    prepare_concat, prepare_decompressed, prepare_direct, prepare_input, prepare_process,
+    prepare_skip_bad, prepare_tar_member, prepare_xattr, prepare_zip_member, InputOptions,
+    MaxInput, PadTo, Reopen, Repeat,
+};
+use manifest::Manifest;
+use mmap::{Madvise, Mapped, MappedReader};
+use mutate::MutateSpec;
+use profile::{CopyMethod, Profile, TimedReader, TimedWriter};
+use range::{Range, RangeSpec};
+use records::{Image, InputFormat};
+use redact::{CutOut, Redact};
+use scan::{IntSpec, Pattern};
+use skipmap::SkipBad;
+use spool::Spool;
+use tee::Tee;
+use transform::{Transform, TransformReader};
 
 /// Slice a byte range from a file
 #[derive(Debug, Parser)]
-#[clap(version)]
+#[clap(version, subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct Args {
-    /// Output file, omit or use "-" for stdout
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Output file, omit or use "-" for stdout. The name can include {start}, {len}, and {end}
+    /// (or {start:x} etc for hex), and {input_name} or {input_stem}.
     #[arg(short, long, name = "OUTFILE")]
     output: Option<PathBuf>,
 
-    /// Hexdump the output
+    /// Append to the output file rather than truncating it
+    #[arg(
+        short,
+        long,
+        requires = "OUTFILE",
+        conflicts_with_all = ["split_at", "volume_size", "mutate", "repro"],
+    )]
+    append: bool,
+
+    /// Compress the output with METHOD (gzip, zstd, xz, or bzip2), optionally at LEVEL. Output
+    /// files named *.gz or *.zst are compressed even without this, use "none" to write them as
+    /// they are, e.g. for a range which is already compressed.
+    #[arg(
+        long,
+        value_name = "METHOD[:LEVEL]",
+        value_parser = compress::parse_compression,
+        conflicts_with_all = ["seek", "split_at", "volume_size", "mutate", "in_place"],
+    )]
+    compress: Option<Compression>,
+
+    /// Write a JSON file to PATH describing the extraction: the input, the offset and length of
+    /// the bytes read from it, their SHA-256 hash, and the time
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["monitor", "list", "in_place"])]
+    metadata: Option<PathBuf>,
+
+    /// Size of the buffer for copying data, e.g. 64K or 4M
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = range::parse_size,
+        env = "BCUT_BUFFER_SIZE",
+        default_value = "1M"
+    )]
+    buffer_size: u64,
+
+    /// Read the input with N threads at once, each reading its own --buffer-size segments of the
+    /// range. This only applies to plain copies from a local file or block device.
+    #[arg(
+        short = 'j',
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..),
+        default_value_t = 1
+    )]
+    jobs: u16,
+
+    /// Flush the output files to storage before exiting, so that success means the data is
+    /// really written
+    #[arg(long)]
+    sync: bool,
+
+    /// Also write the output to PATH, which can be given more than once, or "-" for stdout. The
+    /// input is only read once.
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["split_at", "volume_size", "mutate", "monitor", "list", "in_place"],
+    )]
+    tee: Vec<PathBuf>,
+
+    /// Write the output starting at OFFSET of an existing output file, leaving the rest of it as
+    /// it was rather than truncating it, like `dd conv=notrunc`. This works for patching block
+    /// devices and big images in place too.
+    #[arg(
+        long,
+        value_name = "OFFSET",
+        value_parser = range::parse_size,
+        requires = "OUTFILE",
+        conflicts_with_all = ["append", "split_at", "volume_size", "mutate", "repro"],
+    )]
+    seek: Option<u64>,
+
+    /// Rewrite the input file to hold only the selected range, moving it to the start of the file
+    /// and truncating the rest, rather than writing an output
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "OUTFILE",
+            "hexdump",
+            "format",
+            "list",
+            "monitor",
+            "more_inputs",
+            "spool",
+            "repeat",
+            "skip_map",
+            // anything which reads the range rather than keeping it would have the file truncated
+            // instead of doing what was asked
+            "find",
+            "find_int",
+            "rle_summary",
+            "print_offsets",
+            "filter_cmd",
+            "max_input",
+            "control_socket",
+            "split_at",
+            "volume_size",
+            "mutate",
+            "annotate",
+            "diff",
+            "pager",
+        ],
+    )]
+    in_place: bool,
+
+    /// Write to inherited file descriptor N rather than a path or stdout, e.g. 3 for a file
+    /// opened by the shell with `3>FILE`
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(i32).range(0..),
+        conflicts_with_all = ["OUTFILE", "in_place"],
+    )]
+    output_fd: Option<i32>,
+
+    /// Reserve disk space for the whole output file before copying, when its size is known, so
+    /// that a full disk is reported right away
+    #[arg(long, requires = "OUTFILE")]
+    preallocate: bool,
+
+    /// Refuse to overwrite an existing output file
+    #[arg(
+        long,
+        overrides_with = "force",
+        conflicts_with_all = ["split_at", "volume_size", "mutate"],
+    )]
+    no_clobber: bool,
+
+    /// Overwrite an existing output file, undoing --no-clobber
+    #[arg(long, overrides_with = "no_clobber")]
+    force: bool,
+
+    /// Hexdump the output. This is the default when writing to a terminal.
     #[arg(short = 'H', long)]
     hexdump: bool,
 
+    /// Show hexdump output in $PAGER (or less) when writing to a terminal
+    #[arg(long)]
+    pager: bool,
+
+    /// Write raw bytes even when stdout is a terminal, rather than a hexdump
+    #[arg(long, conflicts_with_all = ["hexdump", "format"])]
+    raw: bool,
+
+    /// Use hexyl's boxed and colored hexdump layout, which is much slower for big inputs
+    #[arg(long)]
+    pretty: bool,
+
+    /// Label regions of the hexdump using a layout FILE
+    ///
+    /// Each line of FILE is "OFFSET[,LEN] LABEL", where LEN defaults to 1 and offsets match the
+    /// ones shown in the hexdump. Labels are printed at the end of the line where their region
+    /// starts, and annotated bytes are colored when colors are enabled. Lines starting with '#'
+    /// are ignored.
+    #[arg(long, value_name = "FILE", requires = "hexdump", conflicts_with = "pretty")]
+    annotate: Option<PathBuf>,
+
+    /// Highlight bytes in the hexdump which differ from the same range of OTHERFILE
+    ///
+    /// Differences are shown in reverse video when colors are enabled, otherwise each line with
+    /// differences ends with a count of them.
+    #[arg(
+        long,
+        value_name = "OTHERFILE",
+        requires = "hexdump",
+        conflicts_with_all = ["pretty", "input_format"]
+    )]
+    diff: Option<PathBuf>,
+
+    /// When to use colors in hexdump output
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t)]
+    color: ColorChoice,
+
+    /// Show hexdump offsets relative to the start of the range rather than the input
+    #[arg(long)]
+    relative_offsets: bool,
+
+    /// How to print the offset column of hexdump output
+    #[arg(long, value_enum, value_name = "BASE", default_value_t)]
+    offset_base: OffsetBase,
+
+    /// Number of 8-byte panels per hexdump line
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    panels: Option<u64>,
+
+    /// Number of bytes per hexdump line, a multiple of 8 (same as --panels N/8)
+    #[arg(long, value_name = "N", value_parser = range::parse_number, conflicts_with = "panels")]
+    hex_width: Option<u64>,
+
+    /// Number of hexdump bytes grouped together, one of 1, 2, 4, or 8
+    #[arg(long, value_name = "N", value_parser = hexdump::parse_group_size, default_value = "1")]
+    group_size: u8,
+
+    /// How to show each byte in hexdump output
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t)]
+    byte_format: ByteFormat,
+
+    /// Use uppercase hex digits in hexdump output and --format hex
+    #[arg(long)]
+    upper: bool,
+
+    /// How to show bytes in the character column of hexdump output
+    #[arg(long, value_enum, value_name = "TABLE", default_value_t)]
+    char_table: CharTable,
+
+    /// Don't print the character column of hexdump output
+    #[arg(long)]
+    no_chars: bool,
+
+    /// Print only the offsets and characters in hexdump output, not the hex bytes
+    #[arg(long, conflicts_with = "no_chars")]
+    chars_only: bool,
+
+    /// Output format, by default inferred from the output file extension or raw
+    ///
+    /// .hex is ihex, .srec (also .s19, .s28, .s37, .mot) is srec, and .b64 is base64. A .gz or
+    /// .zst extension compresses the output, and the extension before it picks the format, e.g.
+    /// "fw.hex.gz".
+    #[arg(short, long, value_enum, conflicts_with = "hexdump")]
+    format: Option<Format>,
+
+    /// Load address of the first output byte for record formats (ihex, srec)
+    #[arg(long, value_name = "ADDR", value_parser = range::parse_number, default_value_t = 0)]
+    base: u64,
+
+    /// S-record address width, by default the smallest one which fits
+    #[arg(long, value_enum, value_name = "TYPE")]
+    srec_type: Option<SrecType>,
+
+    /// Leave printable characters unescaped with --format escaped
+    #[arg(long)]
+    literal_printable: bool,
+
+    /// How to encode the data with --format json
+    #[arg(long, value_enum, value_name = "ENCODING", default_value_t)]
+    json_encoding: DataEncoding,
+
+    /// Pipe the selected bytes through a shell command before output
+    #[arg(long, visible_alias = "filter", value_name = "CMD")]
+    filter_cmd: Option<String>,
+
+    /// Run a shell command with the output on its stdin rather than writing it anywhere, and exit
+    /// with the command's exit status
+    #[arg(
+        long,
+        value_name = "CMD",
+        conflicts_with_all = [
+            "OUTFILE",
+            "output_fd",
+            "tee",
+            "compress",
+            "sync",
+            "sandbox",
+            "pager",
+            "split_at",
+            "volume_size",
+            "mutate",
+            "monitor",
+            "list",
+            "in_place",
+        ],
+    )]
+    exec: Option<String>,
+
+    /// Print the offset of every STEP-th byte in the range instead of the data
+    #[arg(
+        long,
+        value_name = "STEP",
+        value_parser = range::parse_number,
+        conflicts_with_all = ["hexdump", "format"],
+    )]
+    print_offsets: Option<u64>,
+
+    /// With --print-offsets, also print the block number of each offset
+    #[arg(long, value_name = "SIZE", value_parser = range::parse_number, requires = "print_offsets")]
+    block_size: Option<u64>,
+
+    /// Print runs of at least MIN identical bytes (default 16) instead of the data
+    #[arg(
+        long,
+        value_name = "MIN",
+        value_parser = range::parse_number,
+        num_args = 0..=1,
+        default_missing_value = "16",
+        conflicts_with_all = ["hexdump", "format", "print_offsets"],
+    )]
+    rle_summary: Option<u64>,
+
+    /// List the files in the FAT or ext2/3/4 filesystem image starting at the range start
+    #[arg(
+        long,
+        conflicts_with_all = ["hexdump", "format", "print_offsets", "rle_summary", "input_format"],
+    )]
+    list: bool,
+
+    /// Print the offset of each occurrence of PATTERN (hex bytes, e.g. "7f454c46") in the range.
+    /// May be given multiple times to search for several patterns in one pass, in which case the
+    /// matching pattern is printed after each offset.
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        value_parser = scan::parse_hex_pattern,
+        conflicts_with_all = ["hexdump", "format", "print_offsets", "rle_summary", "list"],
+    )]
+    find: Vec<Pattern>,
+
+    /// Print the offset and value of each integer matching SPEC in the range. SPEC is TYPE:VALUE or
+    /// TYPE:LO-HI, where TYPE is u8, u16le, u16be, u32le, u32be, u64le, or u64be.
+    #[arg(
+        long,
+        value_name = "SPEC",
+        value_parser = scan::parse_int_spec,
+        conflicts_with_all = ["hexdump", "format", "print_offsets", "rle_summary", "list", "find"],
+    )]
+    find_int: Option<IntSpec>,
+
+    /// Only report --find and --find-int matches at offsets which are a multiple of N
+    #[arg(long, value_name = "N", value_parser = range::parse_size, default_value = "1")]
+    align: u64,
+
+    /// Split the output into numbered files OUTFILE.000, OUTFILE.001, etc, starting a new file
+    /// at each occurrence of PATTERN (hex bytes, e.g. "1f8b08"), and write a manifest of them to
+    /// OUTFILE.manifest for `bcut join`
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        value_parser = scan::parse_hex_pattern,
+        requires = "OUTFILE",
+        conflicts_with_all = [
+            "hexdump",
+            "format",
+            "print_offsets",
+            "rle_summary",
+            "list",
+            "find",
+            "find_int",
+        ],
+    )]
+    split_at: Option<Pattern>,
+
+    /// Span the output across numbered volume files OUTFILE.000, OUTFILE.001, etc of at most SIZE
+    /// bytes each, and write a manifest of them to OUTFILE.manifest. Each volume begins with a
+    /// small header recording its place in the set.
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = range::parse_size,
+        requires = "OUTFILE",
+        conflicts_with_all = [
+            "hexdump",
+            "format",
+            "print_offsets",
+            "rle_summary",
+            "list",
+            "find",
+            "find_int",
+            "split_at",
+        ],
+    )]
+    volume_size: Option<u64>,
+
+    /// Write N randomized samples of the range to OUTFILE.000, OUTFILE.001, etc, for parser
+    /// testing corpora. Each sample is the range or a random slice of it with a few random bytes
+    /// flipped. The same SEED always gives the same samples, and what was done to each one is
+    /// logged to OUTFILE.mutations.
+    #[arg(
+        long,
+        value_name = "N:SEED",
+        value_parser = mutate::parse_spec,
+        requires = "OUTFILE",
+        conflicts_with_all = [
+            "hexdump",
+            "format",
+            "print_offsets",
+            "rle_summary",
+            "list",
+            "find",
+            "find_int",
+            "split_at",
+            "volume_size",
+        ],
+    )]
+    mutate: Option<MutateSpec>,
+
+    /// Drop the input range from the page cache after copying it, so that slicing a huge file
+    /// doesn't push everything else out of the cache. On macOS the input isn't cached at all.
+    #[arg(long)]
+    drop_cache: bool,
+
+    /// Ask for the next SIZE bytes of the input range to be read into the page cache ahead of
+    /// time while copying, e.g. 64M, which helps with slow storage like spinning disks
+    #[arg(long, value_name = "SIZE", value_parser = range::parse_size)]
+    readahead: Option<u64>,
+
+    /// Keep reading as data is appended to the input, like `tail -f`
+    #[arg(long)]
+    follow: bool,
+
+    /// Read from the named extended attribute (or NTFS alternate data stream on Windows) of the
+    /// input file rather than its contents
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["follow", "monitor", "more_inputs"])]
+    xattr: Option<String>,
+
+    /// Decompress the input (gzip, zstd, xz, or bzip2, detected automatically) and apply the range
+    /// to the decompressed data
+    #[arg(
+        short = 'z',
+        long,
+        conflicts_with_all = ["follow", "monitor", "list", "more_inputs", "xattr"],
+    )]
+    decompress: bool,
+
+    /// Read the named member of a tar archive (which may be compressed), applying the range
+    /// within its data
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["follow", "monitor", "list", "more_inputs", "xattr"],
+    )]
+    tar_member: Option<String>,
+
+    /// Read the named member of a zip archive, applying the range within its data
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = [
+            "follow",
+            "monitor",
+            "list",
+            "more_inputs",
+            "xattr",
+            "decompress",
+            "tar_member",
+        ],
+    )]
+    zip_member: Option<String>,
+
+    /// Memory-map the input file rather than reading it. Raw output is written straight from the
+    /// mapping.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "follow",
+            "monitor",
+            "list",
+            "more_inputs",
+            "xattr",
+            "decompress",
+            "tar_member",
+            "zip_member",
+            "input_format",
+            "max_input",
+            "control_socket",
+            "filter_cmd",
+            "repeat",
+            "metadata",
+        ],
+    )]
+    mmap: bool,
+
+    /// How to advise the kernel about reading the --mmap input. Except with "normal", the pages
+    /// are also dropped once they've been copied.
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t, requires = "mmap")]
+    madvise: Madvise,
+
+    /// Read the input file with O_DIRECT, bypassing the page cache so that slicing a huge image
+    /// doesn't evict everything else from memory
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "follow",
+            "monitor",
+            "list",
+            "more_inputs",
+            "xattr",
+            "decompress",
+            "tar_member",
+            "zip_member",
+            "mmap",
+        ],
+    )]
+    direct: bool,
+
+    /// Read from inherited file descriptor N rather than a path, e.g. 3 for a file opened by the
+    /// shell with `3<FILE`
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(i32).range(0..),
+        conflicts_with_all = [
+            "input",
+            "more_inputs",
+            "monitor",
+            "list",
+            "xattr",
+            "zip_member",
+            "mmap",
+            "direct",
+        ],
+    )]
+    input_fd: Option<i32>,
+
+    /// Copy stdin to a temporary file in DIR (or the system temp directory) first, so that
+    /// ranges relative to the size of the input like ^N- and 50%-, and options which need a
+    /// seekable input file like --list, --zip-member, and --mmap, work with piped input
+    #[arg(
+        long,
+        value_name = "DIR",
+        num_args = 0..=1,
+        require_equals = true,
+        // the sandbox would stop us from deleting the spool file
+        conflicts_with_all = ["follow", "input_fd", "more_inputs", "sandbox"],
+    )]
+    spool: Option<Option<PathBuf>>,
+
+    /// Keep the --spool copy of stdin in memory rather than a file if it's no bigger than SIZE
+    ///
+    /// Bigger inputs are still copied to the temporary file. Only supported on Linux.
+    #[arg(long, value_name = "SIZE", value_parser = range::parse_size, requires = "spool")]
+    spool_memory: Option<u64>,
+
+    /// Wait for the input file to exist before opening it, for up to SECONDS if given, or
+    /// forever otherwise
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        num_args = 0..=1,
+        require_equals = true,
+        value_parser = range::parse_number,
+        conflicts_with_all = ["input_fd", "spool"],
+    )]
+    wait: Option<Option<u64>>,
+
+    /// Read the memory of process PID rather than a file, RANGE then selects virtual addresses
+    #[arg(
+        long,
+        value_name = "PID",
+        conflicts_with_all = [
+            "input",
+            "more_inputs",
+            "input_fd",
+            "follow",
+            "monitor",
+            "list",
+            "xattr",
+            "decompress",
+            "tar_member",
+            "zip_member",
+            "mmap",
+            "direct",
+            "spool",
+            "wait",
+        ],
+    )]
+    pid: Option<u32>,
+
+    /// Read from an endless stream of zeros rather than a file, e.g. `bcut --zero +1G -o FILE`
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "input",
+            "more_inputs",
+            "input_fd",
+            "pid",
+            "follow",
+            "monitor",
+            "list",
+            "xattr",
+            "decompress",
+            "tar_member",
+            "zip_member",
+            "mmap",
+            "direct",
+            "spool",
+            "wait",
+        ],
+    )]
+    zero: bool,
+
+    /// Read from an endless pseudo-random stream rather than a file. The same SEED always gives
+    /// the same data at the same offsets, a random seed is picked and printed if it's omitted.
+    #[arg(
+        long,
+        value_name = "SEED",
+        num_args = 0..=1,
+        require_equals = true,
+        value_parser = range::parse_number,
+        conflicts_with_all = [
+            "input",
+            "more_inputs",
+            "input_fd",
+            "pid",
+            "zero",
+            "follow",
+            "monitor",
+            "list",
+            "xattr",
+            "decompress",
+            "tar_member",
+            "zip_member",
+            "mmap",
+            "direct",
+            "spool",
+            "wait",
+        ],
+    )]
+    random: Option<Option<u64>>,
+
+    /// Output the selected bytes N times over, or forever with "inf"
+    #[arg(long, value_name = "N", value_parser = parse_repeat)]
+    repeat: Option<u64>,
+
+    /// Re-read the range periodically and print the bytes which changed since the last poll
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "hexdump",
+            "format",
+            "print_offsets",
+            "rle_summary",
+            "list",
+            "find",
+            "find_int",
+            "split_at",
+            "follow",
+            "filter_cmd",
+            "input_format",
+        ],
+    )]
+    monitor: bool,
+
+    /// Time between polls with --monitor, in milliseconds
+    #[arg(
+        long,
+        value_name = "MS",
+        value_parser = range::parse_number,
+        default_value_t = 1000,
+        requires = "monitor"
+    )]
+    interval: u64,
+
+    /// Abort if more than SIZE bytes are read from the input
+    ///
+    /// Counts what's actually read from the file or stream, including anything read and thrown
+    /// away to get to the start of the range on a pipe, and the compressed data rather than the
+    /// decompressed data for compressed inputs.
+    #[arg(long, value_name = "SIZE", value_parser = range::parse_size)]
+    max_input: Option<u64>,
+
+    /// Pad the output to SIZE bytes with the --fill byte, e.g. to make a whole flash partition
+    /// image. It's an error for the range to be longer than SIZE.
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = range::parse_size,
+        conflicts_with_all = ["mmap", "in_place"]
+    )]
+    pad_to: Option<u64>,
+
+    /// Copy the whole input, replacing the bytes of the range with the --fill byte, e.g. to scrub
+    /// secrets from a disk image before sharing it
+    #[arg(long, conflicts_with_all = ["mmap", "in_place", "bits"])]
+    redact: bool,
+
+    /// Copy the whole input except for the range, joining the parts before and after it
+    #[arg(long, conflicts_with_all = ["redact", "mmap", "in_place", "bits"])]
+    cut_out: bool,
+
+    /// Byte to fill with for --pad-to (0xff by default) and --redact (0 by default)
+    #[arg(long, value_name = "BYTE", value_parser = range::parse_byte)]
+    fill: Option<u8>,
+
+    /// Listen on a unix socket at PATH for commands to check on and control the job
+    ///
+    /// Each line sent to the socket is one command, and gets a one line response:
+    ///   status    print bytes read so far, elapsed seconds, running/paused, and the rate limit
+    ///   pause     stop reading input until resumed
+    ///   resume    continue after a pause
+    ///   abort     stop the job with an error
+    ///   rate N    limit input to N bytes per second (K/M/G suffixes allowed), 0 for no limit
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["monitor", "list"],
+        verbatim_doc_comment
+    )]
+    control_socket: Option<PathBuf>,
+
+    /// Refuse to allocate more than SIZE bytes for any one structure described by the input
+    ///
+    /// Applies to filesystem listing, archive member lookup, and decoding record formats, where
+    /// corrupt or malicious headers could otherwise ask for huge amounts of memory.
+    #[arg(long, value_name = "SIZE", value_parser = range::parse_size, default_value = "256M")]
+    parser_limit: u64,
+
+    /// Drop filesystem and network access once the input and output are open
+    ///
+    /// Uses landlock on Linux, so that a bug in one of the parsers hit by a malicious input image
+    /// can't be used to touch anything but the already-open files. Not supported on other
+    /// platforms. Can't be used with --zip-member or --tar-member, which parse the input while
+    /// opening it, --control-socket, whose thread would run outside the sandbox, or --metadata,
+    /// --repro, --sync and --pager, which open files or start the pager after copying. With
+    /// --repeat the input is replayed from memory rather than reopened.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "monitor",
+            "split_at",
+            "volume_size",
+            "mutate",
+            "filter_cmd",
+            "zip_member",
+            "tar_member",
+            "control_socket",
+            "metadata",
+            "repro",
+            "sync",
+            "pager",
+        ]
+    )]
+    sandbox: bool,
+
+    /// Print read/write latency statistics to stderr when finished
+    #[arg(long)]
+    profile: bool,
+
+    /// Print the number of bytes copied, how long it took, and how the data was copied (like
+    /// splice or copy_file_range) to stderr when finished
+    #[arg(long)]
+    timing: bool,
+
+    /// Make the output files reproducible: set their timestamps to SOURCE_DATE_EPOCH (or the Unix
+    /// epoch), then print a SHA-256 identifying the whole set of files written. A --metadata
+    /// sidecar is part of the set, and records the same time.
+    #[arg(long, requires = "OUTFILE", conflicts_with_all = ["list", "monitor"])]
+    repro: bool,
+
     /// Byte range to select
     ///
     /// Byte numbers in the input start at zero.
@@ -34,21 +839,194 @@ struct Args {
     ///   +M    same as -M
     ///   -     select the whole input (same as 0-)
     ///   +     select the whole input (same as 0+)
-    #[arg(value_name = "RANGE", verbatim_doc_comment)]
-    range: String,
+    ///   NAME@ the range saved for the input file by `bcut bookmark add`
+    /// Positions can also be relative to the size of the input, which needs an input
+    /// file, or --spool for piped input:
+    ///   ^N    N bytes before the end, so ^N- is the last N bytes like `tail -c N`
+    ///   N%    N percent of the way through, e.g. 25%+10%
+    #[arg(value_name = "RANGE", verbatim_doc_comment, required = true)]
+    range: Option<String>,
+
+    /// Decode the input from a record format, RANGE then selects addresses rather than offsets.
+    /// The compressed formats apply RANGE to the decompressed data.
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t)]
+    input_format: InputFormat,
+
+    /// Byte used to fill gaps between records with --input-format
+    #[arg(long, value_name = "BYTE", value_parser = range::parse_byte, default_value = "0xff")]
+    gap_fill: u8,
+
+    /// Skip the bad regions listed in FILE, one "OFFSET LENGTH" of the input per line, leaving
+    /// them out of the output
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["input_format", "mmap"])]
+    skip_map: Option<PathBuf>,
+
+    /// Replace the --skip-map regions with this byte instead, so the rest keeps its offsets
+    #[arg(long, value_name = "BYTE", value_parser = range::parse_byte, requires = "skip_map")]
+    skip_fill: Option<u8>,
 
-    /// Input file, omit or use "-" for stdin
+    /// Reverse the byte order of each N-byte word (2, 4, or 8) of the range while copying it,
+    /// e.g. for memory dumps from big-endian targets
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = transform::parse_swap_width,
+        conflicts_with_all = ["mmap", "in_place"]
+    )]
+    byte_swap: Option<usize>,
+
+    /// Invert every bit of the range while copying it, e.g. for NAND dumps and ROM images which
+    /// are stored inverted. This is applied after --byte-swap.
+    #[arg(long, conflicts_with_all = ["mmap", "in_place"])]
+    invert: bool,
+
+    /// Measure the range in bits rather than bytes, counting from the most significant bit of each
+    /// byte. The selected bits are shifted to start on a byte boundary, and the last byte of the
+    /// output is padded with zero bits.
+    #[arg(long, conflicts_with_all = ["mmap", "in_place"])]
+    bits: bool,
+
+    /// Input file, http(s):// URL, or tcp://HOST:PORT address, omit or use "-" for stdin
     #[arg(value_name = "FILE")]
     input: Option<PathBuf>,
+
+    /// More input files, which are read as if they were concatenated after the first one
+    #[arg(value_name = "FILE", conflicts_with_all = ["list", "monitor"])]
+    more_inputs: Vec<PathBuf>,
 }
 
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Reassemble a set of files written by --split-at or --volume-size, or build an image from
+    /// a hand-written manifest of regions, verifying the hashes listed in the manifest
+    Join {
+        /// Manifest file of the set
+        #[arg(value_name = "MANIFEST")]
+        manifest: PathBuf,
+
+        /// Output file, omit or use "-" for stdout
+        #[arg(short, long, name = "OUTFILE")]
+        output: Option<PathBuf>,
+    },
+
+    /// Convert a file between encodings, e.g. Intel HEX to S-records
+    ///
+    /// The input is decoded into its address space, optionally cut down to RANGE, and written in
+    /// the output format with the same addresses. Gaps between records are filled.
+    Convert {
+        /// Input encoding
+        #[arg(long, value_enum, value_name = "FORMAT", default_value_t)]
+        from: InputFormat,
+
+        /// Output encoding, by default inferred from the OUT file name
+        #[arg(long, value_enum, value_name = "FORMAT")]
+        to: Option<Format>,
+
+        /// Byte range of the decoded address space to convert, in the same form as the main RANGE
+        #[arg(long, value_name = "RANGE")]
+        range: Option<String>,
+
+        /// S-record address width, by default the smallest one which fits
+        #[arg(long, value_enum, value_name = "TYPE")]
+        srec_type: Option<SrecType>,
+
+        /// Byte used to fill gaps between records
+        #[arg(long, value_name = "BYTE", value_parser = range::parse_byte, default_value = "0xff")]
+        gap_fill: u8,
+
+        /// Refuse to decode more than SIZE bytes
+        #[arg(long, value_name = "SIZE", value_parser = range::parse_size, default_value = "256M")]
+        parser_limit: u64,
+
+        /// Input file, "-" for stdin
+        #[arg(value_name = "IN")]
+        input: PathBuf,
+
+        /// Output file, "-" for stdout
+        #[arg(value_name = "OUT")]
+        output: PathBuf,
+    },
+
+    /// Manage named ranges of a file, which can then be used as the RANGE argument in the form
+    /// NAME@. Bookmarks are stored next to the file in FILE.bookmarks.
+    Bookmark {
+        #[command(subcommand)]
+        action: BookmarkAction,
+    },
+
+    /// Copy a file with the bytes at an offset replaced by the contents of another file
+    ///
+    /// This is the opposite of cutting out a range: the output is the whole input, with the patch
+    /// written over it starting at OFFSET. The patch can run past the end of the input.
+    Patch {
+        /// Offset in the input to write the patch at
+        #[arg(long, value_name = "OFFSET", value_parser = range::parse_number)]
+        at: u64,
+
+        /// File holding the bytes to write
+        #[arg(long, value_name = "FILE")]
+        with: PathBuf,
+
+        /// Fail unless the patch is exactly SIZE bytes
+        #[arg(long, value_name = "SIZE", value_parser = range::parse_size)]
+        length: Option<u64>,
+
+        /// Input file, "-" for stdin
+        #[arg(value_name = "IN")]
+        input: PathBuf,
+
+        /// Output file, omit or use "-" for stdout
+        #[arg(short, long, name = "OUTFILE")]
+        output: Option<PathBuf>,
+    },
+
+    /// Check an existing image against a manifest, listing every region which doesn't match
+    Check {
+        /// Manifest file describing the image
+        #[arg(value_name = "MANIFEST")]
+        manifest: PathBuf,
+
+        /// Image file to check
+        #[arg(value_name = "IMAGE")]
+        image: PathBuf,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum BookmarkAction {
+    /// Add a bookmark, replacing any existing one with the same name
+    Add {
+        #[arg(value_name = "NAME")]
+        name: String,
+        #[arg(value_name = "RANGE", allow_hyphen_values = true)]
+        range: String,
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+    /// Remove a bookmark
+    Remove {
+        #[arg(value_name = "NAME")]
+        name: String,
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+    /// List the bookmarks of a file
+    List {
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+}
+
+/// Size of the buffer used by io_copy, set by --buffer-size
+static BUFFER_SIZE: AtomicUsize = AtomicUsize::new(1024 * 1024);
+
 /// This behaves the same as [`std::io::copy`] but much faster for large inputs. We lose the
 /// Linux-specific sendfile/splice optimizations, but it seems like those don't get used by bcut
 /// anyway and it falls back to stack_buffer_copy with an 8K IO buffer. Increasing that buffer size
-/// to 1M gives nearly 3X speedup when copying large (multi-gigabyte) files on my machine.
+/// to 1M gives nearly 3X speedup when copying large (multi-gigabyte) files on my machine. Plain
+/// copies to or from a pipe get splice back explicitly, see splice_files.
 fn io_copy<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> io::Result<u64> {
-    const BUF_SIZE: usize = 1024 * 1024;
-    let mut buf = vec![0u8; BUF_SIZE];
+    let mut buf = vec![0u8; BUFFER_SIZE.load(Ordering::Relaxed)];
     let mut total = 0;
 
     loop {
@@ -64,115 +1042,1497 @@ fn io_copy<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> io::Result<u64>
     Ok(total)
 }
 
-fn prepare_input(path: &Option<PathBuf>, range: &Range) -> io::Result<Box<dyn Read>> {
-    let is_stdin = match path {
-        Some(ref path) => matches!(path.to_str(), Some("-")),
-        None => true,
-    };
+/// Print the absolute offset of every step-th byte in the range [start, start+len), optionally
+/// followed by the block number of that offset.
+fn print_offsets(
+    output: &mut dyn Write,
+    start: u64,
+    len: u64,
+    step: u64,
+    block_size: Option<u64>,
+) -> io::Result<()> {
+    let end = start + len;
+    let mut offset = start;
+    while offset < end {
+        match block_size {
+            Some(bs) => writeln!(output, "{} {}", offset, offset / bs)?,
+            None => writeln!(output, "{}", offset)?,
+        }
+        offset = match offset.checked_add(step) {
+            Some(o) => o,
+            None => break,
+        };
+    }
+    Ok(())
+}
 
+/// Get a writer for stdout, making it unbuffered when possible on unix. std::io::Stdout is always
+/// line-buffered, which wastes time on memchr looking for line endings when we're dumping lots of
+/// binary data.
+///
+/// Note: this opens a new file descriptor for stdout which bypasses the standard library's
+/// buffering and locking. Continuing to use println!() and io::stdout() won't cause safety issues,
+/// but could result in unexpected jumbled results on stdout if writes between this object and
+/// std's Stdout are interleaved without force-flushing.
+fn open_stdout() -> io::Result<Box<dyn Write>> {
     #[cfg(unix)]
     {
-        use rustix::{
-            fs::{seek, SeekFrom},
-            io::{dup, Errno},
-            stdio::stdin,
-        };
-
-        // treat everything including stdin as a File so that we bypass std's buffering
-        let mut file =
-            if is_stdin { File::from(dup(stdin())?) } else { File::open(path.as_ref().unwrap())? };
-
-        // seek forward into the input if needed
-        if range.start != 0 {
-            match seek(&file, SeekFrom::Current(range.start.try_into().unwrap())) {
-                Ok(_) => (),
-                Err(Errno::SPIPE) => {
-                    // Failed to seek because this File is a pipe, so just read the first N bytes and
-                    // throw them away.
-                    let mut t = (&mut file).take(range.start);
-                    io_copy(&mut t, &mut io::sink())?;
+        Ok(Box::new(File::from(rustix::io::dup(rustix::stdio::stdout())?)))
+    }
+    #[cfg(not(unix))]
+    {
+        Ok(Box::new(io::stdout()))
+    }
+}
+
+/// Parse the --repeat count, where "inf" is u64::MAX
+fn parse_repeat(s: &str) -> Result<u64, String> {
+    match s {
+        "inf" => Ok(u64::MAX),
+        _ => range::parse_number(s).map_err(|e| e.to_string()),
+    }
+}
+
+/// Whether two paths name the same existing file
+fn same_file(a: &Path, b: &Path) -> bool {
+    matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Whether an input or output path option means stdin or stdout, i.e. it was omitted or "-"
+fn is_stdio(path: &Option<PathBuf>) -> bool {
+    match path {
+        None => true,
+        Some(p) => p.to_str() == Some("-"),
+    }
+}
+
+/// Settings which control how the output file is opened
+#[derive(Debug, Default, Clone, Copy)]
+struct OutputOptions {
+    /// add to the end of the file rather than truncating it
+    append: bool,
+    /// write starting at this offset without truncating the file
+    seek: Option<u64>,
+    /// write to this inherited file descriptor rather than the path
+    fd: Option<i32>,
+    /// allocate this many bytes of the file before writing
+    preallocate: Option<u64>,
+    /// refuse to overwrite an existing regular file
+    no_clobber: bool,
+    /// compress the output, rather than only when the file name says so
+    compress: Option<Compression>,
+}
+
+impl OutputOptions {
+    fn new(args: &Args) -> Self {
+        Self {
+            append: args.append,
+            seek: args.seek,
+            fd: args.output_fd,
+            preallocate: None,
+            no_clobber: args.no_clobber && !args.force,
+            compress: args.compress,
+        }
+    }
+
+    /// Open an output file
+    fn open(&self, path: &Path) -> io::Result<File> {
+        let mut options = File::options();
+        options.create(true);
+        if self.append {
+            options.append(true);
+        } else {
+            options.write(true).truncate(self.seek.is_none());
+        }
+        let truncating = !self.append && self.seek.is_none();
+        let mut file = if self.no_clobber && truncating {
+            match File::options().write(true).create_new(true).open(path) {
+                Ok(file) => file,
+                // devices and pipes aren't clobbered by writing to them
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    if std::fs::metadata(path)?.is_file() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            "the file already exists, use --force to overwrite it",
+                        ));
+                    }
+                    options.open(path)?
                 }
-                Err(e) => return Err(e.into()),
+                Err(e) => return Err(e),
             }
+        } else {
+            options.open(path)?
+        };
+        if let Some(offset) = self.seek {
+            file.seek(SeekFrom::Start(offset))?;
         }
+        if let Some(len) = self.preallocate {
+            preallocate(&mut file, len)?;
+        }
+        Ok(file)
+    }
+}
 
-        match range.count {
-            Some(count) => Ok(Box::new(file.take(count))),
-            None => Ok(Box::new(file)),
+/// Reserve disk space for len bytes from the current position of a regular file, so that running
+/// out of space fails now rather than halfway through the copy. The file's size isn't changed, so
+/// nothing needs cleaning up if less gets written.
+fn preallocate(file: &mut File, len: u64) -> io::Result<()> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        use rustix::fs::{fallocate, FallocateFlags};
+        use rustix::io::Errno;
+
+        if len == 0 || !file.metadata()?.is_file() {
+            return Ok(());
         }
+        let pos = file.stream_position()?;
+        match fallocate(&*file, FallocateFlags::KEEP_SIZE, pos, len) {
+            // not every filesystem can do this, which is fine since it's only an optimization
+            Ok(()) | Err(Errno::OPNOTSUPP) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        let _ = (file, len);
+        Ok(())
+    }
+}
+
+/// Print the --timing summary, if it was asked for
+fn report_timing(args: &Args, bytes: u64, start_time: Instant, method: CopyMethod) {
+    if args.timing {
+        profile::report_timing(bytes, start_time.elapsed(), method);
     }
+}
 
+/// Open an inherited file descriptor for --output-fd
+fn open_fd(fd: i32) -> io::Result<File> {
+    #[cfg(unix)]
+    {
+        // SAFETY: the descriptor was inherited from our parent, so nothing else in bcut owns or
+        // closes it. If it isn't actually open, dup fails with EBADF.
+        let fd = unsafe { std::os::fd::BorrowedFd::borrow_raw(fd) };
+        Ok(File::from(rustix::io::dup(fd)?))
+    }
     #[cfg(not(unix))]
     {
-        if is_stdin {
-            let mut stdin = io::stdin();
-            if range.start != 0 {
-                let mut t = stdin.lock().take(range.start);
-                io_copy(&mut t, &mut io::sink())?;
+        let _ = fd;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--output-fd isn't supported on this platform",
+        ))
+    }
+}
+
+fn open_output(path: &Option<PathBuf>, opts: &OutputOptions) -> Result<Box<dyn Write>> {
+    // --compress overrides what the file name says
+    let compression = match opts.compress {
+        Some(Compression { method, level }) => method.map(|method| (method, level)),
+        None => path
+            .as_ref()
+            .filter(|_| !is_stdio(path))
+            .and_then(|path| compress::Method::from_path(path))
+            .map(|method| (method, None)),
+    };
+    if compression.is_some() && opts.seek.is_some() {
+        bail!("--seek can't be used with a compressed output file");
+    }
+
+    let output: Box<dyn Write> = match (opts.fd, path) {
+        (Some(fd), _) => Box::new(open_fd(fd).context("failed to open output descriptor")?),
+        (None, path) if is_stdio(path) => open_stdout().context("failed to open stdout")?,
+        (None, path) => {
+            let path = path.as_ref().unwrap();
+            Box::new(opts.open(path).context("failed to open output file")?)
+        }
+    };
+    Ok(match compression {
+        Some((method, level)) => {
+            method.encoder(output, level).context("failed to start compression")?
+        }
+        None => output,
+    })
+}
+
+/// Open the output along with any --tee outputs
+fn open_outputs(args: &Args, opts: &OutputOptions) -> Result<Box<dyn Write>> {
+    let output = open_output(&args.output, opts)?;
+    if args.tee.is_empty() {
+        return Ok(output);
+    }
+    // --seek and --output-fd are only for the main output
+    let tee_opts = OutputOptions { seek: None, fd: None, ..*opts };
+    let mut outputs = vec![output];
+    for path in &args.tee {
+        outputs.push(
+            open_output(&Some(path.clone()), &tee_opts)
+                .with_context(|| format!("failed to open {}", path.display()))?,
+        );
+    }
+    Ok(Box::new(Tee::new(outputs)))
+}
+
+/// Guess the output format from the output file name, looking past a compression extension
+fn infer_format(path: &Option<PathBuf>) -> Option<Format> {
+    let path = path.as_ref().filter(|_| !is_stdio(path))?;
+    let path = match compress::Method::from_path(path) {
+        Some(_) => Path::new(path.file_stem()?),
+        None => path,
+    };
+    Format::from_extension(path.extension()?.to_str()?)
+}
+
+/// Get the output path when it's a base name for a set of numbered files. We don't want to create
+/// the file itself in this case.
+fn output_base<'a>(path: &'a Option<PathBuf>, option: &str) -> Result<&'a PathBuf> {
+    match path {
+        Some(p) if !is_stdio(path) => Ok(p),
+        _ => bail!("{} requires an output file name", option),
+    }
+}
+
+/// Write the manifest of a set of numbered output files
+fn write_manifest(manifest: &Manifest, base: &Path) -> Result<()> {
+    let mut file = File::create(split::manifest_path(base)).context("failed to create manifest")?;
+    manifest.write(&mut file).context("failed to write manifest")
+}
+
+/// Get the paths of a split or spanned set of files from its manifest, including the manifest
+fn manifest_files(manifest: &Manifest, base: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> =
+        manifest.chunks.iter().map(|chunk| base.with_file_name(&chunk.name)).collect();
+    paths.push(split::manifest_path(base));
+    paths
+}
+
+/// Get the input path if the input is a local file which is read normally from the range start,
+/// and so can be opened again independently.
+fn plain_file_input(args: &Args) -> Option<&PathBuf> {
+    let plain = plain_input(args);
+    args.input
+        .as_ref()
+        .filter(|p| plain && p.to_str().is_none_or(|p| p != "-" && !input::is_remote(p)))
+}
+
+/// Get the size of the input for a range which is relative to it. The range has to apply to the
+/// bytes of a single input file, which --spool makes piped input into.
+fn input_size(args: &Args) -> Result<u64> {
+    let raw = args.input_fd.is_none()
+        && !args.decompress
+        && args.tar_member.is_none()
+        && args.zip_member.is_none()
+        && args.xattr.is_none()
+        && args.more_inputs.is_empty()
+        && args.pid.is_none()
+        && !args.zero
+        && args.random.is_none()
+        && args.input_format == InputFormat::Binary;
+    if !raw {
+        bail!("a range relative to the size of the input can only be used with a plain input file");
+    }
+    let path = match &args.input {
+        Some(path) if !is_stdio(&args.input) && !input::is_remote(&path.to_string_lossy()) => path,
+        _ => bail!("a range relative to the size of the input needs an input file, or --spool"),
+    };
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    if let Some(size) = input::block_device_size(&file)? {
+        return Ok(size);
+    }
+    let meta = file.metadata()?;
+    if !meta.is_file() {
+        bail!(
+            "{} isn't a regular file, so its size isn't known (--spool can copy it from stdin)",
+            path.display()
+        );
+    }
+    Ok(meta.len())
+}
+
+/// Whether the input is read normally from the range start, from a path or stdin
+fn plain_input(args: &Args) -> bool {
+    args.input_fd.is_none()
+        && !args.decompress
+        && args.tar_member.is_none()
+        && args.zip_member.is_none()
+        && args.xattr.is_none()
+        && !args.mmap
+        && !args.direct
+        && !args.follow
+        && args.more_inputs.is_empty()
+        && args.pid.is_none()
+        && !args.zero
+        && args.random.is_none()
+        && args.input_format == InputFormat::Binary
+}
+
+/// Open the input and output for a copy with splice or sendfile, if this run is a plain copy
+/// between a file or stdin and a file, stdout, or --output-fd, where at least one end is a pipe or
+/// the output is a socket
+#[cfg(unix)]
+fn splice_files(args: &Args) -> Result<Option<(File, File)>> {
+    // --readahead needs to see each read
+    if !plain_copy(args) || !plain_input(args) || args.readahead.is_some() {
+        return Ok(None);
+    }
+    let input = match plain_file_input(args) {
+        Some(path) => File::open(path).context("failed to open input")?,
+        None if is_stdio(&args.input) => File::from(rustix::io::dup(rustix::stdio::stdin())?),
+        None => return Ok(None),
+    };
+    let input_is_pipe = splice::is_pipe(&input)?;
+
+    let output = match (args.output_fd, &args.output) {
+        (Some(fd), _) => open_fd(fd).context("failed to open output descriptor")?,
+        (None, path) if is_stdio(path) => File::from(rustix::io::dup(rustix::stdio::stdout())?),
+        (None, Some(path)) => {
+            // don't create or truncate the file unless it's certainly going to be used
+            if !input_is_pipe || compress::Method::from_path(path).is_some() {
+                return Ok(None);
             }
-            Ok(Box::new(stdin))
-        } else {
-            let mut file = File::open(path.as_ref().unwrap())?;
-            if range.start != 0 {
-                match file.seek(SeekFrom::Current(range.start.try_into().unwrap())) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        // failed to seek, probably a pipe? Not sure about Windows semantics...
-                        let mut t = (&mut file).take(range.start);
-                        io_copy(&mut t, &mut io::sink())?;
-                    }
-                }
+            OutputOptions::new(args).open(path).context("failed to open output file")?
+        }
+        (None, None) => unreachable!(),
+    };
+    // the output might be a terminal, which gets a hexdump unless --raw was given
+    let stdout_terminal =
+        is_stdio(&args.output) && args.output_fd.is_none() && output.is_terminal();
+    let kernel_copy = input_is_pipe || splice::is_pipe(&output)? || splice::is_socket(&output)?;
+    if !kernel_copy || (stdout_terminal && !args.raw) {
+        return Ok(None);
+    }
+    Ok(Some((input, output)))
+}
+
+/// Open the input and output for a copy with --jobs, if this run is a plain copy of a range from a
+/// regular file or block device, returning them and the length of the range
+#[cfg(unix)]
+fn parallel_files(args: &Args, range: &Range) -> Result<Option<(File, u64, File)>> {
+    let Some(input_path) = plain_file_input(args) else {
+        return Ok(None);
+    };
+    // --readahead needs to see each read
+    if args.jobs < 2 || !plain_copy(args) || args.readahead.is_some() {
+        return Ok(None);
+    }
+    if let Some(path) = args.output.as_ref().filter(|_| !is_stdio(&args.output)) {
+        if compress::Method::from_path(path).is_some() {
+            return Ok(None);
+        }
+    }
+
+    let input = File::open(input_path).context("failed to open input")?;
+    let size = match input::block_device_size(&input)? {
+        Some(size) => size,
+        None if input.metadata()?.is_file() => input.metadata()?.len(),
+        None => return Ok(None),
+    };
+    let start = range.start.min(size);
+    let len = range.count.unwrap_or(u64::MAX).min(size - start);
+
+    let output = match (args.output_fd, &args.output) {
+        (Some(fd), _) => open_fd(fd).context("failed to open output descriptor")?,
+        (None, path) if is_stdio(path) => {
+            let stdout = File::from(rustix::io::dup(rustix::stdio::stdout())?);
+            // a terminal gets a hexdump unless --raw was given
+            if stdout.is_terminal() && !args.raw {
+                return Ok(None);
             }
-            Ok(Box::new(file))
+            stdout
+        }
+        (None, Some(path)) => {
+            let preallocate = args.preallocate.then_some(len);
+            let opts = OutputOptions { preallocate, ..OutputOptions::new(args) };
+            opts.open(path).context("failed to open output file")?
         }
+        (None, None) => unreachable!(),
+    };
+    Ok(Some((input, len, output)))
+}
+
+/// Open the input and output for a sparse-aware copy, if this run is a plain copy of a range from
+/// one regular file to another. Anything which reads or transforms the data along the way needs
+/// the normal path.
+fn sparse_files(args: &Args) -> Result<Option<(File, File)>> {
+    let (Some(input_path), Some(output_path)) = (plain_file_input(args), &args.output) else {
+        return Ok(None);
+    };
+    // preallocating would fill in the holes, writing into an existing file with --seek has to
+    // overwrite its old data with the holes' zeros, and --readahead needs to see each read
+    if !plain_copy(args)
+        || args.preallocate
+        || args.seek.is_some()
+        || args.readahead.is_some()
+        || is_stdio(&args.output)
+        || compress::Method::from_path(output_path).is_some()
+    {
+        return Ok(None);
     }
+
+    let input = File::open(input_path).context("failed to open input")?;
+    let output_is_file = match std::fs::metadata(output_path) {
+        Ok(meta) => meta.is_file(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => true,
+        Err(e) => return Err(e).context("failed to open output file"),
+    };
+    if !input.metadata()?.is_file() || !output_is_file {
+        return Ok(None);
+    }
+    let output =
+        OutputOptions::new(args).open(output_path).context("failed to open output file")?;
+    Ok(Some((input, output)))
 }
 
-/// Get a writer for stdout, making it unbuffered when possible on unix. std::io::Stdout is always
-/// line-buffered, which wastes time on memchr looking for line endings when we're dumping lots of
-/// binary data.
-///
-/// Note: this opens a new file descriptor for stdout which bypasses the standard library's
-/// buffering and locking. Continuing to use println!() and io::stdout() won't cause safety issues,
-/// but could result in unexpected jumbled results on stdout if writes between this object and
-/// std's Stdout are interleaved without force-flushing.
-fn open_stdout() -> io::Result<Box<dyn Write>> {
+/// Whether the output is exactly the bytes of the range, with nothing reading or transforming the
+/// data along the way
+fn plain_copy(args: &Args) -> bool {
+    args.filter_cmd.is_none()
+        && args.exec.is_none()
+        && args.repeat.is_none()
+        && args.tee.is_empty()
+        && args.compress.is_none()
+        && args.metadata.is_none()
+        && !args.append
+        && args.skip_map.is_none()
+        && args.byte_swap.is_none()
+        && !args.invert
+        && !args.bits
+        && !args.redact
+        && !args.cut_out
+        && args.max_input.is_none()
+        && args.pad_to.is_none()
+        && args.control_socket.is_none()
+        && !args.profile
+        && !args.hexdump
+        && args.print_offsets.is_none()
+        && args.rle_summary.is_none()
+        && args.find.is_empty()
+        && args.find_int.is_none()
+        && args.split_at.is_none()
+        && args.volume_size.is_none()
+        && args.mutate.is_none()
+        && !args.sandbox
+        && args.format.unwrap_or_default() == Format::Raw
+        && infer_format(&args.output).unwrap_or_default() == Format::Raw
+}
+
+/// Get the final size of the output for --preallocate, when it's known before copying
+fn output_size(args: &Args, range: &Range) -> Option<u64> {
+    let output = args.output.as_ref().filter(|_| !is_stdio(&args.output))?;
+    if !plain_copy(args) || compress::Method::from_path(output).is_some() {
+        return None;
+    }
+    range_len(args, range)
+}
+
+/// Get the length of the range, limited to the size of the input if it's a regular file, or None
+/// if it isn't known before reading
+fn range_len(args: &Args, range: &Range) -> Option<u64> {
+    let available = plain_file_input(args)
+        .and_then(|path| std::fs::metadata(path).ok())
+        .filter(|meta| meta.is_file())
+        .map(|meta| meta.len().saturating_sub(range.start));
+    match (range.count, available) {
+        (Some(count), Some(available)) => Some(count.min(available)),
+        (count, available) => count.or(available),
+    }
+}
+
+/// Fill in the placeholders of the output file names
+fn expand_output_names(args: &mut Args, range: &Range) -> Result<()> {
+    let input_name = args.input.as_ref().filter(|_| !is_stdio(&args.input));
+    let input_name = input_name.map(|p| p.to_string_lossy().into_owned());
+    let values = placeholder::Values {
+        start: range.start,
+        len: range_len(args, range),
+        input_name: input_name.as_deref(),
+    };
+    let paths = args.output.iter_mut().chain(&mut args.tee).chain(&mut args.metadata);
+    for path in paths {
+        if let Some(template) = path.to_str().filter(|p| p.contains(['{', '}'])) {
+            *path = placeholder::expand(template, &values)
+                .with_context(|| format!("invalid file name '{}'", template))?
+                .into();
+        }
+    }
+    Ok(())
+}
+
+/// Copy the range with one of the fast paths which bypass the normal reader chain, if this run is
+/// a plain copy which can use one. This has to happen before the normal input is opened, since
+/// opening it moves past the range start of stdin. Returns whether the copy was done.
+fn fast_copy(args: &Args, range: &Range, start_time: Instant) -> Result<bool> {
+    #[cfg(unix)]
+    if let Some((input, len, output)) = parallel_files(args, range)? {
+        let (jobs, chunk) = (args.jobs.into(), BUFFER_SIZE.load(Ordering::Relaxed));
+        // stdout and --output-fd can be shared with other commands or in append mode, so only
+        // an output file we opened ourselves can be written out of order
+        let positioned = args.output_fd.is_none() && !is_stdio(&args.output);
+        if args.drop_cache {
+            input::bypass_cache(&input);
+        }
+        let copied = parallel::copy(&input, range.start, len, &output, positioned, jobs, chunk)
+            .context("failed to copy input")?;
+        if args.drop_cache {
+            input::drop_cache(&input, range.start, Some(len));
+        }
+        drop(output);
+        let paths: Vec<PathBuf> =
+            args.output.iter().filter(|_| !is_stdio(&args.output)).cloned().collect();
+        finish_outputs(args, &paths, None)?;
+        sync_stdio(args)?;
+        report_timing(args, copied, start_time, CopyMethod::Parallel(jobs));
+        return Ok(true);
+    }
+
+    if let Some((mut input, mut output)) = sparse_files(args)? {
+        input::advise_sequential(&input, range.start, range.count);
+        if args.drop_cache {
+            input::bypass_cache(&input);
+        }
+        let (copied, method) =
+            sparse::copy(&mut input, range, &mut output).context("failed to copy input")?;
+        if args.drop_cache {
+            input::drop_cache(&input, range.start, range.count);
+        }
+        drop(output);
+        finish_outputs(args, std::slice::from_ref(args.output.as_ref().unwrap()), None)?;
+        report_timing(args, copied, start_time, method);
+        return Ok(true);
+    }
+
+    #[cfg(unix)]
+    if let Some((mut input, mut output)) = splice_files(args)? {
+        // stdin can be a file which isn't at its start, and a pipe has no position at all
+        let offset = input.stream_position().ok().map(|pos| pos + range.start);
+        if let Some(offset) = offset {
+            input::advise_sequential(&input, offset, range.count);
+        }
+        if args.drop_cache {
+            input::bypass_cache(&input);
+        }
+        let (copied, method) =
+            splice::copy(&mut input, range, &mut output).context("failed to copy input")?;
+        if let Some(offset) = offset.filter(|_| args.drop_cache) {
+            input::drop_cache(&input, offset, range.count);
+        }
+        drop(output);
+        let paths: Vec<PathBuf> =
+            args.output.iter().filter(|_| !is_stdio(&args.output)).cloned().collect();
+        finish_outputs(args, &paths, None)?;
+        sync_stdio(args)?;
+        report_timing(args, copied, start_time, method);
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+/// Implement --metadata, --repro, and --sync for a finished set of output files
+fn finish_outputs(
+    args: &Args,
+    paths: &[PathBuf],
+    recorder: Option<&metadata::Recorder>,
+) -> Result<()> {
+    let metadata = match (&args.metadata, recorder) {
+        (Some(path), Some(recorder)) => {
+            let time = if args.repro { repro::timestamp() } else { SystemTime::now() };
+            recorder.write(path, time).context("failed to write metadata file")?;
+            Some(path)
+        }
+        _ => None,
+    };
+    if args.repro {
+        // the sidecar is part of the output, so it's normalized and hashed along with the rest
+        let paths: Vec<PathBuf> = paths.iter().chain(metadata).cloned().collect();
+        let hash = repro::finish(&paths).context("failed to normalize output files")?;
+        println!("{}", format::hex_string(&hash));
+    }
+    if args.sync {
+        for path in paths {
+            // any descriptor of the file will do, the data being synced belongs to the file
+            File::open(path)
+                .and_then(|file| file.sync_data())
+                .with_context(|| format!("failed to sync {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Implement --sync for an output which isn't a named file, i.e. stdout or --output-fd. Pipes and
+/// terminals can't be synced, which is fine.
+fn sync_stdio(args: &Args) -> Result<()> {
+    if !args.sync || !(is_stdio(&args.output) || args.output_fd.is_some()) {
+        return Ok(());
+    }
     #[cfg(unix)]
     {
-        Ok(Box::new(File::from(rustix::io::dup(rustix::stdio::stdout())?)))
+        let file = match args.output_fd {
+            Some(fd) => open_fd(fd)?,
+            None => File::from(rustix::io::dup(rustix::stdio::stdout())?),
+        };
+        match file.sync_data() {
+            Err(e) if e.kind() != io::ErrorKind::InvalidInput => {
+                Err(e).context("failed to sync output")
+            }
+            _ => Ok(()),
+        }
     }
     #[cfg(not(unix))]
     {
-        Ok(Box::new(io::stdout()))
+        Ok(())
+    }
+}
+
+/// Write the samples for --mutate and their log, returning the paths of all the files written
+fn write_mutations(data: &[u8], spec: MutateSpec, start: u64, base: &Path) -> Result<Vec<PathBuf>> {
+    let mut log_path = base.as_os_str().to_owned();
+    log_path.push(".mutations");
+    let log_path = PathBuf::from(log_path);
+    let mut log = File::create(&log_path).context("failed to create mutation log")?;
+    let mut paths = vec![log_path];
+    writeln!(log, "# seed {}, slice offsets are relative to {:#x}", spec.seed, start)?;
+
+    let mut rng = mutate::Rng::new(spec.seed);
+    for i in 0..spec.count {
+        let (sample, mutations) = mutate::sample(data, &mut rng);
+        let path = split::numbered_path(base, i);
+        std::fs::write(&path, sample)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        write!(log, "{}", path.file_name().unwrap_or_default().to_string_lossy())?;
+        for mutation in mutations {
+            write!(log, " {}", mutation)?;
+        }
+        writeln!(log)?;
+        paths.push(path);
     }
+    Ok(paths)
+}
+
+/// Implement --list, printing the contents of a filesystem which starts at the range start
+fn list_filesystem(args: &Args, range: &Range) -> Result<()> {
+    let path = match &args.input {
+        Some(p) if p.to_str() != Some("-") => p,
+        _ => bail!("--list requires a seekable input file"),
+    };
+    let mut file = File::open(path).context("failed to open input")?;
+    let mut output = open_output(&args.output, &OutputOptions::new(args))?;
+    if args.sandbox {
+        sandbox::enter().context("failed to enter sandbox")?;
+    }
+
+    let entries = fs::list(&mut file, range.start, range.count, args.parser_limit)
+        .context("failed to read filesystem")?;
+    for entry in entries {
+        match entry.offset {
+            Some(offset) => write!(output, "{:#012x}", offset)?,
+            None => write!(output, "{:>12}", "-")?,
+        }
+        writeln!(output, " {:>12} {}", entry.size, entry.path)?;
+    }
+    Ok(())
+}
+
+/// Read and decode a whole record file, then cut the range from the resulting address space,
+/// filling any gaps with gap_fill
+fn decode_records(
+    format: InputFormat,
+    input: &mut dyn Read,
+    range: &Range,
+    gap_fill: u8,
+    parser_limit: u64,
+) -> Result<Vec<u8>> {
+    let mut text = String::new();
+    input.read_to_string(&mut text).context("failed to read input")?;
+    let image = match format {
+        InputFormat::Ihex => Image::parse_ihex(&text),
+        InputFormat::Srec => Image::parse_srec(&text),
+        fmt => unreachable!("{:?} input isn't a record format", fmt),
+    }
+    .context("failed to decode input records")?;
+    let len = image.extract_len(range.start, range.count);
+    if len > parser_limit {
+        bail!("decoded range of {} bytes is larger than the parser limit", len);
+    }
+    Ok(image.extract(range.start, range.count, gap_fill))
+}
+
+/// Run a subcommand rather than the normal slicing
+fn run_command(command: &Command) -> Result<()> {
+    match command {
+        Command::Join { manifest, output } => {
+            let mut output = open_output(output, &OutputOptions::default())?;
+            join::join(manifest, &mut output).context("failed to join files")?;
+        }
+        Command::Convert { from, to, range, srec_type, gap_fill, parser_limit, input, output } => {
+            let range: Range =
+                range.as_deref().unwrap_or_default().parse().context("range parse error")?;
+            let input = Some(input.clone());
+            let output = Some(output.clone());
+            let Some(format) = to.or_else(|| infer_format(&output)) else {
+                bail!("can't tell the output format from the file name, use --to");
+            };
+
+            let data = match from {
+                fmt if !fmt.is_records() => {
+                    let mut data = Vec::new();
+                    let opts = InputOptions::default();
+                    match fmt.compression() {
+                        Some(method) => prepare_decompressed(&input, Some(method), &range, &opts),
+                        None => prepare_input(&input, &range, &opts),
+                    }
+                    .context("failed to open input")?
+                    .read_to_end(&mut data)
+                    .context("failed to read input")?;
+                    data
+                }
+                fmt => {
+                    let whole = Range { start: 0, count: None };
+                    let mut file = prepare_input(&input, &whole, &InputOptions::default())
+                        .context("failed to open input")?;
+                    decode_records(*fmt, &mut file, &range, *gap_fill, *parser_limit)?
+                }
+            };
+
+            let opts = FormatOptions {
+                format,
+                base: range.start,
+                srec_type: *srec_type,
+                ..Default::default()
+            };
+            let mut output = open_output(&output, &OutputOptions::default())?;
+            format::write_formatted(&opts, &mut &data[..], &mut output)
+                .context("failed to write output")?;
+        }
+        Command::Bookmark { action } => match action {
+            BookmarkAction::Add { name, range, file } => bookmark::add(file, name, range)?,
+            BookmarkAction::Remove { name, file } => bookmark::remove(file, name)?,
+            BookmarkAction::List { file } => {
+                for bookmark in bookmark::load(file)? {
+                    println!("{} {}", bookmark.name, bookmark.range);
+                }
+            }
+        },
+        Command::Patch { at, with, length, input, output } => {
+            let data = std::fs::read(with)
+                .with_context(|| format!("failed to read {}", with.display()))?;
+            patch::check_length(&data, *length)?;
+            // the output would be truncated before the input could be read
+            if output.as_ref().is_some_and(|o| !is_stdio(output) && same_file(input, o)) {
+                bail!("the output can't be the input file");
+            }
+
+            let whole = Range { start: 0, count: None };
+            let mut input = prepare_input(&Some(input.clone()), &whole, &InputOptions::default())
+                .context("failed to open input")?;
+            let mut output = open_output(output, &OutputOptions::default())?;
+            patch::patch(&mut input, &mut output, *at, &data).context("failed to patch input")?;
+        }
+        Command::Check { manifest, image } => {
+            let mismatches = join::check(manifest, image).context("failed to check image")?;
+            for mismatch in &mismatches {
+                println!("{}", mismatch);
+            }
+            if !mismatches.is_empty() {
+                bail!("image doesn't match the manifest");
+            }
+        }
+    }
+    Ok(())
 }
 
 fn run() -> Result<()> {
-    let args = Args::parse();
+    run_args(Args::parse())
+}
+
+/// Do everything for a parsed command line
+fn run_args(mut args: Args) -> Result<()> {
+    match usize::try_from(args.buffer_size) {
+        Ok(0) => bail!("buffer size must be nonzero"),
+        Ok(size) => BUFFER_SIZE.store(size, Ordering::Relaxed),
+        Err(_) => bail!("buffer size is too big"),
+    }
+    if let Some(command) = &args.command {
+        return run_command(command);
+    }
+
+    // a range of NAME@ is a bookmark of the input file
+    let mut range_text = args.range.clone().unwrap_or_default();
+    if let Some(name) = range_text.strip_suffix('@') {
+        let Some(input) = args.input.as_ref().filter(|_| !is_stdio(&args.input)) else {
+            bail!("bookmarks require an input file");
+        };
+        range_text = bookmark::lookup(input, name)?;
+    }
+
     // parse range manually so we can control the error message rather than letting clap do it
-    let range: Range = args.range.parse().context("range parse error")?;
+    let range_spec: RangeSpec = range_text.parse().context("range parse error")?;
+
+    if let Some(timeout) = args.wait {
+        let timeout = timeout.map(Duration::from_secs);
+        let paths = args.input.iter().chain(&args.more_inputs);
+        for path in paths.filter(|p| p.to_str().is_none_or(|p| p != "-" && !input::is_remote(p))) {
+            input::wait_for(path, timeout).context("failed to wait for input")?;
+        }
+    }
+
+    // the spool file is deleted when this goes out of scope at the end of the run
+    let _spool = match &args.spool {
+        Some(dir) if is_stdio(&args.input) => {
+            let memory = args.spool_memory.unwrap_or(0);
+            let spool = Spool::stdin(dir.as_deref(), memory).context("failed to spool stdin")?;
+            args.input = Some(spool.path().to_owned());
+            Some(spool)
+        }
+        _ => None,
+    };
+
+    // a range relative to the size of the input can be resolved now that any spool is in place.
+    // With --bits, the range is in bits.
+    let size = if range_spec.needs_size() {
+        Some(input_size(&args)?.saturating_mul(if args.bits { 8 } else { 1 }))
+    } else {
+        None
+    };
+    let range = range_spec.resolve(size).context("range parse error")?;
+    // with --bits, everything works with the bytes holding the bits until they're extracted
+    let (range, bit_range) = if args.bits {
+        let (bytes, shift) = bits::byte_range(&range);
+        (bytes, Some((shift, range.count)))
+    } else {
+        (range, None)
+    };
+    // --redact and --cut-out read the whole input, the range only says which part of it to change
+    let (range, edit_range) = if args.redact || args.cut_out {
+        (Range { start: 0, count: None }, Some(range))
+    } else {
+        (range, None)
+    };
+    expand_output_names(&mut args, &range)?;
+    let start_time = Instant::now();
+
+    if args.list {
+        return list_filesystem(&args, &range);
+    }
+
+    if args.monitor {
+        if is_stdio(&args.input) {
+            bail!("--monitor requires an input file");
+        }
+        let mut output = open_output(&args.output, &OutputOptions::new(&args))?;
+        monitor::monitor(&args.input, &range, Duration::from_millis(args.interval), &mut output)
+            .context("failed to monitor input")?;
+        return Ok(());
+    }
+
+    if args.in_place {
+        let Some(path) = plain_file_input(&args) else {
+            bail!("--in-place needs a local input file which is read normally");
+        };
+        inplace::shrink(path, &range).context("failed to cut input file in place")?;
+        return Ok(());
+    }
+
+    if args.repro && is_stdio(&args.output) {
+        bail!("--repro requires an output file");
+    }
+
+    if fast_copy(&args, &range, start_time)? {
+        return Ok(());
+    }
+
+    let input_opts = InputOptions {
+        follow: args.follow,
+        fd: args.input_fd,
+        drop_cache: args.drop_cache,
+        readahead: args.readahead,
+        max_input: args.max_input,
+    };
+    let (input_range, input_opts) = if args.input_format.is_records() {
+        (Range { start: 0, count: None }, InputOptions { follow: false, ..input_opts })
+    } else {
+        (Range { start: range.start, count: range.count }, input_opts)
+    };
+    let mapped = if args.mmap {
+        let Some(path) = args.input.as_ref().filter(|_| !is_stdio(&args.input)) else {
+            bail!("--mmap needs an input file, not stdin");
+        };
+        Some(Arc::new(Mapped::open(path, &range, args.madvise).context("failed to map input")?))
+    } else {
+        None
+    };
+    // the skip map is loaded now, before any sandbox is entered
+    let mut skip_regions = match &args.skip_map {
+        Some(path) => Some(
+            skipmap::load(path)
+                .with_context(|| format!("failed to load skip map {}", path.display()))?,
+        ),
+        None => None,
+    };
+    // a plain input file is wrapped as it's opened, so that it can seek over the bad regions
+    let skip_file = plain_file_input(&args)
+        .filter(|_| skip_regions.is_some() && !args.drop_cache && args.readahead.is_none());
+
+    // freeing the O_DIRECT buffer while paused
+    let mut direct_release = None;
+    let input = if let Some(map) = &mapped {
+        Ok(Box::new(MappedReader::new(map.clone())) as Box<dyn Read + Send>)
+    } else if args.zero || args.random.is_some() {
+        let Some(count) = input_range.count else {
+            bail!("--zero and --random need a range with a length, like +1G");
+        };
+        let source: Box<dyn Read + Send> = match args.random {
+            Some(seed) => {
+                let seed = seed.unwrap_or_else(|| {
+                    let seed = synth::random_seed();
+                    eprintln!("bcut: random seed {:#x}", seed);
+                    seed
+                });
+                Box::new(synth::Random::new(seed, input_range.start))
+            }
+            None => Box::new(synth::Zero),
+        };
+        Ok(Box::new(source.take(count)) as Box<dyn Read + Send>)
+    } else if let Some(pid) = args.pid {
+        prepare_process(pid, &input_range, &input_opts)
+    } else if args.direct {
+        let Some(path) = args.input.as_ref().filter(|_| !is_stdio(&args.input)) else {
+            bail!("--direct needs an input file, not stdin");
+        };
+        prepare_direct(path, &input_range, &input_opts).map(|(input, release)| {
+            direct_release = Some(release);
+            input
+        })
+    } else if let Some(name) = &args.xattr {
+        let Some(path) = args.input.as_ref().filter(|_| !is_stdio(&args.input)) else {
+            bail!("--xattr needs an input file, not stdin");
+        };
+        prepare_xattr(path, name, &input_range, &input_opts)
+    } else if let Some(member) = &args.zip_member {
+        let Some(path) = args.input.as_ref().filter(|_| !is_stdio(&args.input)) else {
+            bail!("--zip-member needs an input file, not stdin");
+        };
+        prepare_zip_member(path, member, &input_range, &input_opts, args.parser_limit)
+    } else if let Some(member) = &args.tar_member {
+        let method = args.input_format.compression();
+        prepare_tar_member(
+            &args.input,
+            member,
+            method,
+            &input_range,
+            &input_opts,
+            args.parser_limit,
+        )
+    } else if args.decompress || args.input_format.compression().is_some() {
+        let method = args.input_format.compression();
+        prepare_decompressed(&args.input, method, &input_range, &input_opts)
+    } else if let Some(path) = skip_file {
+        let regions = skip_regions.take().unwrap_or_default();
+        prepare_skip_bad(path, regions, args.skip_fill, &input_range)
+    } else if args.more_inputs.is_empty() {
+        prepare_input(&args.input, &input_range, &input_opts)
+    } else {
+        if is_stdio(&args.input) || args.more_inputs.iter().any(|p| p.to_str() == Some("-")) {
+            bail!("stdin can't be used with multiple input files");
+        }
+        let paths: Vec<PathBuf> = args.input.iter().chain(&args.more_inputs).cloned().collect();
+        prepare_concat(&paths, &input_range, &input_opts)
+    }
+    .context("failed to open input")?;
+    // the inputs which don't take the options read just the range from a file or generator, so
+    // the limit can go straight on top of them
+    let sized = mapped.is_some() || args.zero || args.random.is_some() || skip_file.is_some();
+    let mut input: Box<dyn Read + Send> = match args.max_input {
+        Some(limit) if sized => Box::new(MaxInput::new(input, limit)),
+        _ => input,
+    };
+
+    let control = match &args.control_socket {
+        Some(path) => Some(ControlSocket::bind(path).context("failed to create control socket")?),
+        None => None,
+    };
 
-    let mut input = prepare_input(&args.input, &range).context("failed to open input")?;
+    let mut diff_input = match &args.diff {
+        Some(path) => Some(
+            prepare_input(&Some(path.clone()), &range, &InputOptions::default())
+                .with_context(|| format!("failed to open {}", path.display()))?,
+        ),
+        None => None,
+    };
+
+    // read before any sandbox is entered
+    let annotations = match &args.annotate {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            annotate::parse(&text)?
+        }
+        None => Vec::new(),
+    };
+
+    // with --sandbox, open the output now so that nothing from the input is parsed before
+    // entering the sandbox. The options which parse while opening the input (--zip-member and
+    // --tar-member) or start a thread (--control-socket) can't be used with it.
+    let early_output = if args.sandbox {
+        let output = open_outputs(&args, &OutputOptions::new(&args))?;
+        sandbox::enter().context("failed to enter sandbox")?;
+        Some(output)
+    } else {
+        None
+    };
+
+    let input: Box<dyn Read + Send> = match args.input_format {
+        fmt if !fmt.is_records() => input,
+        fmt => Box::new(io::Cursor::new(decode_records(
+            fmt,
+            &mut input,
+            &range,
+            args.gap_fill,
+            args.parser_limit,
+        )?)),
+    };
+    let input: Box<dyn Read + Send> = match skip_regions {
+        Some(regions) => Box::new(SkipBad::new(input, regions, range.start, args.skip_fill)),
+        None => input,
+    };
+    let input: Box<dyn Read + Send> = match &edit_range {
+        Some(edit) if args.cut_out => Box::new(CutOut::new(input, edit)),
+        Some(edit) => Box::new(Redact::new(input, edit, args.fill.unwrap_or(0))),
+        None => input,
+    };
+    let input: Box<dyn Read + Send> = match bit_range {
+        Some((shift, count)) => Box::new(BitReader::new(input, shift, count)),
+        None => input,
+    };
+    // the transforms of the data itself are done together
+    let mut transforms = Vec::new();
+    if let Some(width) = args.byte_swap {
+        transforms.push(Transform::Swap(width));
+    }
+    if args.invert {
+        transforms.push(Transform::Xor(vec![0xff]));
+    }
+    // a raw file can only be read again for --repeat if nothing changes the data on the way
+    let raw_data = args.skip_map.is_none()
+        && edit_range.is_none()
+        && bit_range.is_none()
+        && transforms.is_empty();
+    let input: Box<dyn Read + Send> = if transforms.is_empty() {
+        input
+    } else {
+        Box::new(TransformReader::new(input, transforms))
+    };
+    let input: Box<dyn Read + Send> = match args.repeat {
+        Some(times) => {
+            // files can be read again each time rather than keeping a copy in memory, except in
+            // the sandbox, which can't open them
+            let reopen =
+                plain_file_input(&args).filter(|_| raw_data && !args.sandbox).map(|path| {
+                    let path = Some(path.clone());
+                    let range = Range { start: range.start, count: range.count };
+                    Box::new(move || prepare_input(&path, &range, &InputOptions::default()))
+                        as Reopen
+                });
+            Box::new(Repeat::new(input, times, reopen))
+        }
+        None => input,
+    };
+    let input: Box<dyn Read + Send> = match args.pad_to {
+        Some(size) => Box::new(PadTo::new(input, size, args.fill.unwrap_or(0xff))),
+        None => input,
+    };
+    let input: Box<dyn Read + Send> = match &control {
+        Some(control) => Box::new(control.wrap(input)),
+        None => input,
+    };
+    #[cfg(unix)]
+    let input: Box<dyn Read + Send> = Box::new(
+        pause::PauseOnStop::new(input, direct_release)
+            .context("failed to set up SIGTSTP handler")?,
+    );
+    let recorder = args.metadata.as_ref().map(|_| {
+        let input = args.input.as_ref().filter(|_| !is_stdio(&args.input));
+        metadata::Recorder::new(input.map(|p| p.display().to_string()), range.start)
+    });
+    let input: Box<dyn Read + Send> = match &recorder {
+        Some(recorder) => Box::new(recorder.wrap(input)),
+        None => input,
+    };
+    let mut input: Box<dyn Read> = match &args.filter_cmd {
+        Some(cmd) => Box::new(
+            FilterReader::spawn(cmd, input)
+                .with_context(|| format!("failed to run filter command '{}'", cmd))?,
+        ),
+        None => input,
+    };
+
+    if let Some(pattern) = &args.split_at {
+        let base = output_base(&args.output, "--split-at")?;
+        let manifest =
+            split::split_to_files(&mut input, pattern, base).context("failed to split output")?;
+        write_manifest(&manifest, base)?;
+        return finish_outputs(&args, &manifest_files(&manifest, base), recorder.as_ref());
+    }
+
+    if let Some(volume_size) = args.volume_size {
+        let base = output_base(&args.output, "--volume-size")?;
+        if volume_size <= split::VOLUME_HEADER_SIZE {
+            bail!("volume size must be more than {} bytes", split::VOLUME_HEADER_SIZE);
+        }
+        let manifest = split::span_to_files(&mut input, volume_size, base)
+            .context("failed to write output volumes")?;
+        write_manifest(&manifest, base)?;
+        return finish_outputs(&args, &manifest_files(&manifest, base), recorder.as_ref());
+    }
+
+    if let Some(spec) = args.mutate {
+        let base = output_base(&args.output, "--mutate")?;
+        let mut data = Vec::new();
+        input.read_to_end(&mut data).context("failed to read input")?;
+        let paths = write_mutations(&data, spec, range.start, base)?;
+        return finish_outputs(&args, &paths, recorder.as_ref());
+    }
+
+    if args.align == 0 {
+        bail!("alignment must be nonzero");
+    }
 
-    let mut output: Box<dyn Write> = match &args.output {
-        None => open_stdout().context("failed to open stdout")?,
-        Some(p) if p.to_str() == Some("-") => open_stdout().context("failed to open stdout")?,
-        Some(path) => Box::new(File::create(path).context("failed to open output file")?),
+    let mut exec = match &args.exec {
+        Some(cmd) => Some(filter::Exec::spawn(cmd).context("failed to run command")?),
+        None => None,
     };
+    let mut output = match early_output {
+        Some(output) => output,
+        None if exec.is_some() => Box::new(exec.as_mut().unwrap().stdin()),
+        None => {
+            let preallocate = if args.preallocate { output_size(&args, &range) } else { None };
+            open_outputs(&args, &OutputOptions { preallocate, ..OutputOptions::new(&args) })?
+        }
+    };
+
+    let format = args.format.or_else(|| infer_format(&args.output)).unwrap_or_default();
+    // don't spew raw binary into a terminal unless asked to
+    let hexdump = args.hexdump
+        || (!args.raw
+            && args.format.is_none()
+            && is_stdio(&args.output)
+            && args.output_fd.is_none()
+            && args.exec.is_none()
+            && io::stdout().is_terminal());
+
+    // --timing gets the number of bytes copied from the profile too
+    let profile = (args.profile || args.timing).then(Profile::new_shared);
+    if let Some(profile) = &profile {
+        input = Box::new(TimedReader::new(input, profile.clone()));
+        output = Box::new(TimedWriter::new(output, profile.clone()));
+    }
 
-    if args.hexdump {
-        let mut printer = hexyl::PrinterBuilder::new(output).build();
-        printer.print_all(&mut input)?;
+    // bytes written straight from the mapping, which don't show up as reads
+    let mut mapped_bytes = 0;
+    if let Some(step) = args.print_offsets {
+        if step == 0 || args.block_size == Some(0) {
+            bail!("offset step and block size must be nonzero");
+        }
+        // we need to know where the range really ends, so read through it
+        let len = io_copy(&mut input, &mut io::sink())?;
+        print_offsets(&mut output, range.start, len, step, args.block_size)?;
+    } else if let Some(min_len) = args.rle_summary {
+        scan::rle_runs(&mut input, range.start, min_len.max(1), |run| {
+            writeln!(output, "{:#010x} {:#04x} {}", run.offset, run.byte, run.len)
+        })?;
+    } else if !args.find.is_empty() {
+        let patterns = &args.find;
+        scan::find_patterns(&mut input, range.start, patterns, args.align, |offset, i| {
+            if patterns.len() == 1 {
+                writeln!(output, "{:#010x}", offset)
+            } else {
+                writeln!(output, "{:#010x} {}", offset, format::hex_string(&patterns[i]))
+            }
+        })?;
+    } else if let Some(spec) = &args.find_int {
+        scan::find(
+            &mut input,
+            range.start,
+            spec.width,
+            args.align,
+            |w| spec.matches(w),
+            |offset, w| writeln!(output, "{:#010x} {:#x}", offset, spec.decode(w)),
+        )?;
+    } else if hexdump {
+        if args.char_table == CharTable::Caret && args.pretty && !args.chars_only {
+            bail!("--char-table caret isn't supported with --pretty");
+        }
+        let panels = match (args.panels, args.hex_width) {
+            (_, Some(width)) if width == 0 || !width.is_multiple_of(8) => {
+                bail!("hexdump width must be a nonzero multiple of 8")
+            }
+            (_, Some(width)) => width / 8,
+            (Some(panels), None) => panels,
+            (None, None) => hexdump::DEFAULT_PANELS,
+        };
+        let to_terminal =
+            is_stdio(&args.output) && args.output_fd.is_none() && io::stdout().is_terminal();
+        let pager = if args.pager && to_terminal {
+            let (pager, stdin) = pager::Pager::spawn().context("failed to start pager")?;
+            output = Box::new(stdin);
+            Some(pager)
+        } else {
+            None
+        };
+        let opts = HexdumpOptions {
+            color: match args.color {
+                ColorChoice::Always => true,
+                ColorChoice::Never => false,
+                ColorChoice::Auto => to_terminal,
+            },
+            panels,
+            display_offset: if args.relative_offsets { 0 } else { range.start },
+            offset_base: args.offset_base,
+            group_size: args.group_size,
+            upper: args.upper,
+            byte_format: args.byte_format,
+            char_table: args.char_table,
+            pretty: args.pretty,
+            annotations,
+            columns: if args.no_chars {
+                Columns::HexOnly
+            } else if args.chars_only {
+                Columns::CharsOnly
+            } else {
+                Columns::Both
+            },
+        };
+        let other = diff_input.as_mut().map(|o| o as &mut dyn Read);
+        // hexdump takes ownership of the output, leave a placeholder so that it's always closed
+        // in the same place below
+        let output = std::mem::replace(&mut output, Box::new(io::sink()));
+        let result = hexdump::hexdump_diff(&opts, &mut input, other, output);
+        if let Some(pager) = pager {
+            pager.wait().context("failed to wait for pager")?;
+            // quitting the pager early closes the pipe, which isn't an error
+            match result {
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => (),
+                result => result?,
+            }
+        } else {
+            result?;
+        }
+    } else if format == Format::Raw {
+        match &mapped {
+            // nothing can be transforming the data with --mmap, so skip copying it into a buffer
+            Some(map) => {
+                output.write_all(map)?;
+                mapped_bytes = map.len() as u64;
+            }
+            None => {
+                io_copy(&mut input, &mut output)?;
+            }
+        }
     } else {
-        io_copy(&mut input, &mut output)?;
+        let opts = FormatOptions {
+            format,
+            base: args.base,
+            srec_type: args.srec_type,
+            literal_printable: args.literal_printable,
+            input_name: args
+                .input
+                .as_ref()
+                .filter(|p| p.to_str() != Some("-"))
+                .map(|p| p.display().to_string()),
+            start: range.start,
+            data_encoding: args.json_encoding,
+            upper: args.upper,
+        };
+        format::write_formatted(&opts, &mut input, &mut output)?;
+    }
+
+    // finish writing (and compressing) the output before looking at it
+    output.flush()?;
+    drop(output);
+    let paths: Vec<PathBuf> =
+        args.output.iter().chain(&args.tee).filter(|p| p.to_str() != Some("-")).cloned().collect();
+    finish_outputs(&args, &paths, recorder.as_ref())?;
+    sync_stdio(&args)?;
+
+    if let Some(profile) = profile {
+        let profile = profile.borrow();
+        if args.profile {
+            profile.report(start_time.elapsed());
+        }
+        let method = if mapped.is_some() { CopyMethod::Mmap } else { CopyMethod::Buffered };
+        report_timing(&args, profile.reads.bytes() + mapped_bytes, start_time, method);
+    }
+
+    if let Some(exec) = exec {
+        let status = exec.wait().context("failed to wait for command")?;
+        if !status.success() {
+            // a command killed by a signal has no exit code, report it like the shell would
+            #[cfg(unix)]
+            let code = std::os::unix::process::ExitStatusExt::signal(&status).map(|sig| 128 + sig);
+            #[cfg(not(unix))]
+            let code = None;
+            return Err(ExitCode(status.code().or(code).unwrap_or(1)).into());
+        }
     }
 
     Ok(())
 }
 
+/// Error for exiting with a particular status and no message, like passing on the status of an
+/// --exec command
+#[derive(Debug)]
+struct ExitCode(i32);
+
+impl std::fmt::Display for ExitCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "exit status {}", self.0)
+    }
+}
+
+impl std::error::Error for ExitCode {}
+
+/// Whether an error came from writing to a pipe whose reader has gone away
+fn is_broken_pipe(err: &anyhow::Error) -> bool {
+    err.chain().any(|e| {
+        // some module errors wrap io::Error transparently, which hides it from the chain
+        let io_err = match (e.downcast_ref::<join::JoinError>(), e.downcast_ref::<fs::FsError>()) {
+            (Some(join::JoinError::Io(e)), _) | (_, Some(fs::FsError::Io(e))) => Some(e),
+            _ => e.downcast_ref::<io::Error>(),
+        };
+        io_err.is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+    })
+}
+
 fn main() {
     if let Err(err) = run() {
+        // the reader stopped early, e.g. `bcut - big | head -c 100`, which isn't worth an error
+        // message. Exit with the status the shell would show if SIGPIPE had killed us.
+        if is_broken_pipe(&err) {
+            std::process::exit(if cfg!(unix) { 128 + 13 } else { 1 });
+        }
+        if let Some(ExitCode(code)) = err.downcast_ref() {
+            std::process::exit(*code);
+        }
         eprintln!("Error: {:#}", err);
         std::process::exit(1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    #[test]
+    fn output_format() {
+        let infer = |path: &str| infer_format(&Some(PathBuf::from(path)));
+        assert_eq!(infer("fw.hex"), Some(Format::Ihex));
+        assert_eq!(infer("out/fw.s37.gz"), Some(Format::Srec));
+        assert_eq!(infer("fw.b64.zst"), Some(Format::Base64));
+        assert_eq!(infer("fw.bin.gz"), None);
+        assert_eq!(infer("fw.gz"), None);
+        assert_eq!(infer("-"), None);
+    }
+
+    #[test]
+    fn broken_pipe() {
+        let err = || io::Error::from(io::ErrorKind::BrokenPipe);
+        assert!(is_broken_pipe(&anyhow::Error::new(err()).context("failed to write output")));
+        assert!(is_broken_pipe(&anyhow::Error::new(join::JoinError::Io(err()))));
+        let other = io::Error::from(io::ErrorKind::NotFound);
+        assert!(!is_broken_pipe(&anyhow::Error::new(other)));
+    }
+
+    #[test]
+    fn in_place_conflicts() {
+        let parse = |extra: &[&str]| {
+            let args = ["bcut", "--in-place"].iter().chain(extra).chain(&["0x200-", "file"]);
+            Args::try_parse_from(args).map_err(|e| e.kind())
+        };
+        assert!(parse(&[]).is_ok());
+        for extra in [
+            &["--find", "deadbeef"][..],
+            &["--rle-summary", "16"],
+            &["--print-offsets", "16"],
+            &["--filter", "cat"],
+            &["--max-input", "1M"],
+            &["--control-socket", "sock"],
+        ] {
+            assert_eq!(
+                parse(extra).unwrap_err(),
+                clap::error::ErrorKind::ArgumentConflict,
+                "{:?}",
+                extra
+            );
+        }
+    }
+
+    #[test]
+    fn sandbox_conflicts() {
+        let parse = |extra: &[&str]| {
+            let args = ["bcut", "--sandbox"].iter().chain(extra).chain(&["0x200-", "file"]);
+            Args::try_parse_from(args).map_err(|e| e.kind())
+        };
+        assert!(parse(&[]).is_ok());
+        assert!(parse(&["--annotate", "notes", "--hexdump"]).is_ok());
+        for extra in [&["--metadata", "meta.json"][..], &["--repro"], &["--sync"], &["--pager"]] {
+            assert_eq!(
+                parse(extra).unwrap_err(),
+                clap::error::ErrorKind::ArgumentConflict,
+                "{:?}",
+                extra
+            );
+        }
+    }
+
+    #[test]
+    fn relative_range_size() {
+        let path = TempPath::new("input-size");
+        std::fs::write(&path, [0; 100]).unwrap();
+        let parse = |extra: &[&str]| {
+            let path = path.to_str().unwrap();
+            Args::try_parse_from(["bcut"].iter().chain(extra).chain(&["^16-", path])).unwrap()
+        };
+        assert_eq!(input_size(&parse(&[])).unwrap(), 100);
+        // the size of a compressed file isn't the size of the data the range applies to
+        assert!(input_size(&parse(&["--decompress"])).is_err());
+        let stdin = Args::try_parse_from(["bcut", "^16-"]).unwrap();
+        assert!(input_size(&stdin).is_err());
+    }
+
+    #[test]
+    fn seek_overwrites() {
+        let path = TempPath::new("seek");
+        std::fs::write(&path, b"0123456789").unwrap();
+        let opts = OutputOptions { seek: Some(4), ..Default::default() };
+        opts.open(&path).unwrap().write_all(b"ab").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"0123ab6789");
+        // writing past the end extends the file
+        let opts = OutputOptions { seek: Some(9), ..Default::default() };
+        opts.open(&path).unwrap().write_all(b"xyz").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"0123ab678xyz");
+    }
+
+    #[test]
+    fn seek_sparse_input() {
+        let input = TempPath::new("seek-sparse-in");
+        let output = TempPath::new("seek-sparse-out");
+        // data, then a hole up to 1M, then more data
+        let mut file = File::create(&input).unwrap();
+        file.write_all(&[0xaa; 4096]).unwrap();
+        file.seek(SeekFrom::Start(1 << 20)).unwrap();
+        file.write_all(&[0xbb; 4096]).unwrap();
+        drop(file);
+        std::fs::write(&output, vec![b'x'; 2 << 20]).unwrap();
+
+        let (input_arg, output_arg) = (input.to_str().unwrap(), output.to_str().unwrap());
+        let args = ["bcut", "-o", output_arg, "--seek", "16", "0+0x101000", input_arg];
+        run_args(Args::parse_from(args)).unwrap();
+        // the hole overwrites what was there before, like dd conv=notrunc
+        let (out, data) = (std::fs::read(&output).unwrap(), std::fs::read(&input).unwrap());
+        let end = 16 + data.len();
+        assert_eq!(out.len(), 2 << 20);
+        assert!(out[..16].iter().chain(&out[end..]).all(|&b| b == b'x'));
+        assert!(out[16..end] == data);
+    }
+}
@@ -0,0 +1,390 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Write};
+
+use sha2::{Digest, Sha256};
+
+use crate::format::hex_string;
+use crate::range::{parse_byte, parse_number};
+use crate::records::decode_hex;
+use crate::transform::Transform;
+
+/// A SHA-256 digest
+pub type Hash = [u8; 32];
+
+/// First line of every manifest file
+const MAGIC: &str = "bcut-manifest 1";
+
+/// Byte used to fill gaps between chunks when the manifest doesn't say otherwise
+pub const DEFAULT_FILL: u8 = 0xff;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("manifest line {line}: {reason}")]
+pub struct ManifestError {
+    line: usize,
+    reason: &'static str,
+}
+
+/// One file of a split or spanned set, or one region of an assembled image
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// file name, relative to the directory containing the manifest
+    pub name: String,
+    /// number of bytes in the region, if known. This is after transforms and padding, and doesn't
+    /// count the volume header.
+    pub size: Option<u64>,
+    /// hash of the region, if known
+    pub sha256: Option<Hash>,
+    /// offset of the region in the output, None to place it right after the previous one
+    pub offset: Option<u64>,
+    /// transforms applied in order to the file's data
+    pub transforms: Vec<Transform>,
+    /// pad the region with fill bytes up to this size
+    pub pad: Option<u64>,
+    /// byte to pad with, overriding the manifest's fill byte
+    pub fill: Option<u8>,
+}
+
+/// Description of a set of files which can be put together to reproduce some data. This is either
+/// the pieces of a split or spanned output which are simply concatenated, or a hand-written
+/// description of an image with regions at specific offsets which may need to be transformed.
+///
+/// The text format is line based, beginning with "bcut-manifest 1". The other lines are:
+///   header-size N   size of the volume header at the start of each file
+///   fill BYTE       byte used for gaps between regions and padding, 0xff by default
+///   chunk NAME SIZE SHA256 [ATTR...]
+///                   a file and the size and hash of its region in the output, either of which
+///                   may be "-" if unknown. NAME is in double quotes if it has spaces or other
+///                   special characters, with \", \\, and \n escapes inside them. ATTR can be
+///                   at=OFFSET, pad=SIZE, fill=BYTE, or a transform xor=HEXKEY, swap=2|4|8, or
+///                   inflate=deflate|zlib|gzip. Transforms are applied in the order given.
+///   total SIZE SHA256
+///                   size and hash of the whole output, optional and must be last
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    /// size of the volume header at the start of each file, 0 if there isn't one
+    pub header_size: u64,
+    /// byte used to fill gaps between regions
+    pub fill: u8,
+    pub chunks: Vec<Chunk>,
+    /// total size of all the data
+    pub size: Option<u64>,
+    /// hash of all the data
+    pub sha256: Option<Hash>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self { header_size: 0, fill: DEFAULT_FILL, chunks: Vec::new(), size: None, sha256: None }
+    }
+}
+
+/// Hasher which tracks a chunk and the whole set at once
+pub struct ManifestBuilder {
+    manifest: Manifest,
+    chunk: Option<(Chunk, Sha256)>,
+    total: Sha256,
+}
+
+impl ManifestBuilder {
+    pub fn new(header_size: u64) -> Self {
+        Self {
+            manifest: Manifest { header_size, ..Default::default() },
+            chunk: None,
+            total: Sha256::new(),
+        }
+    }
+
+    /// Start a new chunk, finishing the current one if any
+    pub fn start_chunk(&mut self, name: String) {
+        self.finish_chunk();
+        let chunk = Chunk { name, size: Some(0), ..Default::default() };
+        self.chunk = Some((chunk, Sha256::new()));
+    }
+
+    /// Add data to the current chunk
+    pub fn update(&mut self, data: &[u8]) {
+        let (chunk, hasher) = self.chunk.as_mut().expect("no chunk started");
+        *chunk.size.as_mut().unwrap() += data.len() as u64;
+        hasher.update(data);
+        *self.manifest.size.get_or_insert(0) += data.len() as u64;
+        self.total.update(data);
+    }
+
+    fn finish_chunk(&mut self) {
+        if let Some((mut chunk, hasher)) = self.chunk.take() {
+            chunk.sha256 = Some(hasher.finalize().into());
+            self.manifest.chunks.push(chunk);
+        }
+    }
+
+    pub fn finish(mut self) -> Manifest {
+        self.finish_chunk();
+        self.manifest.size.get_or_insert(0);
+        self.manifest.sha256 = Some(self.total.finalize().into());
+        self.manifest
+    }
+}
+
+/// Format an optional size or hash, "-" if it's unknown
+fn optional<T: fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "-".to_owned(), |v| v.to_string())
+}
+
+/// Quote a chunk name if it has characters which would otherwise split it or make it look like a
+/// comment
+fn quote_name(name: &str) -> Cow<'_, str> {
+    let plain = !name.is_empty()
+        && !name.starts_with(['#', '"'])
+        && !name.contains(|c: char| c.is_whitespace() || c == '\\');
+    if plain {
+        return Cow::Borrowed(name);
+    }
+    let mut quoted = String::with_capacity(name.len() + 2);
+    quoted.push('"');
+    for c in name.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    Cow::Owned(quoted)
+}
+
+/// Split a line into whitespace separated fields, where a field in double quotes can contain
+/// anything (see quote_name). Returns None for an unterminated quote or a bad escape.
+fn split_fields(line: &str) -> Option<Vec<Cow<'_, str>>> {
+    let mut fields = Vec::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        let Some(quoted) = rest.strip_prefix('"') else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            fields.push(Cow::Borrowed(&rest[..end]));
+            rest = rest[end..].trim_start();
+            continue;
+        };
+        let mut field = String::new();
+        let mut chars = quoted.char_indices();
+        let end = loop {
+            match chars.next()? {
+                (i, '"') => break i + 1,
+                (_, '\\') => match chars.next()?.1 {
+                    'n' => field.push('\n'),
+                    c @ ('"' | '\\') => field.push(c),
+                    _ => return None,
+                },
+                (_, c) => field.push(c),
+            }
+        };
+        rest = &quoted[end..];
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        fields.push(Cow::Owned(field));
+        rest = rest.trim_start();
+    }
+    Some(fields)
+}
+
+impl Manifest {
+    /// Write the manifest in its text format
+    pub fn write(&self, output: &mut dyn Write) -> io::Result<()> {
+        writeln!(output, "{}", MAGIC)?;
+        if self.header_size != 0 {
+            writeln!(output, "header-size {}", self.header_size)?;
+        }
+        if self.fill != DEFAULT_FILL {
+            writeln!(output, "fill {:#04x}", self.fill)?;
+        }
+        for chunk in &self.chunks {
+            write!(
+                output,
+                "chunk {} {} {}",
+                quote_name(&chunk.name),
+                optional(chunk.size),
+                optional(chunk.sha256.map(|h| hex_string(&h)))
+            )?;
+            if let Some(offset) = chunk.offset {
+                write!(output, " at={:#x}", offset)?;
+            }
+            for transform in &chunk.transforms {
+                write!(output, " {}", transform)?;
+            }
+            if let Some(pad) = chunk.pad {
+                write!(output, " pad={:#x}", pad)?;
+            }
+            if let Some(fill) = chunk.fill {
+                write!(output, " fill={:#04x}", fill)?;
+            }
+            writeln!(output)?;
+        }
+        if self.size.is_some() || self.sha256.is_some() {
+            writeln!(
+                output,
+                "total {} {}",
+                optional(self.size),
+                optional(self.sha256.map(|h| hex_string(&h)))
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Parse the text format written by Manifest::write
+    pub fn parse(text: &str) -> Result<Self, ManifestError> {
+        let mut manifest = Self::default();
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, l)| l.trim()) != Some(MAGIC) {
+            return Err(ManifestError { line: 1, reason: "not a bcut manifest" });
+        }
+
+        let mut total = false;
+        for (i, line) in lines {
+            let err = |reason| ManifestError { line: i + 1, reason };
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+            let fields = split_fields(line).ok_or_else(|| err("invalid quoted name"))?;
+            let fields: Vec<&str> = fields.iter().map(|f| f.as_ref()).collect();
+            let number = |s: &str| parse_number(s).map_err(|_| err("invalid number"));
+            let byte = |s: &str| parse_byte(s).map_err(|_| err("invalid fill byte"));
+            let size = |s: &str| if s == "-" { Ok(None) } else { number(s).map(Some) };
+            let hash = |s: &str| match s {
+                "-" => Ok(None),
+                _ => decode_hex(s)
+                    .and_then(|h| h.try_into().ok())
+                    .map(Some)
+                    .ok_or_else(|| err("invalid hash")),
+            };
+            match fields[..] {
+                [] => continue,
+                _ if total => return Err(err("data after total")),
+                ["header-size", n] => manifest.header_size = number(n)?,
+                ["fill", b] => manifest.fill = byte(b)?,
+                ["chunk", name, n, h, ref attrs @ ..] => {
+                    let mut chunk = Chunk {
+                        name: name.to_owned(),
+                        size: size(n)?,
+                        sha256: hash(h)?,
+                        ..Default::default()
+                    };
+                    for attr in attrs {
+                        let (key, value) =
+                            attr.split_once('=').ok_or_else(|| err("invalid attribute"))?;
+                        match key {
+                            "at" => chunk.offset = Some(number(value)?),
+                            "pad" => chunk.pad = Some(number(value)?),
+                            "fill" => chunk.fill = Some(byte(value)?),
+                            _ => chunk.transforms.push(Transform::parse(key, value).map_err(err)?),
+                        }
+                    }
+                    manifest.chunks.push(chunk);
+                }
+                ["total", n, h] => {
+                    manifest.size = size(n)?;
+                    manifest.sha256 = hash(h)?;
+                    total = true;
+                }
+                _ => return Err(err("unrecognized line")),
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let mut builder = ManifestBuilder::new(24);
+        builder.start_chunk("out.000".into());
+        builder.update(b"ab");
+        builder.update(b"c");
+        builder.start_chunk("out.001".into());
+        builder.update(b"");
+        let manifest = builder.finish();
+
+        assert_eq!(manifest.size, Some(3));
+        assert_eq!(manifest.chunks.len(), 2);
+        assert_eq!(manifest.chunks[0].sha256, manifest.sha256);
+        assert_eq!(
+            hex_string(&manifest.sha256.unwrap()),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let mut text = Vec::new();
+        manifest.write(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.starts_with("bcut-manifest 1\nheader-size 24\nchunk out.000 3 ba7816bf"));
+        assert_eq!(Manifest::parse(&text), Ok(manifest));
+    }
+
+    #[test]
+    fn quoted_names() {
+        let mut manifest = Manifest::default();
+        for name in ["my out.bin.000", "#hash", "a\"b\\c\nd", "plain.bin"] {
+            manifest.chunks.push(Chunk { name: name.into(), ..Default::default() });
+        }
+        let mut text = Vec::new();
+        manifest.write(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("chunk \"my out.bin.000\" - -\n"));
+        assert!(text.contains("chunk \"#hash\" - -\n"));
+        assert!(text.contains("chunk \"a\\\"b\\\\c\\nd\" - -\n"));
+        assert!(text.contains("chunk plain.bin - -\n"));
+        assert_eq!(Manifest::parse(&text), Ok(manifest));
+
+        let err = |text| Manifest::parse(text).unwrap_err().to_string();
+        assert_eq!(err("bcut-manifest 1\nchunk \"a - -\n"), "manifest line 2: invalid quoted name");
+        assert_eq!(
+            err("bcut-manifest 1\nchunk \"a\"b - -\n"),
+            "manifest line 2: invalid quoted name"
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |text| Manifest::parse(text).unwrap_err().to_string();
+        assert_eq!(err("hello\n"), "manifest line 1: not a bcut manifest");
+        assert_eq!(err("bcut-manifest 1\nchunk a 1\n"), "manifest line 2: unrecognized line");
+        assert_eq!(err("bcut-manifest 1\nchunk a x 00\n"), "manifest line 2: invalid number");
+        assert_eq!(err("bcut-manifest 1\nchunk a 1 00\n"), "manifest line 2: invalid hash");
+        assert_eq!(
+            err("bcut-manifest 1\nchunk a 1 - swap=3\n"),
+            "manifest line 2: swap width must be 2, 4, or 8"
+        );
+        assert_eq!(
+            err("bcut-manifest 1\ntotal - -\nfill 0\n"),
+            "manifest line 3: data after total"
+        );
+    }
+
+    #[test]
+    fn parse_regions() {
+        let text = "bcut-manifest 1\n\
+                    # a hand-written image description\n\
+                    fill 0\n\
+                    chunk boot.bin - - pad=0x100\n\
+                    chunk kernel.z - - at=0x1000 inflate=zlib xor=5a fill=0xff\n";
+        let manifest = Manifest::parse(text).unwrap();
+        assert_eq!(manifest.fill, 0);
+        assert_eq!(manifest.size, None);
+        assert_eq!(manifest.chunks[0].pad, Some(0x100));
+        let kernel = &manifest.chunks[1];
+        assert_eq!(kernel.offset, Some(0x1000));
+        assert_eq!(kernel.fill, Some(0xff));
+        assert_eq!(kernel.transforms.len(), 2);
+
+        let mut out = Vec::new();
+        manifest.write(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.ends_with("chunk kernel.z - - at=0x1000 inflate=zlib xor=5a fill=0xff\n"));
+        assert_eq!(Manifest::parse(&out).unwrap(), manifest);
+    }
+}
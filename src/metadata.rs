@@ -0,0 +1,94 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+use crate::format::{hex_string, json_string};
+
+/// The hash and length of the bytes read so far
+struct Extracted {
+    hasher: Sha256,
+    len: u64,
+}
+
+/// Records what was extracted for a --metadata sidecar file
+pub struct Recorder {
+    input: Option<String>,
+    start: u64,
+    extracted: Arc<Mutex<Extracted>>,
+}
+
+impl Recorder {
+    /// Start recording an extraction from input (None for stdin) at offset start
+    pub fn new(input: Option<String>, start: u64) -> Self {
+        let extracted = Extracted { hasher: Sha256::new(), len: 0 };
+        Self { input, start, extracted: Arc::new(Mutex::new(extracted)) }
+    }
+
+    /// Wrap the input so that everything read from it is recorded
+    pub fn wrap<R: Read>(&self, inner: R) -> RecordingReader<R> {
+        RecordingReader { inner, extracted: self.extracted.clone() }
+    }
+
+    /// Write the sidecar file as a single JSON object, recording time as when the extraction
+    /// happened
+    pub fn write(&self, path: &Path, time: SystemTime) -> io::Result<()> {
+        let extracted = self.extracted.lock().unwrap();
+        let time = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let input = self.input.as_deref().map_or_else(|| "null".into(), json_string);
+        let json = format!(
+            r#"{{"input":{},"start":{},"length":{},"sha256":"{}","time":{}}}"#,
+            input,
+            self.start,
+            extracted.len,
+            hex_string(&extracted.hasher.clone().finalize()),
+            time.as_secs(),
+        );
+        fs::write(path, json + "\n")
+    }
+}
+
+/// Reader which records the hash and length of everything read through it
+pub struct RecordingReader<R> {
+    inner: R,
+    extracted: Arc<Mutex<Extracted>>,
+}
+
+impl<R: Read> Read for RecordingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        let mut extracted = self.extracted.lock().unwrap();
+        extracted.hasher.update(&buf[..count]);
+        extracted.len += count as u64;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    #[test]
+    fn sidecar() {
+        let path = TempPath::new("metadata");
+        let recorder = Recorder::new(Some("in \"1\".bin".into()), 0x10);
+        let mut out = Vec::new();
+        recorder.wrap(&b"abc"[..]).read_to_end(&mut out).unwrap();
+        recorder.write(&path, UNIX_EPOCH + std::time::Duration::from_secs(1234)).unwrap();
+
+        let json = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"input":"in \"1\".bin","start":16,"length":3,"#,
+                r#""sha256":"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad","#,
+                r#""time":1234}"#,
+                "\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+
+use clap::ValueEnum;
+
+use crate::range::Range;
+
+/// How to advise the kernel about the pages of a mapping
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Madvise {
+    /// Start reading the range in right away and read ahead aggressively
+    #[default]
+    Sequential,
+    /// Start reading the range in right away, with normal read ahead after that
+    Willneed,
+    /// Don't read ahead at all
+    Random,
+    /// Don't give any advice, so the pages aren't dropped after the copy either
+    Normal,
+}
+
+/// A read-only memory mapping of the range of a file
+pub struct Mapped {
+    #[cfg(unix)]
+    ptr: *mut std::ffi::c_void,
+    /// length of the whole mapping, which starts at a page boundary before the range
+    #[cfg(unix)]
+    map_len: usize,
+    /// offset of the range in the mapping
+    #[cfg(unix)]
+    skip: usize,
+    /// length of the range in the mapping
+    #[cfg(unix)]
+    len: usize,
+    #[cfg(unix)]
+    advice: Madvise,
+}
+
+// SAFETY: the mapping is read-only and owned by this struct, so it can be shared between threads
+unsafe impl Send for Mapped {}
+unsafe impl Sync for Mapped {}
+
+impl Mapped {
+    /// Map the range of a regular file, advising the kernel how it'll be read. The range is
+    /// clamped to the file's size, like a read would be.
+    #[cfg(unix)]
+    pub fn open(path: &Path, range: &Range, advice: Madvise) -> io::Result<Self> {
+        use rustix::mm::{madvise, mmap, Advice, MapFlags, ProtFlags};
+
+        let file = File::open(path)?;
+        let meta = file.metadata()?;
+        if !meta.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only regular files can be memory-mapped",
+            ));
+        }
+        let size = meta.len();
+        let start = range.start.min(size);
+        let end = match range.count {
+            Some(count) => start.saturating_add(count).min(size),
+            None => size,
+        };
+        let len: usize = (end - start)
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "range is too big to map"))?;
+        if len == 0 {
+            return Ok(Self { ptr: std::ptr::null_mut(), map_len: 0, skip: 0, len: 0, advice });
+        }
+
+        // mappings have to start on a page boundary
+        let page_size = rustix::param::page_size() as u64;
+        let map_start = start - start % page_size;
+        let skip = (start - map_start) as usize;
+        let map_len = skip + len;
+        // SAFETY: this is a new private read-only mapping, not overlapping any existing memory.
+        // If the file is truncated while mapped, reading past its new end raises SIGBUS, which is
+        // the usual caveat of mmap.
+        let ptr = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                map_len,
+                ProtFlags::READ,
+                MapFlags::PRIVATE,
+                &file,
+                map_start,
+            )?
+        };
+
+        // advice is only an optimization, so it doesn't matter if the kernel ignores it
+        let advise = |advice| {
+            // SAFETY: this is the whole of the mapping we just made
+            let _ = unsafe { madvise(ptr, map_len, advice) };
+        };
+        match advice {
+            Madvise::Sequential => {
+                advise(Advice::Sequential);
+                advise(Advice::WillNeed);
+            }
+            Madvise::Willneed => advise(Advice::WillNeed),
+            Madvise::Random => advise(Advice::Random),
+            Madvise::Normal => (),
+        }
+        Ok(Self { ptr, map_len, skip, len, advice })
+    }
+
+    #[cfg(not(unix))]
+    pub fn open(_path: &Path, _range: &Range, _advice: Madvise) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "--mmap isn't supported on this platform"))
+    }
+}
+
+impl Deref for Mapped {
+    type Target = [u8];
+
+    #[cfg(unix)]
+    fn deref(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // SAFETY: the mapping is valid for map_len bytes until we're dropped
+        unsafe { std::slice::from_raw_parts(self.ptr.cast::<u8>().add(self.skip), self.len) }
+    }
+
+    #[cfg(not(unix))]
+    fn deref(&self) -> &[u8] {
+        &[]
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mapped {
+    fn drop(&mut self) {
+        if self.map_len != 0 {
+            // SAFETY: the mapping came from mmap with this length, and nothing borrows it anymore
+            unsafe {
+                // the pages have been copied, so let them go rather than leaving them to push
+                // other things out of memory. POSIX_MADV_DONTNEED does nothing on Linux.
+                if self.advice != Madvise::Normal {
+                    #[cfg(any(target_os = "linux", target_os = "android"))]
+                    let dont_need = rustix::mm::Advice::LinuxDontNeed;
+                    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+                    let dont_need = rustix::mm::Advice::DontNeed;
+                    let _ = rustix::mm::madvise(self.ptr, self.map_len, dont_need);
+                }
+                let _ = rustix::mm::munmap(self.ptr, self.map_len);
+            }
+        }
+    }
+}
+
+/// Reader which copies out of a shared mapping
+pub struct MappedReader {
+    map: Arc<Mapped>,
+    pos: usize,
+}
+
+impl MappedReader {
+    pub fn new(map: Arc<Mapped>) -> Self {
+        Self { map, pos: 0 }
+    }
+}
+
+impl Read for MappedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut rest = &self.map[self.pos..];
+        let count = rest.read(buf)?;
+        self.pos += count;
+        Ok(count)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    #[test]
+    fn map_range() {
+        let path = TempPath::new("mmap");
+        let data: Vec<u8> = (0..10000u32).map(|i| i as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let open = |start, count, advice| Mapped::open(&path, &Range { start, count }, advice);
+        let map = open(5000, Some(100), Madvise::default()).unwrap();
+        assert_eq!(&map[..], &data[5000..5100]);
+        let mut out = Vec::new();
+        MappedReader::new(Arc::new(map)).read_to_end(&mut out).unwrap();
+        assert_eq!(out, &data[5000..5100]);
+
+        assert_eq!(&open(9990, None, Madvise::Random).unwrap()[..], &data[9990..]);
+        assert!(open(20000, None, Madvise::Normal).unwrap().is_empty());
+    }
+}
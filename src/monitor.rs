@@ -0,0 +1,126 @@
+use std::io::{self, Read, Write};
+use std::ops::Range as IndexRange;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::input::{prepare_input, InputOptions};
+use crate::range::Range;
+
+/// Maximum number of changed bytes shown on one line
+const BYTES_PER_LINE: usize = 16;
+
+/// Find the runs of indices where old and new differ, including bytes present in only one of them
+/// because the input grew or shrank.
+pub fn changed_runs(old: &[u8], new: &[u8]) -> Vec<IndexRange<usize>> {
+    let len = old.len().max(new.len());
+    let mut runs = Vec::new();
+    let mut run_start = None;
+    for i in 0..=len {
+        let changed = i < len && old.get(i) != new.get(i);
+        match (changed, run_start) {
+            (true, None) => run_start = Some(i),
+            (false, Some(start)) => {
+                runs.push(start..i);
+                run_start = None;
+            }
+            _ => (),
+        }
+    }
+    runs
+}
+
+/// Write a byte as two hex digits, or "--" if it doesn't exist
+fn write_byte(output: &mut dyn Write, byte: Option<&u8>) -> io::Result<()> {
+    match byte {
+        Some(b) => write!(output, " {:02x}", b),
+        None => write!(output, " --"),
+    }
+}
+
+/// Print the bytes which changed between old and new, one line per run of at most
+/// BYTES_PER_LINE bytes, tagged with the elapsed time and absolute offset.
+pub fn write_changes(
+    output: &mut dyn Write,
+    elapsed: Duration,
+    start: u64,
+    old: &[u8],
+    new: &[u8],
+) -> io::Result<()> {
+    for run in changed_runs(old, new) {
+        for line_start in run.clone().step_by(BYTES_PER_LINE) {
+            let line = line_start..(line_start + BYTES_PER_LINE).min(run.end);
+            write!(
+                output,
+                "[{:5}.{:03}] {:#010x}:",
+                elapsed.as_secs(),
+                elapsed.subsec_millis(),
+                start + line.start as u64
+            )?;
+            for i in line.clone() {
+                write_byte(output, old.get(i))?;
+            }
+            write!(output, " ->")?;
+            for i in line {
+                write_byte(output, new.get(i))?;
+            }
+            writeln!(output)?;
+        }
+    }
+    Ok(())
+}
+
+/// Re-read the range of the input file every interval and print the bytes which changed since
+/// the previous poll. The first poll prints the whole range. Runs until an error occurs.
+pub fn monitor(
+    path: &Option<PathBuf>,
+    range: &Range,
+    interval: Duration,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    let start_time = Instant::now();
+    let mut old = Vec::new();
+    loop {
+        let mut new = Vec::new();
+        prepare_input(path, range, &InputOptions::default())?.read_to_end(&mut new)?;
+        write_changes(output, start_time.elapsed(), range.start, &old, &new)?;
+        output.flush()?;
+        old = new;
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs() {
+        assert_eq!(changed_runs(b"abcd", b"abcd"), vec![]);
+        assert_eq!(changed_runs(b"abcd", b"xbcy"), vec![0..1, 3..4]);
+        assert_eq!(changed_runs(b"abcd", b"aXYd"), vec![1..3]);
+        assert_eq!(changed_runs(b"ab", b"abcd"), vec![2..4]);
+        assert_eq!(changed_runs(b"abcd", b"aX"), vec![1..4]);
+        assert_eq!(changed_runs(b"", b""), vec![]);
+    }
+
+    #[test]
+    fn changes() {
+        let mut out = Vec::new();
+        let old = [0u8; 20];
+        let mut new = [0u8; 20];
+        new[2] = 0x12;
+        new[3] = 0x34;
+        write_changes(&mut out, Duration::from_millis(1250), 0x100, &old, &new).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "[    1.250] 0x00000102: 00 00 -> 12 34\n");
+
+        // long runs are split, missing bytes are shown as --
+        let mut out = Vec::new();
+        write_changes(&mut out, Duration::ZERO, 0, b"", &new[..18]).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("[    0.000] 0x00000000: -- -- "));
+        assert_eq!(lines[1], "[    0.000] 0x00000010: -- -- -> 00 00");
+    }
+}
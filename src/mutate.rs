@@ -0,0 +1,138 @@
+use std::fmt;
+
+/// Settings for --mutate, parsed from "N:SEED"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MutateSpec {
+    /// number of samples to generate
+    pub count: u32,
+    pub seed: u64,
+}
+
+/// Parse a --mutate argument, a sample count and a seed separated by ':', e.g. "100:42"
+pub fn parse_spec(input: &str) -> Result<MutateSpec, String> {
+    let (count, seed) = input.split_once(':').ok_or("expected N:SEED")?;
+    let count = crate::range::parse_number(count).map_err(|e| e.to_string())?;
+    let seed = crate::range::parse_number(seed).map_err(|e| e.to_string())?;
+    match u32::try_from(count) {
+        Ok(count) if count > 0 => Ok(MutateSpec { count, seed }),
+        _ => Err("sample count must be between 1 and 2^32-1".into()),
+    }
+}
+
+/// A small deterministic PRNG (splitmix64). We don't need good randomness, but we do need the
+/// same seed to always give the same samples, on every platform and bcut version.
+#[derive(Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// Skip ahead n numbers in the sequence, which splitmix can do in constant time
+    pub fn skip(&mut self, n: u64) {
+        self.0 = self.0.wrapping_add(n.wrapping_mul(Self::GAMMA));
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(Self::GAMMA);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in [0, n), n must be nonzero
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
+
+/// One change made to produce a sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mutation {
+    /// the sample is only len bytes of the range starting at start, relative to the range
+    Slice { start: u64, len: u64 },
+    /// the byte at offset in the sample was XORed with mask
+    Flip { offset: u64, mask: u8 },
+}
+
+/// Format a mutation for the log, e.g. "slice 0x10+0x20" or "flip 0x4^0x80"
+impl fmt::Display for Mutation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Slice { start, len } => write!(f, "slice {:#x}+{:#x}", start, len),
+            Self::Flip { offset, mask } => write!(f, "flip {:#x}^{:#04x}", offset, mask),
+        }
+    }
+}
+
+/// Most bytes flipped in one sample
+const MAX_FLIPS: u64 = 16;
+
+/// Make one randomized sample of data: half the time a random subrange of it, and then a few
+/// random bytes flipped. Returns the sample and what was done to it.
+pub fn sample(data: &[u8], rng: &mut Rng) -> (Vec<u8>, Vec<Mutation>) {
+    let mut mutations = Vec::new();
+    let len = data.len() as u64;
+    let mut out = if len > 1 && rng.below(2) == 0 {
+        let start = rng.below(len);
+        let count = 1 + rng.below(len - start);
+        mutations.push(Mutation::Slice { start, len: count });
+        data[start as usize..(start + count) as usize].to_vec()
+    } else {
+        data.to_vec()
+    };
+
+    if !out.is_empty() {
+        // about one flip per 64 bytes, but at least one and not too many
+        let flips = 1 + rng.below((out.len() as u64 / 64).clamp(1, MAX_FLIPS));
+        for _ in 0..flips {
+            let offset = rng.below(out.len() as u64);
+            let mask = 1 + rng.below(255) as u8;
+            out[offset as usize] ^= mask;
+            mutations.push(Mutation::Flip { offset, mask });
+        }
+    }
+    (out, mutations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec() {
+        assert_eq!(parse_spec("10:0x2a"), Ok(MutateSpec { count: 10, seed: 42 }));
+        assert!(parse_spec("10").is_err());
+        assert!(parse_spec("0:1").is_err());
+    }
+
+    #[test]
+    fn reproducible() {
+        let data: Vec<u8> = (0..=255).collect();
+        let samples = |seed| {
+            let mut rng = Rng::new(seed);
+            (0..20).map(|_| sample(&data, &mut rng)).collect::<Vec<_>>()
+        };
+        let a = samples(1);
+        assert_eq!(a, samples(1));
+        assert_ne!(a, samples(2));
+
+        // replaying the log gives the same sample
+        for (out, mutations) in a {
+            let mut replay = data.clone();
+            for m in mutations {
+                match m {
+                    Mutation::Slice { start, len } => {
+                        replay = replay[start as usize..(start + len) as usize].to_vec()
+                    }
+                    Mutation::Flip { offset, mask } => replay[offset as usize] ^= mask,
+                }
+            }
+            assert_eq!(out, replay);
+        }
+    }
+}
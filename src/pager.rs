@@ -0,0 +1,32 @@
+use std::io;
+use std::process::{Child, ChildStdin, Stdio};
+
+use crate::filter::shell_command;
+
+/// A pager like less which output can be written into, for reading long hexdumps in a terminal
+pub struct Pager {
+    child: Child,
+}
+
+impl Pager {
+    /// Start $PAGER, or less if it's not set, and get the pipe to write into it.
+    ///
+    /// Like git, LESS defaults to "FRX" so that less passes colors through and exits right away
+    /// if everything fits on one screen.
+    pub fn spawn() -> io::Result<(Self, ChildStdin)> {
+        let cmd = std::env::var("PAGER").ok().filter(|p| !p.is_empty());
+        let mut command = shell_command(cmd.as_deref().unwrap_or("less"));
+        if std::env::var_os("LESS").is_none() {
+            command.env("LESS", "FRX");
+        }
+        let mut child = command.stdin(Stdio::piped()).spawn()?;
+        let stdin = child.stdin.take().expect("pager stdin is piped");
+        Ok((Self { child }, stdin))
+    }
+
+    /// Wait for the user to quit the pager. The pipe into it should be closed first.
+    pub fn wait(mut self) -> io::Result<()> {
+        self.child.wait()?;
+        Ok(())
+    }
+}
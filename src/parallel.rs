@@ -0,0 +1,145 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::unix::fs::FileExt;
+use std::sync::mpsc;
+use std::thread;
+
+/// Read as much of buf as possible from file at offset, stopping short only at EOF
+fn read_at_most(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read_at(&mut buf[filled..], offset + filled as u64) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Copy len bytes of input from offset start to the current position of output, splitting them
+/// into chunk-sized segments which are read by `jobs` threads at once with positioned reads. If
+/// output is a regular file that nothing else is writing to and isn't in append mode, which the
+/// caller says with `positioned`, each thread writes with positioned writes and the file position
+/// is moved past the data at the end. Anything else, like a stdout which the shell redirected to a
+/// file, gets the segments written in order. Returns the number of bytes copied, which is fewer
+/// than len only if the input ends first.
+pub fn copy(
+    input: &File,
+    start: u64,
+    len: u64,
+    output: &File,
+    positioned: bool,
+    jobs: usize,
+    chunk: usize,
+) -> io::Result<u64> {
+    let chunk = chunk.max(1) as u64;
+    let chunks = len.div_ceil(chunk);
+    let jobs = jobs.min(chunks.try_into().unwrap_or(usize::MAX)).max(1);
+    let out_start = if positioned && output.metadata()?.is_file() {
+        Some((&*output).stream_position()?)
+    } else {
+        None
+    };
+
+    thread::scope(|scope| {
+        let mut receivers = Vec::with_capacity(jobs);
+        let mut workers = Vec::with_capacity(jobs);
+        for job in 0..jobs {
+            // each job only gets one segment ahead of the writer, which bounds the memory used
+            let (sender, receiver) = mpsc::sync_channel::<Vec<u8>>(1);
+            receivers.push(receiver);
+            // job N copies segments N, N + jobs, N + 2*jobs, and so on. Returns where the input
+            // ended, if it ended early.
+            workers.push(scope.spawn(move || -> io::Result<Option<u64>> {
+                let mut index = job as u64;
+                while index < chunks {
+                    let offset = index * chunk;
+                    let size = chunk.min(len - offset) as usize;
+                    let mut buf = vec![0u8; size];
+                    let count = read_at_most(input, &mut buf, start + offset)?;
+                    buf.truncate(count);
+                    match out_start {
+                        Some(out_start) => output.write_all_at(&buf, out_start + offset)?,
+                        // the writer has stopped, either at the end of the input or an error
+                        None if sender.send(buf).is_err() => return Ok(None),
+                        None => (),
+                    }
+                    if count < size {
+                        return Ok(Some(offset + count as u64));
+                    }
+                    index += jobs as u64;
+                }
+                Ok(None)
+            }));
+        }
+
+        let mut copied = len;
+        if out_start.is_none() {
+            // segment N comes from job N % jobs, so taking one from each job in turn puts them
+            // back in order
+            copied = 0;
+            for receiver in receivers.iter().cycle() {
+                let Ok(data) = receiver.recv() else { break };
+                (&*output).write_all(&data)?;
+                copied += data.len() as u64;
+                if copied == len || data.len() < chunk as usize {
+                    break;
+                }
+            }
+        }
+        drop(receivers);
+
+        for worker in workers {
+            if let Some(end) = worker.join().unwrap()? {
+                copied = copied.min(end);
+            }
+        }
+        if let Some(out_start) = out_start {
+            (&*output).seek(SeekFrom::Start(out_start + copied))?;
+        }
+        Ok(copied)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+    use std::io::Read;
+    use std::os::fd::OwnedFd;
+
+    #[test]
+    fn copy_segments() {
+        let in_path = TempPath::new("parallel-in");
+        let out_path = TempPath::new("parallel-out");
+        let data: Vec<u8> = (0..10000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&in_path, &data).unwrap();
+        let input = File::open(&in_path).unwrap();
+
+        // positioned writes to a file, after some existing data, leaving the position at the end
+        let mut output = File::create(&out_path).unwrap();
+        output.write_all(b"head").unwrap();
+        assert_eq!(copy(&input, 100, 5500, &output, true, 3, 1000).unwrap(), 5500);
+        output.write_all(b"tail").unwrap();
+        drop(output);
+        let expected = [&b"head"[..], &data[100..5600], b"tail"].concat();
+        assert_eq!(std::fs::read(&out_path).unwrap(), expected);
+
+        // in order to a file opened for appending, like `bcut -j4 ... >> out`
+        let output = File::options().append(true).open(&out_path).unwrap();
+        assert_eq!(copy(&input, 0, 3000, &output, false, 4, 500).unwrap(), 3000);
+        drop(output);
+        assert_eq!(std::fs::read(&out_path).unwrap(), [&expected[..], &data[..3000]].concat());
+
+        // in order to a pipe, running off the end of the input
+        let (mut reader, writer) = io::pipe().unwrap();
+        let writer = File::from(OwnedFd::from(writer));
+        assert_eq!(copy(&input, 7000, 5000, &writer, false, 4, 700).unwrap(), 3000);
+        drop(writer);
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, &data[7000..]);
+    }
+}
@@ -0,0 +1,68 @@
+use std::io::{self, Read, Write};
+
+use crate::io_copy;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PatchError {
+    #[error("the input ends at {0:#x}, before the patch offset")]
+    PastEnd(u64),
+    #[error("the patch is {actual} bytes rather than {expected}")]
+    WrongLength { expected: u64, actual: u64 },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Check that a patch is the expected number of bytes, if there's an expected length
+pub fn check_length(data: &[u8], expected: Option<u64>) -> Result<(), PatchError> {
+    match expected {
+        Some(expected) if expected != data.len() as u64 => {
+            Err(PatchError::WrongLength { expected, actual: data.len() as u64 })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Copy all of input to output, replacing the bytes starting at offset with data. The patch can
+/// run past the end of the input, which makes the output longer, but it can't start past the end.
+pub fn patch(
+    mut input: &mut dyn Read,
+    mut output: &mut dyn Write,
+    offset: u64,
+    data: &[u8],
+) -> Result<(), PatchError> {
+    let copied = io_copy(&mut (&mut input).take(offset), &mut output)?;
+    if copied < offset {
+        return Err(PatchError::PastEnd(copied));
+    }
+    output.write_all(data)?;
+    // skip over the bytes which were replaced
+    io_copy(&mut (&mut input).take(data.len() as u64), &mut io::sink())?;
+    io_copy(&mut input, &mut output)?;
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patch_bytes() {
+        let run = |offset, data: &[u8]| {
+            let mut out = Vec::new();
+            patch(&mut &b"0123456789"[..], &mut out, offset, data).map(|_| out)
+        };
+        assert_eq!(run(2, b"ab").unwrap(), b"01ab456789");
+        assert_eq!(run(0, b"").unwrap(), b"0123456789");
+        assert_eq!(run(8, b"abcd").unwrap(), b"01234567abcd");
+        assert_eq!(run(10, b"ab").unwrap(), b"0123456789ab");
+        assert!(matches!(run(11, b"ab"), Err(PatchError::PastEnd(10))));
+
+        assert!(check_length(b"abc", Some(3)).is_ok());
+        assert!(check_length(b"abc", None).is_ok());
+        assert!(matches!(
+            check_length(b"abc", Some(4)),
+            Err(PatchError::WrongLength { expected: 4, actual: 3 })
+        ));
+    }
+}
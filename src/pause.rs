@@ -0,0 +1,99 @@
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use signal_hook::consts::{SIGSTOP, SIGTSTP};
+use signal_hook::iterator::Signals;
+
+use crate::input::Release;
+
+/// How long a stop waits for the reader to get to a read boundary before stopping wherever the job
+/// is, e.g. blocked writing to a slow pipe
+const QUIESCE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A reader which makes SIGTSTP (e.g. ctrl-Z) pause the job cleanly.
+///
+/// Normally SIGTSTP stops the process wherever it is. Instead we catch it on a separate thread and
+/// stop at the next read, after the data from the previous read has been handed off, so nothing is
+/// left half-written while stopped. If the job is already blocked reading input (a terminal, a
+/// slow pipe, or --follow waiting for more data) that's a boundary too, so it stops right away, and
+/// if it doesn't get to one within a second it stops anyway. The state is reported to stderr, any
+/// O_DIRECT buffer is released, and the process stops itself with SIGSTOP, then carries on from
+/// the same place when SIGCONT arrives.
+pub struct PauseOnStop<R> {
+    inner: R,
+    state: Arc<State>,
+}
+
+struct State {
+    /// a stop was requested and nobody has stopped for it yet
+    stop: AtomicBool,
+    /// the reader is inside a read of its input
+    reading: AtomicBool,
+    /// bytes read so far, for the status message
+    bytes: AtomicU64,
+    release: Option<Release>,
+}
+
+impl State {
+    /// Stop the process until SIGCONT, if a stop is still pending
+    fn pause(&self) -> io::Result<()> {
+        if !self.stop.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        if let Some(release) = &self.release {
+            release();
+        }
+        eprintln!("bcut: paused after reading {} bytes", self.bytes.load(Ordering::Relaxed));
+        signal_hook::low_level::raise(SIGSTOP)?;
+        eprintln!("bcut: resumed");
+        Ok(())
+    }
+
+    /// Handle each SIGTSTP, waiting for the reader to pause or stopping the process ourselves
+    fn watch(&self, mut signals: Signals) {
+        for _ in signals.forever() {
+            self.stop.store(true, Ordering::SeqCst);
+            let deadline = Instant::now() + QUIESCE_TIMEOUT;
+            while self.stop.load(Ordering::SeqCst)
+                && !self.reading.load(Ordering::SeqCst)
+                && Instant::now() < deadline
+            {
+                thread::sleep(Duration::from_millis(10));
+            }
+            // does nothing if the reader already paused for this stop
+            let _ = self.pause();
+        }
+    }
+}
+
+impl<R: Read> PauseOnStop<R> {
+    /// Wrap inner, calling release (if given) before each pause to free memory we don't need
+    /// while stopped
+    pub fn new(inner: R, release: Option<Release>) -> io::Result<Self> {
+        let state = Arc::new(State {
+            stop: AtomicBool::new(false),
+            reading: AtomicBool::new(false),
+            bytes: AtomicU64::new(0),
+            release,
+        });
+        let signals = Signals::new([SIGTSTP])?;
+        let watcher = state.clone();
+        thread::spawn(move || watcher.watch(signals));
+        Ok(Self { inner, state })
+    }
+}
+
+impl<R: Read> Read for PauseOnStop<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.state.pause()?;
+        self.state.reading.store(true, Ordering::SeqCst);
+        let res = self.inner.read(buf);
+        self.state.reading.store(false, Ordering::SeqCst);
+        let n = res?;
+        self.state.bytes.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
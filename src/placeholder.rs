@@ -0,0 +1,92 @@
+/// Values which can be filled into an output file name
+pub struct Values<'a> {
+    pub start: u64,
+    /// length of the range, if it's known
+    pub len: Option<u64>,
+    /// input file name, None for stdin
+    pub input_name: Option<&'a str>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PlaceholderError {
+    #[error("unknown placeholder '{{{0}}}'")]
+    Unknown(String),
+    #[error("unterminated '{{' in file name")]
+    Unterminated,
+    #[error("'{{{0}}}' needs a range with a known length")]
+    NoLength(String),
+    #[error("'{{{0}}}' needs an input file")]
+    NoInput(String),
+}
+
+/// Fill in the placeholders of a file name template: {start}, {len}, and {end} (which can be
+/// given as e.g. {start:x} for hex), {input_name}, and {input_stem}. Literal braces are written
+/// as {{ and }}.
+pub fn expand(template: &str, values: &Values) -> Result<String, PlaceholderError> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(pos) = rest.find(['{', '}']) {
+        out.push_str(&rest[..pos]);
+        let brace = rest.as_bytes()[pos];
+        rest = &rest[pos + 1..];
+        if rest.as_bytes().first() == Some(&brace) {
+            out.push(brace as char);
+            rest = &rest[1..];
+            continue;
+        }
+        if brace == b'}' {
+            // a lone closing brace isn't special
+            out.push('}');
+            continue;
+        }
+        let end = rest.find('}').ok_or(PlaceholderError::Unterminated)?;
+        out.push_str(&value(&rest[..end], values)?);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Get the text of one placeholder, given what's between its braces
+fn value(name: &str, values: &Values) -> Result<String, PlaceholderError> {
+    let (key, hex) = match name.split_once(':') {
+        Some((key, "x")) => (key, true),
+        Some(_) => return Err(PlaceholderError::Unknown(name.into())),
+        None => (name, false),
+    };
+    let no_len = || PlaceholderError::NoLength(name.into());
+    let number = match key {
+        "start" => values.start,
+        "len" => values.len.ok_or_else(no_len)?,
+        "end" => values.start + values.len.ok_or_else(no_len)?,
+        "input_name" | "input_stem" if !hex => {
+            let path = values.input_name.ok_or_else(|| PlaceholderError::NoInput(name.into()))?;
+            let path = std::path::Path::new(path);
+            let part = if key == "input_name" { path.file_name() } else { path.file_stem() };
+            return Ok(part.unwrap_or_default().to_string_lossy().into_owned());
+        }
+        _ => return Err(PlaceholderError::Unknown(name.into())),
+    };
+    Ok(if hex { format!("{:x}", number) } else { number.to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_names() {
+        let values = Values { start: 0x800, len: Some(0x200), input_name: Some("dumps/img.bin") };
+        let expand = |template| expand(template, &values).unwrap();
+        assert_eq!(expand("{input_stem}_{start:x}.bin"), "img_800.bin");
+        assert_eq!(expand("{input_name}.{start}-{end:x}"), "img.bin.2048-a00");
+        assert_eq!(expand("{{len}} {len}}"), "{len} 512}");
+        assert_eq!(expand("plain"), "plain");
+
+        assert!(super::expand("{size}", &values).is_err());
+        assert!(super::expand("{start", &values).is_err());
+        let stdin = Values { start: 0, len: None, input_name: None };
+        assert!(super::expand("{len}", &stdin).is_err());
+        assert!(super::expand("{input_stem}", &stdin).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// A collection of per-call latencies for one side of the copy (reads or writes)
+#[derive(Debug, Default)]
+pub struct Latencies {
+    samples: Vec<Duration>,
+    bytes: u64,
+}
+
+impl Latencies {
+    /// Total number of bytes read or written
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    fn record(&mut self, elapsed: Duration, bytes: usize) {
+        self.samples.push(elapsed);
+        self.bytes += bytes as u64;
+    }
+
+    /// Get the p-th percentile (0-100) of a sorted sample list using the nearest-rank method
+    fn percentile(sorted: &[Duration], p: u32) -> Duration {
+        if sorted.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (sorted.len() * p as usize).div_ceil(100);
+        sorted[rank.saturating_sub(1)]
+    }
+}
+
+impl fmt::Display for Latencies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        let total: Duration = sorted.iter().sum();
+
+        write!(f, "{} calls, {} bytes, total {:.3?}", sorted.len(), self.bytes, total)?;
+        if !sorted.is_empty() {
+            write!(
+                f,
+                "; p50 {:.3?}, p90 {:.3?}, p99 {:.3?}, max {:.3?}",
+                Self::percentile(&sorted, 50),
+                Self::percentile(&sorted, 90),
+                Self::percentile(&sorted, 99),
+                sorted[sorted.len() - 1],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Latency statistics for a whole bcut run, shared between the timed reader and writer
+#[derive(Debug, Default)]
+pub struct Profile {
+    pub reads: Latencies,
+    pub writes: Latencies,
+}
+
+pub type SharedProfile = Rc<RefCell<Profile>>;
+
+impl Profile {
+    pub fn new_shared() -> SharedProfile {
+        Rc::new(RefCell::new(Self::default()))
+    }
+
+    /// Print the profile summary to stderr
+    pub fn report(&self, elapsed: Duration) {
+        eprintln!("profile: elapsed {:.3?}", elapsed);
+        eprintln!("profile: read  {}", self.reads);
+        eprintln!("profile: write {}", self.writes);
+    }
+}
+
+/// How the data of a copy was moved, for --timing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyMethod {
+    /// read into our own buffer and written out from there
+    Buffered,
+    /// written straight out of a memory mapping of the input
+    Mmap,
+    /// positioned reads by this many threads
+    Parallel(usize),
+    /// the output shares the input's extents
+    Reflink,
+    CopyFileRange,
+    Splice,
+    Sendfile,
+}
+
+impl fmt::Display for CopyMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Buffered => f.write_str("buffered read/write"),
+            Self::Mmap => f.write_str("mmap"),
+            Self::Parallel(jobs) => write!(f, "pread with {} threads", jobs),
+            Self::Reflink => f.write_str("FICLONERANGE reflink"),
+            Self::CopyFileRange => f.write_str("copy_file_range"),
+            Self::Splice => f.write_str("splice"),
+            Self::Sendfile => f.write_str("sendfile"),
+        }
+    }
+}
+
+/// Print the --timing summary of a copy to stderr
+pub fn report_timing(bytes: u64, elapsed: Duration, method: CopyMethod) {
+    let secs = elapsed.as_secs_f64();
+    let rate = if secs > 0.0 { bytes as f64 / secs / 1e6 } else { 0.0 };
+    eprintln!("timing: {} bytes in {:.3?} ({:.1} MB/s) using {}", bytes, elapsed, rate, method);
+}
+
+/// Reader wrapper which records the latency of every read call
+pub struct TimedReader<R> {
+    inner: R,
+    profile: SharedProfile,
+}
+
+impl<R: Read> TimedReader<R> {
+    pub fn new(inner: R, profile: SharedProfile) -> Self {
+        Self { inner, profile }
+    }
+}
+
+impl<R: Read> Read for TimedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let start = Instant::now();
+        let count = self.inner.read(buf)?;
+        self.profile.borrow_mut().reads.record(start.elapsed(), count);
+        Ok(count)
+    }
+}
+
+/// Writer wrapper which records the latency of every write call
+pub struct TimedWriter<W> {
+    inner: W,
+    profile: SharedProfile,
+}
+
+impl<W: Write> TimedWriter<W> {
+    pub fn new(inner: W, profile: SharedProfile) -> Self {
+        Self { inner, profile }
+    }
+}
+
+impl<W: Write> Write for TimedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let start = Instant::now();
+        let count = self.inner.write(buf)?;
+        self.profile.borrow_mut().writes.record(start.elapsed(), count);
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles() {
+        let sorted: Vec<_> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(Latencies::percentile(&sorted, 50), Duration::from_millis(50));
+        assert_eq!(Latencies::percentile(&sorted, 99), Duration::from_millis(99));
+        assert_eq!(Latencies::percentile(&sorted, 100), Duration::from_millis(100));
+        assert_eq!(Latencies::percentile(&[], 50), Duration::ZERO);
+
+        let one = [Duration::from_millis(7)];
+        assert_eq!(Latencies::percentile(&one, 1), Duration::from_millis(7));
+        assert_eq!(Latencies::percentile(&one, 99), Duration::from_millis(7));
+    }
+
+    #[test]
+    fn timed_wrappers() {
+        let profile = Profile::new_shared();
+        let mut reader = TimedReader::new(&b"hello world"[..], profile.clone());
+        let mut writer = TimedWriter::new(Vec::new(), profile.clone());
+        io::copy(&mut reader, &mut writer).unwrap();
+        assert_eq!(writer.inner, b"hello world");
+
+        let p = profile.borrow();
+        assert_eq!(p.reads.bytes, 11);
+        assert_eq!(p.writes.bytes, 11);
+        assert!(!p.writes.samples.is_empty());
+    }
+}
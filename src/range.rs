@@ -4,10 +4,10 @@ use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{char, digit1, hex_digit1, one_of},
-    combinator::{eof, map_res, opt, recognize},
+    combinator::{eof, map, map_res, opt, recognize},
     error::Error as NomError,
     multi::{many0, many1},
-    sequence::{preceded, terminated},
+    sequence::{pair, preceded, terminated},
     Finish, IResult,
 };
 
@@ -29,6 +29,14 @@ pub enum ParseRangeError {
     EndBeforeStart,
     #[error("byte count overflow")]
     Overflow,
+    #[error("byte value exceeds 0xff")]
+    ByteOutOfRange,
+    #[error("percentage exceeds 100")]
+    PercentOutOfRange,
+    #[error("a count can't be relative to the end of the input")]
+    CountFromEnd,
+    #[error("range is relative to the size of the input, which isn't known")]
+    NeedsSize,
 }
 
 /// ParseRangeError needs an owned error type, extra conversion for the borrowed error we get from
@@ -72,48 +80,115 @@ fn number(input: &str) -> IResult<&str, u64> {
     alt((hex, dec))(input)
 }
 
+/// Parse a complete string as a single integer, either decimal or hex. Suitable for use as a clap
+/// value_parser for numeric options.
+pub fn parse_number(input: &str) -> Result<u64, ParseRangeError> {
+    let (_, n) = terminated(number, eof)(input).finish()?;
+    Ok(n)
+}
+
+/// Parse a complete string as a size, an integer optionally followed by a binary unit suffix K,
+/// M, G, or T (case insensitive), e.g. "4K" is 4096.
+pub fn parse_size(input: &str) -> Result<u64, ParseRangeError> {
+    let (_, (n, suffix)) =
+        terminated(pair(number, opt(one_of("kKmMgGtT"))), eof)(input).finish()?;
+    let shift = match suffix.map(|c| c.to_ascii_uppercase()) {
+        None => 0,
+        Some('K') => 10,
+        Some('M') => 20,
+        Some('G') => 30,
+        Some('T') => 40,
+        Some(c) => unreachable!("unexpected suffix {c}"),
+    };
+    n.checked_mul(1 << shift).ok_or(ParseRangeError::Overflow)
+}
+
+/// Parse a complete string as a single byte value, e.g. a fill byte
+pub fn parse_byte(input: &str) -> Result<u8, ParseRangeError> {
+    parse_number(input)?.try_into().map_err(|_| ParseRangeError::ByteOutOfRange)
+}
+
+/// A position in a range, which may depend on the size of the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pos {
+    /// a byte offset
+    Offset(u64),
+    /// this many bytes before the end of the input
+    FromEnd(u64),
+    /// this percentage of the way through the input
+    Percent(u64),
+}
+
+/// Parse a position: N, ^N for N bytes before the end, or N% for a percentage of the size
+fn pos(input: &str) -> IResult<&str, Pos> {
+    alt((
+        map(preceded(char('^'), number), Pos::FromEnd),
+        map(terminated(number, char('%')), Pos::Percent),
+        map(number, Pos::Offset),
+    ))(input)
+}
+
 /// The top-level raw components we parse using nom
 #[derive(Debug)]
 struct RangePieces {
-    start: Option<u64>,
+    start: Option<Pos>,
     mode: char,
-    end: Option<u64>,
+    end: Option<Pos>,
 }
 
 /// Parse a string into RangePieces
 fn parse_range_pieces(input: &str) -> IResult<&str, RangePieces> {
-    let (input, start) = opt(number)(input)?;
+    let (input, start) = opt(pos)(input)?;
     let (input, mode) = one_of("-+")(input)?;
-    let (input, end) = opt(number)(input)?;
+    let (input, end) = opt(pos)(input)?;
     let (input, _) = eof(input)?;
 
     Ok((input, RangePieces { start, mode, end }))
 }
 
-/// Parse a string into a Range
-impl FromStr for Range {
-    type Err = ParseRangeError;
+/// A range as it was written, whose positions may be relative to the size of the input, which
+/// isn't known until the input is open
+#[derive(Debug)]
+pub struct RangeSpec {
+    start: Pos,
+    mode: char,
+    end: Option<Pos>,
+}
 
-    fn from_str(input: &str) -> Result<Range, ParseRangeError> {
-        let (rest, pieces) = parse_range_pieces(input).finish()?;
-        assert!(rest.is_empty(), "unexpected trailing data {rest:?}");
+impl RangeSpec {
+    /// Whether resolve needs the size of the input
+    pub fn needs_size(&self) -> bool {
+        [Some(self.start), self.end].into_iter().flatten().any(|p| !matches!(p, Pos::Offset(_)))
+    }
+
+    /// Turn this into a Range of byte offsets, given the size of the input, which is only used
+    /// (and must be known) if needs_size is true
+    pub fn resolve(&self, size: Option<u64>) -> Result<Range, ParseRangeError> {
+        let offset = |p: Pos| match (p, size) {
+            (Pos::Offset(n), _) => Ok(n),
+            (Pos::FromEnd(n), Some(size)) => Ok(size.saturating_sub(n)),
+            // p is at most 100, so this fits back into a u64
+            (Pos::Percent(p), Some(size)) => Ok((u128::from(size) * u128::from(p) / 100) as u64),
+            (_, None) => Err(ParseRangeError::NeedsSize),
+        };
 
-        let start = pieces.start.unwrap_or(0);
+        let start = offset(self.start)?;
         if start > (i64::MAX as u64) {
             return Err(ParseRangeError::StartOutOfBounds);
         }
 
-        let count = match (pieces.mode, pieces.end) {
+        let count = match (self.mode, self.end) {
             // read from start to EOF
             (_, None) => None,
 
             // read count bytes beginning at start
-            ('+', Some(count)) => Some(count),
+            ('+', Some(count)) => Some(offset(count)?),
 
             // read bytes start to end (inclusive).
             // Because end is inclusive, count = end-start+1
             ('-', Some(end)) => Some(
-                end.checked_sub(start)
+                offset(end)?
+                    .checked_sub(start)
                     .ok_or(ParseRangeError::EndBeforeStart)?
                     .checked_add(1)
                     .ok_or(ParseRangeError::Overflow)?,
@@ -127,6 +202,44 @@ impl FromStr for Range {
     }
 }
 
+/// Parse a string into a RangeSpec
+impl FromStr for RangeSpec {
+    type Err = ParseRangeError;
+
+    fn from_str(input: &str) -> Result<RangeSpec, ParseRangeError> {
+        let (rest, pieces) = parse_range_pieces(input).finish()?;
+        assert!(rest.is_empty(), "unexpected trailing data {rest:?}");
+
+        let positions = [pieces.start, pieces.end];
+        if positions.into_iter().flatten().any(|p| matches!(p, Pos::Percent(p) if p > 100)) {
+            return Err(ParseRangeError::PercentOutOfRange);
+        }
+        if let ('+', Some(Pos::FromEnd(_))) = (pieces.mode, pieces.end) {
+            return Err(ParseRangeError::CountFromEnd);
+        }
+
+        let spec = RangeSpec {
+            start: pieces.start.unwrap_or(Pos::Offset(0)),
+            mode: pieces.mode,
+            end: pieces.end,
+        };
+        // catch the errors which don't depend on the size now
+        if !spec.needs_size() {
+            spec.resolve(None)?;
+        }
+        Ok(spec)
+    }
+}
+
+/// Parse a string into a Range, which can't be relative to the size of the input
+impl FromStr for Range {
+    type Err = ParseRangeError;
+
+    fn from_str(input: &str) -> Result<Range, ParseRangeError> {
+        input.parse::<RangeSpec>()?.resolve(None)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -160,6 +273,25 @@ mod tests {
         assert_eq!(number("123-456"), Ok(("-456", 123)));
     }
 
+    #[test]
+    fn parse_full_number() {
+        assert_eq!(super::parse_number("0x1_000").unwrap(), 4096);
+        assert_eq!(super::parse_number("512").unwrap(), 512);
+        assert!(super::parse_number("").is_err());
+        assert!(super::parse_number("12 ").is_err());
+        assert!(super::parse_number("1-2").is_err());
+
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("4K").unwrap(), 4096);
+        assert_eq!(parse_size("0x10m").unwrap(), 16 << 20);
+        assert_eq!(parse_size("2T").unwrap(), 2 << 40);
+        assert!(parse_size("4KB").is_err());
+        assert!(parse_size("0xffffffff_ffffffffK").is_err());
+
+        assert_eq!(parse_byte("0xff").unwrap(), 0xff);
+        assert!(parse_byte("256").is_err());
+    }
+
     #[test]
     fn parse_range() {
         macro_rules! assert_range_matches {
@@ -211,5 +343,37 @@ mod tests {
         range_test!("10", Err);
         range_test!("0x80000000_00000000+10", Err); // start exceeds isize
         range_test!("0-0xffffffff_ffffffff", Err); // overflow
+
+        // the size isn't known
+        range_test!("^16-", Err);
+        range_test!("0-50%", Err);
+    }
+
+    #[test]
+    fn resolve_relative() {
+        let resolve = |s: &str, size| {
+            let range = s.parse::<RangeSpec>()?.resolve(Some(size))?;
+            Ok::<_, ParseRangeError>((range.start, range.count))
+        };
+
+        assert!(!"0x10-0x20".parse::<RangeSpec>().unwrap().needs_size());
+        assert!("^16-".parse::<RangeSpec>().unwrap().needs_size());
+        assert_eq!(resolve("0x10-0x20", 1000).unwrap(), (16, Some(17)));
+
+        // like tail -c
+        assert_eq!(resolve("^16-", 1000).unwrap(), (984, None));
+        assert_eq!(resolve("^0x10+4", 1000).unwrap(), (984, Some(4)));
+        assert_eq!(resolve("^2000-", 1000).unwrap(), (0, None));
+        // everything but the last 16 bytes, and the last byte alone
+        assert_eq!(resolve("-^17", 1000).unwrap(), (0, Some(984)));
+        assert_eq!(resolve("^1-^1", 1000).unwrap(), (999, Some(1)));
+
+        assert_eq!(resolve("50%-", 1000).unwrap(), (500, None));
+        assert_eq!(resolve("25%+10%", 1000).unwrap(), (250, Some(100)));
+        assert_eq!(resolve("0x10-100%", 1000).unwrap(), (16, Some(985)));
+        assert!(resolve("100%-", u64::MAX).is_err()); // start exceeds i64::MAX
+        assert!("101%-".parse::<RangeSpec>().is_err());
+        assert!("0+^16".parse::<RangeSpec>().is_err());
+        assert!(resolve("^16-^32", 1000).is_err());
     }
 }
@@ -0,0 +1,251 @@
+use clap::ValueEnum;
+
+use crate::compress::Method;
+
+/// Input encodings which can be decoded into a flat address space before applying the range
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// Raw binary, the range selects file offsets
+    #[default]
+    Binary,
+    /// Intel HEX records, the range selects addresses
+    Ihex,
+    /// Motorola S-records, the range selects addresses
+    Srec,
+    /// gzip compressed binary, the range selects offsets in the decompressed data
+    Gzip,
+    /// zstd compressed binary, the range selects offsets in the decompressed data
+    Zstd,
+    /// xz compressed binary, the range selects offsets in the decompressed data
+    Xz,
+    /// bzip2 compressed binary, the range selects offsets in the decompressed data
+    Bzip2,
+}
+
+impl InputFormat {
+    /// Whether this is a record format which has to be decoded into an address space
+    pub fn is_records(self) -> bool {
+        matches!(self, Self::Ihex | Self::Srec)
+    }
+
+    /// The compression which has to be removed from the input, for compressed binary formats
+    pub fn compression(self) -> Option<Method> {
+        match self {
+            Self::Gzip => Some(Method::Gzip),
+            Self::Zstd => Some(Method::Zstd),
+            Self::Xz => Some(Method::Xz),
+            Self::Bzip2 => Some(Method::Bzip2),
+            Self::Binary | Self::Ihex | Self::Srec => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("line {line}: {reason}")]
+pub struct RecordError {
+    line: usize,
+    reason: &'static str,
+}
+
+/// A sparse memory image decoded from a record file. Each segment is a load address and the bytes
+/// loaded there, in file order so that later records overwrite earlier ones where they overlap.
+#[derive(Debug, Default)]
+pub struct Image {
+    segments: Vec<(u64, Vec<u8>)>,
+}
+
+/// Decode a string of hex digit pairs
+pub fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok()).collect()
+}
+
+impl Image {
+    /// Add data at addr, extending the last segment if it's contiguous
+    fn load(&mut self, addr: u64, data: &[u8]) {
+        if let Some((last_addr, last_data)) = self.segments.last_mut() {
+            if *last_addr + last_data.len() as u64 == addr {
+                last_data.extend_from_slice(data);
+                return;
+            }
+        }
+        self.segments.push((addr, data.to_vec()));
+    }
+
+    /// Parse an Intel HEX file
+    pub fn parse_ihex(text: &str) -> Result<Self, RecordError> {
+        let mut image = Self::default();
+        // base address from the last extended segment/linear address record
+        let mut base = 0u64;
+
+        for (i, line) in text.lines().enumerate() {
+            let err = |reason| RecordError { line: i + 1, reason };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let bytes = line
+                .strip_prefix(':')
+                .ok_or_else(|| err("missing ':' start code"))
+                .and_then(|hex| decode_hex(hex).ok_or_else(|| err("invalid hex digits")))?;
+            if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+                return Err(err("wrong record length"));
+            }
+            if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+                return Err(err("bad checksum"));
+            }
+
+            let addr = u16::from_be_bytes([bytes[1], bytes[2]]) as u64;
+            let data = &bytes[4..bytes.len() - 1];
+            match bytes[3] {
+                0x00 => image.load(base + addr, data),
+                0x01 => break,
+                0x02 | 0x04 if data.len() == 2 => {
+                    let value = u16::from_be_bytes([data[0], data[1]]) as u64;
+                    base = if bytes[3] == 0x02 { value << 4 } else { value << 16 };
+                }
+                // start address records don't affect the image
+                0x03 | 0x05 => (),
+                _ => return Err(err("unsupported record type")),
+            }
+        }
+        Ok(image)
+    }
+
+    /// Parse a Motorola S-record file
+    pub fn parse_srec(text: &str) -> Result<Self, RecordError> {
+        let mut image = Self::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let err = |reason| RecordError { line: i + 1, reason };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let rest = line.strip_prefix('S').ok_or_else(|| err("missing 'S' start code"))?;
+            let kind = rest
+                .chars()
+                .next()
+                .and_then(|c| c.to_digit(10))
+                .ok_or_else(|| err("bad record type"))?;
+            let bytes = decode_hex(&rest[1..]).ok_or_else(|| err("invalid hex digits"))?;
+            if bytes.is_empty() || bytes.len() != bytes[0] as usize + 1 {
+                return Err(err("wrong record length"));
+            }
+            if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0xff {
+                return Err(err("bad checksum"));
+            }
+
+            let addr_len = match kind {
+                0 | 1 | 5 | 9 => 2,
+                2 | 6 | 8 => 3,
+                3 | 7 => 4,
+                _ => return Err(err("unsupported record type")),
+            };
+            if bytes.len() < addr_len + 2 {
+                return Err(err("wrong record length"));
+            }
+            let addr = bytes[1..=addr_len].iter().fold(0u64, |a, b| (a << 8) | *b as u64);
+            let data = &bytes[addr_len + 1..bytes.len() - 1];
+            match kind {
+                1..=3 => image.load(addr, data),
+                7..=9 => break,
+                // header and count records don't affect the image
+                _ => (),
+            }
+        }
+        Ok(image)
+    }
+
+    /// One past the highest address loaded in the image
+    pub fn end(&self) -> u64 {
+        self.segments.iter().map(|(addr, data)| addr + data.len() as u64).max().unwrap_or(0)
+    }
+
+    /// Get the number of bytes that extract() will return for the same start and count
+    pub fn extract_len(&self, start: u64, count: Option<u64>) -> u64 {
+        let end = match count {
+            Some(count) => start.saturating_add(count).min(self.end()),
+            None => self.end(),
+        };
+        end.saturating_sub(start)
+    }
+
+    /// Get the bytes at addresses [start, start+count), filling any gaps with fill. The result
+    /// stops at the end of the image, like reading a file stops at EOF.
+    pub fn extract(&self, start: u64, count: Option<u64>, fill: u8) -> Vec<u8> {
+        let len = self.extract_len(start, count);
+        if len == 0 {
+            return Vec::new();
+        }
+        let end = start + len;
+
+        let mut out = vec![fill; len as usize];
+        for (addr, data) in &self.segments {
+            let seg_end = addr + data.len() as u64;
+            let lo = (*addr).max(start);
+            let hi = seg_end.min(end);
+            if lo < hi {
+                out[(lo - start) as usize..(hi - start) as usize]
+                    .copy_from_slice(&data[(lo - addr) as usize..(hi - addr) as usize]);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ihex() {
+        let text =
+            ":020000040001F9\n:02FFFE000102FE\n:020000040002F8\n:0100000003FC\n:00000001FF\n";
+        let image = Image::parse_ihex(text).unwrap();
+        assert_eq!(image.end(), 0x20001);
+        assert_eq!(image.extract(0x1fffd, None, 0xff), b"\xff\x01\x02\x03");
+        assert_eq!(image.extract(0x1fffe, Some(2), 0xff), b"\x01\x02");
+        assert_eq!(image.extract(0x30000, None, 0xff), b"");
+        assert_eq!(image.extract_len(0, None), 0x20001);
+        assert_eq!(image.extract_len(0x1fffe, Some(100)), 3);
+
+        // extended segment address and a gap between records
+        let text = ":020000021000EC\n:0100000011EE\n:0100020022DB\n:00000001FF\n";
+        let image = Image::parse_ihex(text).unwrap();
+        assert_eq!(image.extract(0x10000, None, 0), b"\x11\x00\x22");
+
+        assert_eq!(
+            Image::parse_ihex(":0100000011EF\n").unwrap_err(),
+            RecordError { line: 1, reason: "bad checksum" }
+        );
+        assert!(Image::parse_ihex("\n0100000011EE\n").is_err());
+        assert!(Image::parse_ihex(":0200000011EE\n").is_err());
+    }
+
+    #[test]
+    fn srec() {
+        let text = concat!(
+            "S0030000FC\n",
+            "S1137AF00A0A0D0000000000000000000000000061\n",
+            "S20600FFFFAABB96\n",
+            "S5030002FA\n",
+            "S9037AF092\n",
+        );
+        let image = Image::parse_srec(text).unwrap();
+        assert_eq!(image.end(), 0x10001);
+        assert_eq!(image.extract(0x7aef, Some(4), 0xff), b"\xff\x0a\x0a\x0d");
+        assert_eq!(image.extract(0xfffe, None, 0xee), b"\xee\xaa\xbb");
+
+        assert_eq!(
+            Image::parse_srec("S0030000FC\nS1137AF00A0A0D0000000000000000000000000062\n")
+                .unwrap_err(),
+            RecordError { line: 2, reason: "bad checksum" }
+        );
+        assert!(Image::parse_srec("S4030000FC\n").is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use std::io::{self, Read};
+
+use crate::range::Range;
+
+/// Reader which replaces the bytes of a range of the input with a fill byte, passing everything
+/// else through unchanged
+pub struct Redact<R> {
+    inner: R,
+    /// offset of the next byte read
+    pos: u64,
+    start: u64,
+    end: u64,
+    fill: u8,
+}
+
+impl<R: Read> Redact<R> {
+    pub fn new(inner: R, range: &Range, fill: u8) -> Self {
+        let end = range.count.map_or(u64::MAX, |count| range.start.saturating_add(count));
+        Self { inner, pos: 0, start: range.start, end, fill }
+    }
+}
+
+impl<R: Read> Read for Redact<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        let end = self.pos + count as u64;
+        // the part of the range in this read, relative to it
+        let from = self.start.clamp(self.pos, end) - self.pos;
+        let to = self.end.clamp(self.pos, end) - self.pos;
+        buf[from as usize..to as usize].fill(self.fill);
+        self.pos = end;
+        Ok(count)
+    }
+}
+
+/// Reader which leaves the bytes of a range out of the input, joining what comes before and
+/// after it
+pub struct CutOut<R> {
+    inner: R,
+    /// offset of the next byte read
+    pos: u64,
+    start: u64,
+    end: u64,
+}
+
+impl<R: Read> CutOut<R> {
+    pub fn new(inner: R, range: &Range) -> Self {
+        let end = range.count.map_or(u64::MAX, |count| range.start.saturating_add(count));
+        Self { inner, pos: 0, start: range.start, end }
+    }
+}
+
+impl<R: Read> Read for CutOut<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let count = self.inner.read(buf)?;
+            let end = self.pos + count as u64;
+            let from = (self.start.clamp(self.pos, end) - self.pos) as usize;
+            let to = (self.end.clamp(self.pos, end) - self.pos) as usize;
+            buf.copy_within(to..count, from);
+            self.pos = end;
+            // a read which was all inside the range isn't EOF, so keep going
+            let kept = count - (to - from);
+            if kept != 0 || count == 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_range() {
+        let redact = |start, count| {
+            // read a few bytes at a time so the range spans several reads
+            let data = b"0123456789";
+            let input = data[..3].chain(&data[3..7]).chain(&data[7..]);
+            let mut out = Vec::new();
+            Redact::new(input, &Range { start, count }, b'x').read_to_end(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(redact(2, Some(6)), "01xxxxxx89");
+        assert_eq!(redact(0, Some(1)), "x123456789");
+        assert_eq!(redact(5, None), "01234xxxxx");
+        assert_eq!(redact(8, Some(100)), "01234567xx");
+        assert_eq!(redact(20, Some(1)), "0123456789");
+        assert_eq!(redact(4, Some(0)), "0123456789");
+    }
+
+    #[test]
+    fn cut_out_range() {
+        let cut = |start, count| {
+            let data = b"0123456789";
+            let input = data[..3].chain(&data[3..7]).chain(&data[7..]);
+            let mut out = Vec::new();
+            CutOut::new(input, &Range { start, count }).read_to_end(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(cut(2, Some(6)), "0189");
+        assert_eq!(cut(3, Some(4)), "012789");
+        assert_eq!(cut(0, Some(7)), "789");
+        assert_eq!(cut(5, None), "01234");
+        assert_eq!(cut(20, Some(1)), "0123456789");
+        assert_eq!(cut(0, None), "");
+    }
+}
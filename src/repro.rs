@@ -0,0 +1,90 @@
+use std::fs::{File, FileTimes};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use sha2::{Digest, Sha256};
+
+use crate::manifest::{Chunk, Hash, Manifest};
+
+/// Timestamp given to every output, from SOURCE_DATE_EPOCH if it's set or the Unix epoch otherwise
+pub fn timestamp() -> SystemTime {
+    let secs = std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|s| s.trim().parse().ok());
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs.unwrap_or(0))
+}
+
+/// Hash a file, returning its size and SHA-256
+fn hash_file(file: &mut File) -> io::Result<(u64, Hash)> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut size = 0;
+    loop {
+        match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                hasher.update(&buf[..n]);
+                size += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok((size, hasher.finalize().into()))
+}
+
+/// Build the canonical manifest of a set of output files: every file by name (without its
+/// directory) in sorted order, with its size and hash.
+fn canonical_manifest(paths: &[PathBuf]) -> io::Result<Manifest> {
+    let mut chunks = Vec::new();
+    for path in paths {
+        let mut file = File::open(path)?;
+        let (size, sha256) = hash_file(&mut file)?;
+        let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned();
+        chunks.push(Chunk { name, size: Some(size), sha256: Some(sha256), ..Default::default() });
+    }
+    chunks.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Manifest { chunks, ..Default::default() })
+}
+
+/// Normalize a finished set of output files so that the same extraction always gives the same
+/// result, and return a hash identifying the whole set. The modification times are set to
+/// timestamp(), and the hash is the SHA-256 of the set's canonical manifest.
+pub fn finish(paths: &[PathBuf]) -> io::Result<Hash> {
+    let time = timestamp();
+    for path in paths {
+        set_times(path, time)?;
+    }
+
+    let mut text = Vec::new();
+    canonical_manifest(paths)?.write(&mut text)?;
+    Ok(Sha256::digest(&text).into())
+}
+
+fn set_times(path: &Path, time: SystemTime) -> io::Result<()> {
+    let file = File::options().write(true).open(path)?;
+    file.set_times(FileTimes::new().set_accessed(time).set_modified(time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    #[test]
+    fn canonical_order() {
+        let dir = TempPath::new("repro");
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("out.000");
+        let b = dir.join("out.001");
+        std::fs::write(&a, b"abc").unwrap();
+        std::fs::write(&b, b"").unwrap();
+
+        let forward = finish(&[a.clone(), b.clone()]).unwrap();
+        let backward = finish(&[b.clone(), a.clone()]).unwrap();
+        assert_eq!(forward, backward);
+        assert_eq!(std::fs::metadata(&a).unwrap().modified().unwrap(), timestamp());
+
+        std::fs::write(&b, b"x").unwrap();
+        assert_ne!(finish(&[a, b]).unwrap(), forward);
+    }
+}
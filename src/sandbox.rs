@@ -0,0 +1,41 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SandboxError {
+    #[cfg(target_os = "linux")]
+    #[error(transparent)]
+    Landlock(#[from] landlock::RulesetError),
+    #[error("the kernel doesn't support landlock")]
+    Unsupported,
+}
+
+/// Drop all filesystem and network access for the rest of the process's life.
+///
+/// Files which are already open stay usable, so this is called once the input and output are
+/// set up, before any parsing of the (possibly untrusted) input happens. Any access which would
+/// open a new file or socket afterwards fails with EACCES. Landlock only restricts the calling
+/// thread and the threads and processes it creates later, so this must run on the main thread.
+///
+/// If the kernel supports landlock but not every access right (e.g. network restrictions need
+/// Linux 6.7), the parts it does support are still enforced.
+#[cfg(target_os = "linux")]
+pub fn enter() -> Result<(), SandboxError> {
+    use landlock::{Access, AccessFs, AccessNet, Ruleset, RulesetAttr, RulesetStatus, ABI};
+
+    let abi = ABI::V4;
+    // handle every access right and add no rules, which denies all of them
+    let status = Ruleset::default()
+        .handle_access(AccessFs::from_all(abi))?
+        .handle_access(AccessNet::from_all(abi))?
+        .create()?
+        .restrict_self()?;
+    match status.ruleset {
+        RulesetStatus::NotEnforced => Err(SandboxError::Unsupported),
+        RulesetStatus::FullyEnforced | RulesetStatus::PartiallyEnforced => Ok(()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn enter() -> Result<(), SandboxError> {
+    Err(SandboxError::Unsupported)
+}
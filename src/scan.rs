@@ -0,0 +1,369 @@
+use std::io::{self, Read};
+
+use aho_corasick::AhoCorasick;
+
+/// Size of the buffer used when scanning through input data
+const SCAN_BUF_SIZE: usize = 1024 * 1024;
+
+/// A byte pattern to search for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern(pub Vec<u8>);
+
+impl std::ops::Deref for Pattern {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Parse a byte pattern written as hex digits, optionally separated by spaces or colons, e.g.
+/// "1f8b08" or "1f:8b:08"
+pub fn parse_hex_pattern(input: &str) -> Result<Pattern, String> {
+    let digits: String = input.chars().filter(|c| !matches!(c, ' ' | ':')).collect();
+    let digits = digits.strip_prefix("0x").unwrap_or(&digits);
+    if digits.is_empty()
+        || !digits.len().is_multiple_of(2)
+        || !digits.bytes().all(|b| b.is_ascii_hexdigit())
+    {
+        return Err("pattern must be a non-empty, even number of hex digits".into());
+    }
+    Ok(Pattern(
+        (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+            .collect(),
+    ))
+}
+
+/// An unsigned integer type and range of values to search for, e.g. "u32le:0xdeadbeef" or
+/// "u16be:0x100-0x1ff"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntSpec {
+    /// size of the integer in bytes
+    pub width: usize,
+    pub big_endian: bool,
+    /// inclusive range of values which match
+    pub lo: u64,
+    pub hi: u64,
+}
+
+impl IntSpec {
+    /// Decode an integer of this type from the start of bytes, which must be at least width long
+    pub fn decode(&self, bytes: &[u8]) -> u64 {
+        let mut buf = [0u8; 8];
+        if self.big_endian {
+            buf[8 - self.width..].copy_from_slice(&bytes[..self.width]);
+            u64::from_be_bytes(buf)
+        } else {
+            buf[..self.width].copy_from_slice(&bytes[..self.width]);
+            u64::from_le_bytes(buf)
+        }
+    }
+
+    pub fn matches(&self, bytes: &[u8]) -> bool {
+        (self.lo..=self.hi).contains(&self.decode(bytes))
+    }
+}
+
+/// Parse an IntSpec, TYPE:VALUE or TYPE:LO-HI where TYPE is u8, u16le, u16be, u32le, u32be,
+/// u64le, or u64be.
+pub fn parse_int_spec(input: &str) -> Result<IntSpec, String> {
+    let (ty, values) = input.split_once(':').ok_or("expected TYPE:VALUE")?;
+    let (width, big_endian) = match ty.to_ascii_lowercase().as_str() {
+        "u8" => (1, false),
+        "u16le" => (2, false),
+        "u16be" => (2, true),
+        "u32le" => (4, false),
+        "u32be" => (4, true),
+        "u64le" => (8, false),
+        "u64be" => (8, true),
+        _ => return Err(format!("unknown integer type '{}'", ty)),
+    };
+
+    let parse =
+        |s: &str| crate::range::parse_number(s).map_err(|e| format!("invalid value: {}", e));
+    let (lo, hi) = match values.split_once('-') {
+        Some((lo, hi)) => (parse(lo)?, parse(hi)?),
+        None => {
+            let v = parse(values)?;
+            (v, v)
+        }
+    };
+    if lo > hi {
+        return Err("range end is less than start".into());
+    }
+    if width < 8 && hi >= 1 << (width * 8) {
+        return Err(format!("value too large for {}", ty));
+    }
+    Ok(IntSpec { width, big_endian, lo, hi })
+}
+
+/// Call found with the absolute offset and data of every position in input where is_match
+/// returns true for the next width bytes. start is the absolute offset of the first byte of
+/// input. Only absolute offsets which are a multiple of align are checked. Matches may overlap.
+pub fn find<R, M, F>(
+    input: &mut R,
+    start: u64,
+    width: usize,
+    align: u64,
+    mut is_match: M,
+    mut found: F,
+) -> io::Result<()>
+where
+    R: Read,
+    M: FnMut(&[u8]) -> bool,
+    F: FnMut(u64, &[u8]) -> io::Result<()>,
+{
+    assert!(width > 0 && align > 0);
+    let mut buf = vec![0u8; SCAN_BUF_SIZE];
+    // unsearched data, the last width-1 bytes are kept between reads
+    let mut pending: Vec<u8> = Vec::new();
+    // absolute offset of pending[0]
+    let mut offset = start;
+
+    loop {
+        let count = match input.read(&mut buf) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        pending.extend_from_slice(&buf[..count]);
+
+        if pending.len() >= width {
+            let searched = pending.len() - width + 1;
+            // index of the first aligned window
+            let first = ((align - offset % align) % align) as usize;
+            for i in (first..searched).step_by(align as usize) {
+                let window = &pending[i..i + width];
+                if is_match(window) {
+                    found(offset + i as u64, window)?;
+                }
+            }
+            pending.drain(..searched);
+            offset += searched as u64;
+        }
+    }
+    Ok(())
+}
+
+/// Search input for all of the given patterns in a single pass, calling found with the absolute
+/// offset and pattern index of each match. start is the absolute offset of the first byte of
+/// input, and only matches at offsets which are a multiple of align are reported. Matches may
+/// overlap, and are reported in order of their end offset.
+pub fn find_patterns<R, F>(
+    input: &mut R,
+    start: u64,
+    patterns: &[Pattern],
+    align: u64,
+    mut found: F,
+) -> io::Result<()>
+where
+    R: Read,
+    F: FnMut(u64, usize) -> io::Result<()>,
+{
+    assert!(!patterns.is_empty() && align > 0);
+    let ac = AhoCorasick::new(patterns.iter().map(|p| &p[..])).map_err(io::Error::other)?;
+    let max_len = patterns.iter().map(|p| p.len()).max().unwrap();
+    let mut buf = vec![0u8; SCAN_BUF_SIZE];
+    // unsearched data, the last max_len-1 bytes are kept between reads
+    let mut pending: Vec<u8> = Vec::new();
+    // absolute offset of pending[0]
+    let mut offset = start;
+
+    loop {
+        let count = match input.read(&mut buf) {
+            Ok(count) => count,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        pending.extend_from_slice(&buf[..count]);
+
+        // Matches starting in the kept tail will be found again after the next read, so only
+        // report them now if we're at EOF.
+        let searched =
+            if count == 0 { pending.len() } else { pending.len().saturating_sub(max_len - 1) };
+        for m in ac.find_overlapping_iter(&pending) {
+            let match_offset = offset + m.start() as u64;
+            if m.start() < searched && match_offset.is_multiple_of(align) {
+                found(match_offset, m.pattern().as_usize())?;
+            }
+        }
+        pending.drain(..searched);
+        offset += searched as u64;
+
+        if count == 0 {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// A run of identical bytes found in the input
+#[derive(Debug, PartialEq, Eq)]
+pub struct Run {
+    /// absolute offset of the first byte in the run
+    pub offset: u64,
+    /// the repeated byte value
+    pub byte: u8,
+    /// number of bytes in the run
+    pub len: u64,
+}
+
+/// Scan through input looking for runs of at least min_len identical bytes, calling found for
+/// each one in order. start is the absolute offset of the first byte of input.
+pub fn rle_runs<R: Read, F: FnMut(Run) -> io::Result<()>>(
+    input: &mut R,
+    start: u64,
+    min_len: u64,
+    mut found: F,
+) -> io::Result<()> {
+    let mut buf = vec![0u8; SCAN_BUF_SIZE];
+    let mut offset = start;
+    let mut current: Option<Run> = None;
+
+    loop {
+        let count = match input.read(&mut buf) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        for &byte in &buf[..count] {
+            match current {
+                Some(ref mut run) if run.byte == byte => run.len += 1,
+                _ => {
+                    if let Some(run) = current.take() {
+                        if run.len >= min_len {
+                            found(run)?;
+                        }
+                    }
+                    current = Some(Run { offset, byte, len: 1 });
+                }
+            }
+            offset += 1;
+        }
+    }
+
+    if let Some(run) = current {
+        if run.len >= min_len {
+            found(run)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runs(data: &[u8], start: u64, min_len: u64) -> Vec<Run> {
+        let mut v = Vec::new();
+        rle_runs(&mut &data[..], start, min_len, |run| {
+            v.push(run);
+            Ok(())
+        })
+        .unwrap();
+        v
+    }
+
+    #[test]
+    fn rle() {
+        assert_eq!(runs(b"", 0, 1), vec![]);
+        assert_eq!(
+            runs(b"aaabcc", 10, 2),
+            vec![Run { offset: 10, byte: b'a', len: 3 }, Run { offset: 14, byte: b'c', len: 2 }]
+        );
+        assert_eq!(runs(b"abcdef", 0, 2), vec![]);
+        assert_eq!(
+            runs(b"\xff\xff\xff\xff\x00\xff", 0, 4),
+            vec![Run { offset: 0, byte: 0xff, len: 4 }]
+        );
+    }
+
+    fn find_offsets(data: &[u8], start: u64, align: u64, pat: &[u8]) -> Vec<u64> {
+        let mut v = Vec::new();
+        find(
+            &mut &data[..],
+            start,
+            pat.len(),
+            align,
+            |w| w == pat,
+            |off, _| {
+                v.push(off);
+                Ok(())
+            },
+        )
+        .unwrap();
+        v
+    }
+
+    #[test]
+    fn find_bytes() {
+        assert_eq!(find_offsets(b"", 0, 1, b"ab"), vec![]);
+        assert_eq!(find_offsets(b"a", 0, 1, b"ab"), vec![]);
+        assert_eq!(find_offsets(b"abcabab", 100, 1, b"ab"), vec![100, 103, 105]);
+        assert_eq!(find_offsets(b"aaaa", 0, 1, b"aa"), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn find_multiple() {
+        let patterns = [Pattern(b"ab".to_vec()), Pattern(b"bcd".to_vec()), Pattern(b"d".to_vec())];
+        let mut v = Vec::new();
+        find_patterns(&mut &b"abcdxab"[..], 10, &patterns, 1, |off, i| {
+            v.push((off, i));
+            Ok(())
+        })
+        .unwrap();
+        v.sort();
+        assert_eq!(v, vec![(10, 0), (11, 1), (13, 2), (15, 0)]);
+
+        let mut v = Vec::new();
+        find_patterns(&mut &b"abcdxab"[..], 10, &patterns, 5, |off, i| {
+            v.push((off, i));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(v, vec![(10, 0), (15, 0)]);
+    }
+
+    #[test]
+    fn find_aligned() {
+        assert_eq!(find_offsets(b"abcabab", 100, 2, b"ab"), vec![100]);
+        assert_eq!(find_offsets(b"abcabab", 101, 2, b"ab"), vec![104, 106]);
+        assert_eq!(find_offsets(b"xxxxab", 0, 4, b"ab"), vec![4]);
+        assert_eq!(find_offsets(b"xxxxab", 2, 4, b"ab"), vec![]);
+    }
+
+    #[test]
+    fn int_spec() {
+        let spec = parse_int_spec("u32le:0xdeadbeef").unwrap();
+        assert_eq!(spec, IntSpec { width: 4, big_endian: false, lo: 0xdeadbeef, hi: 0xdeadbeef });
+        assert!(spec.matches(b"\xef\xbe\xad\xde"));
+        assert!(!spec.matches(b"\xde\xad\xbe\xef"));
+
+        let spec = parse_int_spec("U16BE:0x100-0x1ff").unwrap();
+        assert_eq!(spec.decode(b"\x01\x80"), 0x180);
+        assert!(spec.matches(b"\x01\x80"));
+        assert!(!spec.matches(b"\x02\x00"));
+
+        assert_eq!(parse_int_spec("u64be:1").unwrap().decode(b"\0\0\0\0\0\0\0\x01"), 1);
+        assert!(parse_int_spec("u8:256").is_err());
+        assert!(parse_int_spec("u8:5-4").is_err());
+        assert!(parse_int_spec("i32:5").is_err());
+        assert!(parse_int_spec("u32le").is_err());
+    }
+
+    #[test]
+    fn hex_pattern() {
+        assert_eq!(parse_hex_pattern("1f8b08").unwrap().0, vec![0x1f, 0x8b, 0x08]);
+        assert_eq!(parse_hex_pattern("1f:8b 08").unwrap().0, vec![0x1f, 0x8b, 0x08]);
+        assert_eq!(parse_hex_pattern("0xABcd").unwrap().0, vec![0xab, 0xcd]);
+        assert!(parse_hex_pattern("").is_err());
+        assert!(parse_hex_pattern("abc").is_err());
+        assert!(parse_hex_pattern("zz").is_err());
+        assert!(parse_hex_pattern("+1").is_err());
+    }
+}
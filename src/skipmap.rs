@@ -0,0 +1,224 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::input::block_device_size;
+use crate::range::parse_size;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SkipMapError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("{path} line {line}: expected OFFSET LENGTH")]
+    Parse { path: String, line: usize },
+}
+
+/// Load a bad-block map, one "OFFSET LENGTH" per line with '#' comments, and return the regions
+/// as sorted and merged (start, end) pairs of input offsets.
+pub fn load(path: &Path) -> Result<Vec<(u64, u64)>, SkipMapError> {
+    parse(&fs::read_to_string(path)?, path)
+}
+
+fn parse(text: &str, path: &Path) -> Result<Vec<(u64, u64)>, SkipMapError> {
+    let mut regions = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let err = || SkipMapError::Parse { path: path.display().to_string(), line: i + 1 };
+        let [offset, len] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+            return Err(err());
+        };
+        let offset = parse_size(offset).map_err(|_| err())?;
+        let len = parse_size(len).map_err(|_| err())?;
+        if len != 0 {
+            regions.push((offset, offset.saturating_add(len)));
+        }
+    }
+
+    regions.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(regions.len());
+    for (start, end) in regions {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    Ok(merged)
+}
+
+/// An input which can get past a bad region. Files seek over it so that the bad sectors are never
+/// read, anything else has to read through it.
+pub trait SkipOver: Read {
+    /// Skip up to len bytes, returning how many there were before the end of the input
+    fn skip_over(&mut self, len: u64) -> io::Result<u64>;
+}
+
+impl SkipOver for io::Take<File> {
+    fn skip_over(&mut self, len: u64) -> io::Result<u64> {
+        let limit = self.limit();
+        let file = self.get_mut();
+        let size = match block_device_size(file)? {
+            Some(size) => size,
+            None => file.metadata()?.len(),
+        };
+        let len = len.min(limit).min(size.saturating_sub(file.stream_position()?));
+        // len is at most the file size, which fits in an i64
+        file.seek(SeekFrom::Current(len as i64))?;
+        self.set_limit(limit - len);
+        Ok(len)
+    }
+}
+
+impl SkipOver for Box<dyn Read + Send> {
+    fn skip_over(&mut self, len: u64) -> io::Result<u64> {
+        io::copy(&mut self.take(len), &mut io::sink())
+    }
+}
+
+/// Reader which drops the bytes of an input falling in bad regions, or replaces them with a fill
+/// byte so that everything else keeps its offset. The bad regions themselves are skipped over
+/// rather than read, so an input which can seek never touches them.
+pub struct SkipBad<R> {
+    inner: R,
+    /// remaining regions, the first of which may already have started
+    regions: Vec<(u64, u64)>,
+    next: usize,
+    /// input offset of the next byte read from inner
+    pos: u64,
+    fill: Option<u8>,
+}
+
+impl<R: SkipOver> SkipBad<R> {
+    /// Wrap inner, which starts at input offset start
+    pub fn new(inner: R, regions: Vec<(u64, u64)>, start: u64, fill: Option<u8>) -> Self {
+        let next = regions.partition_point(|&(_, end)| end <= start);
+        Self { inner, regions, next, pos: start, fill }
+    }
+
+    /// Read good data into buf, which doesn't reach the next bad region
+    fn read_good(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl<R: SkipOver> Read for SkipBad<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let Some(&(bad_start, bad_end)) = self.regions.get(self.next) else {
+                return self.read_good(buf);
+            };
+            if bad_start > self.pos {
+                let len = usize::try_from(bad_start - self.pos).unwrap_or(usize::MAX);
+                let len = len.min(buf.len());
+                return self.read_good(&mut buf[..len]);
+            }
+
+            // in a bad region, so get past it without reading it, filling only as much of it as
+            // the input actually has
+            let len = match self.fill {
+                Some(_) => (bad_end - self.pos).min(buf.len() as u64),
+                None => bad_end - self.pos,
+            };
+            let skipped = self.inner.skip_over(len)?;
+            self.pos += skipped;
+            if self.pos == bad_end {
+                self.next += 1;
+            }
+            if let Some(fill) = self.fill {
+                buf[..skipped as usize].fill(fill);
+                return Ok(skipped as usize);
+            }
+            if skipped < len {
+                // the input ended inside the region
+                return Ok(0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    #[test]
+    fn parse_and_skip() {
+        let map = "# offset length\n0x10 4\n12 5 # overlaps\n\n40 1K\n";
+        let regions = parse(map, Path::new("map")).unwrap();
+        assert_eq!(regions, [(12, 20), (40, 1064)]);
+        assert!(parse("10\n", Path::new("map")).is_err());
+
+        let data: Vec<u8> = (0..50).collect();
+        let mut out = Vec::new();
+        // small reads to split regions across them
+        let inner: Box<dyn Read + Send> = Box::new(io::Cursor::new(data[10..].to_vec()));
+        let mut reader = SkipBad::new(inner, regions.clone(), 10, None);
+        let mut buf = [0u8; 3];
+        loop {
+            let n = reader.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+        let expected: Vec<u8> = (10..12).chain(20..40).collect();
+        assert_eq!(out, expected);
+
+        out.clear();
+        let inner: Box<dyn Read + Send> = Box::new(io::Cursor::new(data.clone()));
+        SkipBad::new(inner, regions, 0, Some(0xff)).read_to_end(&mut out).unwrap();
+        let mut expected = data.clone();
+        expected[12..20].fill(0xff);
+        expected[40..].fill(0xff);
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn seek_over_bad() {
+        // an input file whose bad region can't be read, like a failing disk returning EIO
+        let path = TempPath::new("skipmap");
+        let data: Vec<u8> = (0..100).collect();
+        std::fs::write(&path, &data).unwrap();
+        struct Failing(io::Take<File>);
+        impl Read for Failing {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let pos = self.0.get_mut().stream_position()?;
+                if pos + buf.len() as u64 > 20 && pos < 30 {
+                    return Err(io::Error::from_raw_os_error(5));
+                }
+                self.0.read(buf)
+            }
+        }
+        impl SkipOver for Failing {
+            fn skip_over(&mut self, len: u64) -> io::Result<u64> {
+                self.0.skip_over(len)
+            }
+        }
+        let open = |start| {
+            let mut file = File::open(&path).unwrap();
+            file.seek(SeekFrom::Start(start)).unwrap();
+            Failing(file.take(80))
+        };
+
+        let mut out = Vec::new();
+        SkipBad::new(open(10), vec![(20, 30), (85, 200)], 10, None).read_to_end(&mut out).unwrap();
+        let expected: Vec<u8> = (10..20).chain(30..85).collect();
+        assert_eq!(out, expected);
+
+        // the range starts inside the region, and ends before the end of the second one
+        out.clear();
+        let mut reader = SkipBad::new(open(25), vec![(20, 30), (85, 200)], 25, Some(0));
+        reader.read_to_end(&mut out).unwrap();
+        let mut expected = data[25..].to_vec();
+        expected[..5].fill(0);
+        expected[60..].fill(0);
+        assert_eq!(out, expected);
+    }
+}
@@ -0,0 +1,195 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::io_copy;
+use crate::profile::CopyMethod;
+use crate::range::Range;
+
+/// Find the next region of data in a file at or after pos, returning its start and end, or None if
+/// there's only a hole left before end.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "freebsd"))]
+fn next_data(file: &File, pos: u64, end: u64) -> io::Result<Option<(u64, u64)>> {
+    use rustix::fs::{seek, SeekFrom as RawSeekFrom};
+    use rustix::io::Errno;
+
+    let offset = |pos: u64| pos.try_into().unwrap();
+    let data = match seek(file, RawSeekFrom::Data(offset(pos))) {
+        Ok(data) => data,
+        // no more data after pos
+        Err(Errno::NXIO) => return Ok(None),
+        // old kernels don't know about holes, so everything is data
+        Err(Errno::INVAL) => return Ok(Some((pos, end))),
+        Err(e) => return Err(e.into()),
+    };
+    if data >= end {
+        return Ok(None);
+    }
+    let hole = seek(file, RawSeekFrom::Hole(offset(data)))?;
+    Ok(Some((data, hole.min(end))))
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "freebsd"
+)))]
+fn next_data(_file: &File, pos: u64, end: u64) -> io::Result<Option<(u64, u64)>> {
+    Ok((pos < end).then_some((pos, end)))
+}
+
+/// Clone len bytes of input at offset from to offset to of output with FICLONERANGE, so that the
+/// output shares the input's extents rather than copying them. This only works within one
+/// filesystem which supports reflinks, like Btrfs or XFS, and for ranges which are aligned to
+/// its blocks (or end at the end of the input). Returns false if the clone couldn't be done, in
+/// which case nothing was changed.
+#[cfg(target_os = "linux")]
+fn clone_range(input: &File, from: u64, output: &File, to: u64, len: u64) -> io::Result<bool> {
+    use rustix::io::Errno;
+    use rustix::ioctl::{ioctl, Setter, WriteOpcode};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::MetadataExt;
+
+    #[repr(C)]
+    struct FileCloneRange {
+        src_fd: i64,
+        src_offset: u64,
+        src_length: u64,
+        dest_offset: u64,
+    }
+    type FiCloneRange = WriteOpcode<0x94, 13, FileCloneRange>;
+
+    // a length of 0 means the rest of the input to FICLONERANGE
+    let meta = input.metadata()?;
+    let block = meta.blksize().max(1);
+    let aligned = |n: u64| n.is_multiple_of(block);
+    if len == 0 || !aligned(from) || !aligned(to) || !(aligned(len) || from + len == meta.len()) {
+        return Ok(false);
+    }
+
+    let args = FileCloneRange {
+        src_fd: input.as_raw_fd().into(),
+        src_offset: from,
+        src_length: len,
+        dest_offset: to,
+    };
+    // SAFETY: FICLONERANGE takes a pointer to a struct file_clone_range, which FileCloneRange
+    // matches, and only reads it
+    match unsafe { ioctl(output, Setter::<FiCloneRange, _>::new(args)) } {
+        Ok(()) => Ok(true),
+        // different filesystems, no reflink support, or a range the filesystem won't clone
+        Err(Errno::XDEV | Errno::OPNOTSUPP | Errno::INVAL | Errno::NOTTY | Errno::NOSYS) => {
+            Ok(false)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn clone_range(_input: &File, _from: u64, _output: &File, _to: u64, _len: u64) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Copy len bytes of input at offset from to offset to of output, returning how many bytes were
+/// copied, which is fewer than len only if the input ends first. On Linux this uses
+/// copy_file_range so that the kernel (or the filesystem, for reflinks and server-side copies)
+/// does the work, and sets method when it does.
+fn copy_region(
+    input: &mut File,
+    from: u64,
+    output: &mut File,
+    to: u64,
+    len: u64,
+    method: &mut CopyMethod,
+) -> io::Result<u64> {
+    let mut copied = 0;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        use rustix::fs::copy_file_range;
+        use rustix::io::Errno;
+
+        while copied < len {
+            let (mut off_in, mut off_out) = (from + copied, to + copied);
+            let chunk = (len - copied).min(1 << 30) as usize;
+            match copy_file_range(&*input, Some(&mut off_in), &*output, Some(&mut off_out), chunk) {
+                Ok(0) => return Ok(copied),
+                Ok(n) => {
+                    copied += n as u64;
+                    *method = CopyMethod::CopyFileRange;
+                }
+                Err(Errno::INTR) => continue,
+                // not supported by this kernel or between these filesystems, so do it ourselves
+                Err(Errno::NOSYS | Errno::XDEV | Errno::INVAL | Errno::OPNOTSUPP) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if copied == len {
+            return Ok(copied);
+        }
+    }
+
+    input.seek(SeekFrom::Start(from + copied))?;
+    output.seek(SeekFrom::Start(to + copied))?;
+    Ok(copied + io_copy(&mut (&mut *input).take(len - copied), output)?)
+}
+
+/// Copy the range of a regular file to the current position of an output file, seeking over the
+/// holes in the input rather than writing zeros so that they stay holes in the output. Returns
+/// the number of bytes copied, including holes, and how they were copied.
+pub fn copy(input: &mut File, range: &Range, output: &mut File) -> io::Result<(u64, CopyMethod)> {
+    let size = input.metadata()?.len();
+    let start = range.start.min(size);
+    let end = match range.count {
+        Some(count) => start.saturating_add(count).min(size),
+        None => size,
+    };
+    let out_start = output.stream_position()?;
+
+    // the whole range can be shared with the input when the filesystem supports it, which keeps
+    // the holes too
+    if clone_range(input, start, output, out_start, end - start)? {
+        return Ok((end - start, CopyMethod::Reflink));
+    }
+
+    let mut method = CopyMethod::Buffered;
+    let mut pos = start;
+    while let Some((data, hole)) = next_data(input, pos, end)? {
+        let to = out_start + (data - start);
+        let copied = copy_region(input, data, output, to, hole - data, &mut method)?;
+        if copied < hole - data {
+            // the input was truncated while we were copying it
+            return Ok((data - start + copied, method));
+        }
+        pos = hole;
+    }
+    output.flush()?;
+
+    // a hole at the end needs the file extended to reach the full size
+    let out_end = out_start + (end - start);
+    if output.metadata()?.len() < out_end {
+        output.set_len(out_end)?;
+    }
+    Ok((end - start, method))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    #[test]
+    fn copy_range() {
+        let in_path = TempPath::new("sparse-in");
+        let out_path = TempPath::new("sparse-out");
+        let mut data = vec![0u8; 1 << 20];
+        data[100..200].fill(0xaa);
+        std::fs::write(&in_path, &data).unwrap();
+
+        let mut input = File::open(&in_path).unwrap();
+        let mut output = File::create(&out_path).unwrap();
+        let range = Range { start: 50, count: Some(500000) };
+        assert_eq!(copy(&mut input, &range, &mut output).unwrap().0, 500000);
+        drop(output);
+        assert_eq!(std::fs::read(&out_path).unwrap(), &data[50..500050]);
+    }
+}
@@ -0,0 +1,196 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::io_copy;
+use crate::profile::CopyMethod;
+use crate::range::Range;
+
+/// Whether a file is a pipe, which is needed at one end or the other for splice
+pub fn is_pipe(file: &File) -> io::Result<bool> {
+    use std::os::unix::fs::FileTypeExt;
+    Ok(file.metadata()?.file_type().is_fifo())
+}
+
+/// Whether a file is a regular file or block device, which we can read from an offset
+fn is_seekable(file: &File) -> io::Result<bool> {
+    use std::os::unix::fs::FileTypeExt;
+    let file_type = file.metadata()?.file_type();
+    Ok(file_type.is_file() || file_type.is_block_device())
+}
+
+/// Whether a file is a socket, which sendfile can write to
+pub fn is_socket(file: &File) -> io::Result<bool> {
+    use std::os::unix::fs::FileTypeExt;
+    Ok(file.metadata()?.file_type().is_socket())
+}
+
+/// Copy the range of input to the current position of output, where at least one of them is a
+/// pipe or the output is a socket, moving the data with splice or sendfile so that it never has
+/// to be copied through our own buffers. A regular file or block device input is read from the
+/// range start after its current position, anything else (a pipe, terminal or socket) is read up
+/// to the range start first. If the kernel can't do
+/// the copy, this falls back to a normal one. Returns the number of bytes copied, and how the
+/// kernel copied them if it did.
+pub fn copy(input: &mut File, range: &Range, output: &mut File) -> io::Result<(u64, CopyMethod)> {
+    let mut offset = if is_seekable(input)? {
+        // stdin can be a file which isn't at its start
+        Some(input.stream_position()? + range.start)
+    } else {
+        io_copy(&mut (&mut *input).take(range.start), &mut io::sink())?;
+        None
+    };
+    let len = range.count.unwrap_or(u64::MAX);
+    let (copied, method) = match offset {
+        Some(ref mut offset) if is_socket(output)? => {
+            (sendfile_loop(input, offset, output, len)?, CopyMethod::Sendfile)
+        }
+        _ => (splice_loop(input, &mut offset, output, len)?, CopyMethod::Splice),
+    };
+    let method = if copied == 0 { CopyMethod::Buffered } else { method };
+    if copied == len {
+        return Ok((copied, method));
+    }
+
+    // the kernel copy stopped early, either at EOF or because it can't be used here. Any offset
+    // we're using has kept track of where we got to, so carry on with a normal copy from there; at
+    // EOF this will just read nothing.
+    if let Some(offset) = offset {
+        input.seek(SeekFrom::Start(offset))?;
+    }
+    Ok((copied + io_copy(&mut (&mut *input).take(len - copied), output)?, method))
+}
+
+/// Splice up to len bytes, reading input from offset if it's not a pipe, until EOF or an error
+/// which means splice can't be used
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn splice_loop(input: &File, offset: &mut Option<u64>, output: &File, len: u64) -> io::Result<u64> {
+    use rustix::io::Errno;
+    use rustix::pipe::{splice, SpliceFlags};
+
+    let mut copied = 0;
+    while copied < len {
+        let chunk = (len - copied).min(1 << 30) as usize;
+        match splice(input, offset.as_mut(), output, None, chunk, SpliceFlags::MOVE) {
+            // EOF, which the fallback copy will confirm
+            Ok(0) => break,
+            Ok(n) => copied += n as u64,
+            Err(Errno::INTR) => continue,
+            Err(Errno::INVAL | Errno::NOSYS) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(copied)
+}
+
+/// Send up to len bytes of input from offset to a socket, until EOF or an error which means
+/// sendfile can't be used
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn sendfile_loop(input: &File, offset: &mut u64, output: &File, len: u64) -> io::Result<u64> {
+    use rustix::fs::sendfile;
+    use rustix::io::Errno;
+
+    let mut copied = 0;
+    while copied < len {
+        let chunk = (len - copied).min(1 << 30) as usize;
+        match sendfile(output, input, Some(offset), chunk) {
+            Ok(0) => break,
+            Ok(n) => copied += n as u64,
+            Err(Errno::INTR) => continue,
+            Err(Errno::INVAL | Errno::NOSYS) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(copied)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn sendfile_loop(_input: &File, _offset: &mut u64, _output: &File, _len: u64) -> io::Result<u64> {
+    Ok(0)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn splice_loop(
+    _input: &File,
+    _offset: &mut Option<u64>,
+    _output: &File,
+    _len: u64,
+) -> io::Result<u64> {
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+    use std::io::Write;
+    use std::os::fd::OwnedFd;
+
+    #[test]
+    fn file_to_pipe() {
+        let path = TempPath::new("splice");
+        let data: Vec<u8> = (0..10000u32).map(|i| i as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let (mut reader, writer) = io::pipe().unwrap();
+        let mut writer = File::from(OwnedFd::from(writer));
+        let mut input = File::open(&path).unwrap();
+        let range = Range { start: 100, count: Some(5000) };
+        assert_eq!(copy(&mut input, &range, &mut writer).unwrap().0, 5000);
+        drop(writer);
+
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, &data[100..5100]);
+    }
+
+    #[test]
+    fn stdin_range_start() {
+        let path = TempPath::new("splice-stdin");
+        let data: Vec<u8> = (0..10000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+        let range = Range { start: 0x100, count: Some(0x10) };
+        let run = |input: &mut File| {
+            let (mut reader, writer) = io::pipe().unwrap();
+            let mut writer = File::from(OwnedFd::from(writer));
+            assert_eq!(copy(input, &range, &mut writer).unwrap().0, 0x10);
+            drop(writer);
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out).unwrap();
+            out
+        };
+
+        // like `cat img | bcut 0x100+0x10`, the start is skipped exactly once
+        let (pipe_reader, mut pipe_writer) = io::pipe().unwrap();
+        pipe_writer.write_all(&data[..4096]).unwrap();
+        drop(pipe_writer);
+        let mut input = File::from(OwnedFd::from(pipe_reader));
+        assert_eq!(run(&mut input), &data[0x100..0x110]);
+
+        // stdin redirected from a file which something else already read part of
+        let mut input = File::open(&path).unwrap();
+        input.seek(SeekFrom::Start(1000)).unwrap();
+        assert_eq!(run(&mut input), &data[1000 + 0x100..1000 + 0x110]);
+    }
+
+    #[test]
+    fn socket_input() {
+        use std::os::unix::net::UnixStream;
+
+        // like `bcut 0x10+0x20 | cat` run from something which gave us a socket for stdin
+        let data: Vec<u8> = (0..0x100u32).map(|i| i as u8).collect();
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        sender.write_all(&data).unwrap();
+        drop(sender);
+
+        let (mut reader, writer) = io::pipe().unwrap();
+        let mut writer = File::from(OwnedFd::from(writer));
+        let mut input = File::from(OwnedFd::from(receiver));
+        let range = Range { start: 0x10, count: Some(0x20) };
+        assert_eq!(copy(&mut input, &range, &mut writer).unwrap().0, 0x20);
+        drop(writer);
+
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, &data[0x10..0x30]);
+    }
+}
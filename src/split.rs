@@ -0,0 +1,298 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::manifest::{Manifest, ManifestBuilder};
+
+/// Magic bytes at the start of every volume header
+pub const VOLUME_MAGIC: &[u8; 8] = b"BCUTVOL\0";
+
+/// Size of the header at the start of every volume of a spanned set: the magic, the volume index
+/// (u32), flags (u32), and the offset of the volume's data in the whole set (u64), all little
+/// endian.
+pub const VOLUME_HEADER_SIZE: u64 = 24;
+
+/// Volume header flag set on every volume which continues data from the previous one
+pub const VOLUME_CONTINUED: u32 = 1;
+
+/// Get the name of the n-th split output file, e.g. "out.bin.003"
+pub fn numbered_path(base: &Path, n: u32) -> PathBuf {
+    let mut name = OsString::from(base.as_os_str());
+    name.push(format!(".{:03}", n));
+    name.into()
+}
+
+/// Get the name of the manifest file for a split or spanned set, e.g. "out.bin.manifest"
+pub fn manifest_path(base: &Path) -> PathBuf {
+    let mut name = OsString::from(base.as_os_str());
+    name.push(".manifest");
+    name.into()
+}
+
+/// Build a volume header
+pub fn volume_header(index: u32, offset: u64) -> [u8; VOLUME_HEADER_SIZE as usize] {
+    let flags = if index == 0 { 0 } else { VOLUME_CONTINUED };
+    let mut header = [0u8; VOLUME_HEADER_SIZE as usize];
+    header[..8].copy_from_slice(VOLUME_MAGIC);
+    header[8..12].copy_from_slice(&index.to_le_bytes());
+    header[12..16].copy_from_slice(&flags.to_le_bytes());
+    header[16..].copy_from_slice(&offset.to_le_bytes());
+    header
+}
+
+/// Tracks the current output of a split, opening the next one lazily when data arrives
+struct Outputs<W, F> {
+    open: F,
+    current: Option<W>,
+    count: u32,
+    manifest: ManifestBuilder,
+}
+
+impl<W: Write, F: FnMut(u32) -> io::Result<(String, W)>> Outputs<W, F> {
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        if self.current.is_none() {
+            let (name, w) = (self.open)(self.count)?;
+            self.manifest.start_chunk(name);
+            self.current = Some(w);
+            self.count += 1;
+        }
+        self.current.as_mut().unwrap().write_all(data)?;
+        self.manifest.update(data);
+        Ok(())
+    }
+
+    /// Finish the current output, if any. The next write will go to a new one.
+    fn next(&mut self) -> io::Result<()> {
+        if let Some(mut w) = self.current.take() {
+            w.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Copy input to a series of outputs, starting a new one (by calling open with an increasing
+/// index) each time pattern occurs, so every output after the first begins with the pattern. open
+/// returns the output's name for the manifest along with its writer. Empty outputs are never
+/// created.
+pub fn split_stream<R, W, F>(input: &mut R, pattern: &[u8], open: F) -> io::Result<Manifest>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(u32) -> io::Result<(String, W)>,
+{
+    assert!(!pattern.is_empty());
+    let mut buf = vec![0u8; 1024 * 1024];
+    // data read but not yet written, the tail may hold the start of a pattern match
+    let mut pending: Vec<u8> = Vec::new();
+    let mut outputs = Outputs { open, current: None, count: 0, manifest: ManifestBuilder::new(0) };
+
+    loop {
+        let n = match input.read(&mut buf) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        pending.extend_from_slice(&buf[..n]);
+
+        let mut pos = 0;
+        while let Some(i) = pending[pos..].windows(pattern.len()).position(|w| w == pattern) {
+            let at = pos + i;
+            outputs.write(&pending[pos..at])?;
+            outputs.next()?;
+            outputs.write(&pending[at..at + pattern.len()])?;
+            pos = at + pattern.len();
+        }
+
+        // keep back anything which could be the start of a match in the next read
+        let keep = if n == 0 { 0 } else { (pattern.len() - 1).min(pending.len() - pos) };
+        let flush_end = pending.len() - keep;
+        outputs.write(&pending[pos..flush_end])?;
+        pending.drain(..flush_end);
+
+        if n == 0 {
+            break;
+        }
+    }
+    outputs.next()?;
+    Ok(outputs.manifest.finish())
+}
+
+/// Open the n-th numbered output file, returning its name for the manifest. The manifest is text,
+/// so the name has to be valid UTF-8.
+fn create_numbered(base: &Path, n: u32) -> io::Result<(String, File)> {
+    let path = numbered_path(base, n);
+    let Some(name) = path.file_name().unwrap_or_default().to_str() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "output file names must be valid UTF-8 to be listed in the manifest",
+        ));
+    };
+    Ok((name.to_owned(), File::create(&path)?))
+}
+
+/// Split input into files named after base, see split_stream. The manifest is returned rather
+/// than written so that the caller can decide where it goes.
+pub fn split_to_files<R: Read>(input: &mut R, pattern: &[u8], base: &Path) -> io::Result<Manifest> {
+    split_stream(input, pattern, |n| create_numbered(base, n))
+}
+
+/// Copy input to a series of volumes of at most volume_size bytes each (including the header),
+/// opening each with open, which returns the volume's name for the manifest along with its writer.
+/// No empty volume is created, except for the first one if the input is empty.
+pub fn span_stream<R, W, F>(input: &mut R, volume_size: u64, mut open: F) -> io::Result<Manifest>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(u32) -> io::Result<(String, W)>,
+{
+    assert!(volume_size > VOLUME_HEADER_SIZE);
+    let payload_size = volume_size - VOLUME_HEADER_SIZE;
+    let mut buf = vec![0u8; (1024 * 1024).min(payload_size) as usize];
+    let mut builder = ManifestBuilder::new(VOLUME_HEADER_SIZE);
+    let mut current: Option<(W, u64)> = None;
+    let mut index = 0;
+    let mut offset = 0u64;
+
+    loop {
+        // don't read more than fits in the current volume
+        let space = current.as_ref().map_or(payload_size, |(_, used)| payload_size - used);
+        let want = buf.len().min(space as usize);
+        let n = match input.read(&mut buf[..want]) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if n == 0 && (index > 0 || current.is_some()) {
+            break;
+        }
+
+        if current.is_none() {
+            let (name, mut w) = open(index)?;
+            w.write_all(&volume_header(index, offset))?;
+            builder.start_chunk(name);
+            current = Some((w, 0));
+            index += 1;
+        }
+        let (w, used) = current.as_mut().unwrap();
+        w.write_all(&buf[..n])?;
+        builder.update(&buf[..n]);
+        *used += n as u64;
+        offset += n as u64;
+
+        if n == 0 {
+            // empty input, we still made one volume so that the set isn't empty
+            break;
+        }
+        if *used == payload_size {
+            let (mut w, _) = current.take().unwrap();
+            w.flush()?;
+        }
+    }
+
+    if let Some((mut w, _)) = current {
+        w.flush()?;
+    }
+    Ok(builder.finish())
+}
+
+/// Span input across volume files named after base, see span_stream. The manifest is returned
+/// rather than written so that the caller can decide where it goes.
+pub fn span_to_files<R: Read>(
+    input: &mut R,
+    volume_size: u64,
+    base: &Path,
+) -> io::Result<Manifest> {
+    span_stream(input, volume_size, |n| create_numbered(base, n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct SharedVec(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedVec {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn split(data: &[u8], pattern: &[u8]) -> Vec<Vec<u8>> {
+        let outputs = RefCell::new(Vec::new());
+        let manifest = split_stream(&mut &data[..], pattern, |n| {
+            let mut outputs = outputs.borrow_mut();
+            assert_eq!(n as usize, outputs.len());
+            outputs.push(Rc::new(RefCell::new(Vec::new())));
+            Ok((format!("s{}", n), SharedVec(outputs[n as usize].clone())))
+        })
+        .unwrap();
+
+        let outputs: Vec<Vec<u8>> = outputs.into_inner().into_iter().map(|v| v.take()).collect();
+        assert_eq!(manifest.chunks.len(), outputs.len());
+        assert_eq!(manifest.size, Some(data.len() as u64));
+        outputs
+    }
+
+    #[test]
+    fn split_pattern() {
+        assert_eq!(split(b"", b"XY"), Vec::<Vec<u8>>::new());
+        assert_eq!(split(b"abc", b"XY"), vec![b"abc".to_vec()]);
+        assert_eq!(
+            split(b"abXYcdXYXYe", b"XY"),
+            vec![b"ab".to_vec(), b"XYcd".to_vec(), b"XY".to_vec(), b"XYe".to_vec()]
+        );
+        // a match at the very start doesn't make an empty first file
+        assert_eq!(split(b"XYaXYb", b"XY"), vec![b"XYa".to_vec(), b"XYb".to_vec()]);
+        assert_eq!(split(b"aXY", b"XY"), vec![b"a".to_vec(), b"XY".to_vec()]);
+    }
+
+    fn span(data: &[u8], volume_size: u64) -> (Vec<Vec<u8>>, Manifest) {
+        let mut volumes = Vec::new();
+        let manifest = span_stream(&mut &data[..], volume_size, |n| {
+            assert_eq!(n as usize, volumes.len());
+            let v = Rc::new(RefCell::new(Vec::new()));
+            volumes.push(v.clone());
+            Ok((format!("v{}", n), SharedVec(v)))
+        })
+        .unwrap();
+        (volumes.into_iter().map(|v| v.take()).collect(), manifest)
+    }
+
+    #[test]
+    fn span_volumes() {
+        let (volumes, manifest) = span(b"abcdefg", VOLUME_HEADER_SIZE + 3);
+        assert_eq!(volumes.len(), 3);
+        assert_eq!(volumes[0][..VOLUME_HEADER_SIZE as usize], volume_header(0, 0));
+        assert_eq!(&volumes[0][VOLUME_HEADER_SIZE as usize..], b"abc");
+        assert_eq!(volumes[1][..VOLUME_HEADER_SIZE as usize], volume_header(1, 3));
+        assert_eq!(&volumes[1][VOLUME_HEADER_SIZE as usize..], b"def");
+        assert_eq!(&volumes[2][VOLUME_HEADER_SIZE as usize..], b"g");
+        assert_eq!(manifest.size, Some(7));
+        let names: Vec<&str> = manifest.chunks.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["v0", "v1", "v2"]);
+
+        // an exact fit doesn't leave an empty trailing volume
+        let (volumes, _) = span(b"abcdef", VOLUME_HEADER_SIZE + 3);
+        assert_eq!(volumes.len(), 2);
+
+        let (volumes, manifest) = span(b"", VOLUME_HEADER_SIZE + 3);
+        assert_eq!(volumes.len(), 1);
+        assert_eq!(manifest.chunks[0].size, Some(0));
+    }
+
+    #[test]
+    fn numbered() {
+        assert_eq!(numbered_path(Path::new("dir/out.bin"), 7), PathBuf::from("dir/out.bin.007"));
+    }
+}
@@ -0,0 +1,143 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::io_copy;
+
+/// A temporary copy of stdin, which is deleted when dropped
+pub struct Spool {
+    path: PathBuf,
+    /// the memory file holding the copy, if it fit in memory. Its path only works while it's open.
+    memory: Option<File>,
+}
+
+impl Spool {
+    /// Copy all of stdin to a new temporary file in dir, or the system temp directory if dir is
+    /// None, so that it can be used as a seekable input file. If stdin is no bigger than memory
+    /// bytes, it's kept in a memory file instead.
+    pub fn stdin(dir: Option<&Path>, memory: u64) -> io::Result<Self> {
+        let mut stdin = io::stdin().lock();
+        // whatever went into memory before finding out that it doesn't all fit
+        let mut head = None;
+        if memory != 0 {
+            match Self::memory(&mut stdin, memory)? {
+                Ok(spool) => return Ok(spool),
+                Err(file) => head = Some(file),
+            }
+        }
+
+        let dir = dir.map_or_else(std::env::temp_dir, Path::to_owned);
+        let (spool, mut file) = Self::create(&dir)?;
+        if let Some(mut head) = head {
+            io_copy(&mut head, &mut file)?;
+        }
+        io_copy(&mut stdin, &mut file)?;
+        file.flush()?;
+        Ok(spool)
+    }
+
+    /// Copy input to a memory file if it's no bigger than limit bytes. Otherwise the memory file
+    /// is returned as the error, rewound to the start of what was read into it.
+    #[cfg(target_os = "linux")]
+    fn memory(input: &mut impl Read, limit: u64) -> io::Result<Result<Self, File>> {
+        use rustix::fs::{memfd_create, MemfdFlags};
+        use std::io::Seek;
+        use std::os::fd::AsRawFd;
+
+        let mut file = File::from(memfd_create("bcut-spool", MemfdFlags::CLOEXEC)?);
+        let len = io_copy(&mut input.take(limit.saturating_add(1)), &mut file)?;
+        if len > limit {
+            file.rewind()?;
+            return Ok(Err(file));
+        }
+        // opening this path opens the memory file again, so it works like any other input file
+        let path = PathBuf::from(format!("/proc/self/fd/{}", file.as_raw_fd()));
+        Ok(Ok(Self { path, memory: Some(file) }))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn memory(_input: &mut impl Read, _limit: u64) -> io::Result<Result<Self, File>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "spooling to memory isn't supported on this platform",
+        ))
+    }
+
+    /// Create a new uniquely named file in dir, which only we can read since it holds a copy of
+    /// whatever was piped to us
+    fn create(dir: &Path) -> io::Result<(Self, File)> {
+        let pid = std::process::id();
+        let mut options = File::options();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        for n in 0.. {
+            let path = dir.join(format!(".bcut-spool-{}-{}", pid, n));
+            match options.open(&path) {
+                Ok(file) => return Ok((Self { path, memory: None }, file)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        unreachable!()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        // a memory file goes away by itself once it's closed
+        if self.memory.is_none() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_and_removed() {
+        let dir = std::env::temp_dir();
+        let (a, _) = Spool::create(&dir).unwrap();
+        let (b, _) = Spool::create(&dir).unwrap();
+        assert_ne!(a.path(), b.path());
+        let path = a.path().to_owned();
+        assert!(path.exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        drop(a);
+        assert!(!path.exists());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn memory() {
+        let data: Vec<u8> = (0..=255).collect();
+        let Ok(spool) = Spool::memory(&mut &data[..], 256).unwrap() else {
+            panic!("256 bytes should fit in memory");
+        };
+        assert_eq!(fs::read(spool.path()).unwrap(), data);
+        assert_eq!(fs::metadata(spool.path()).unwrap().len(), 256);
+
+        // one byte more than the limit is read to find out that it doesn't fit
+        let mut input = &data[..];
+        let Err(mut head) = Spool::memory(&mut input, 100).unwrap() else {
+            panic!("256 bytes shouldn't fit in 100");
+        };
+        let mut copied = Vec::new();
+        head.read_to_end(&mut copied).unwrap();
+        copied.extend_from_slice(input);
+        assert_eq!(copied, data);
+    }
+}
@@ -0,0 +1,73 @@
+use std::io::{self, Read};
+
+use crate::mutate::Rng;
+
+/// Endless stream of zero bytes
+pub struct Zero;
+
+impl Read for Zero {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        buf.fill(0);
+        Ok(buf.len())
+    }
+}
+
+/// Endless stream of pseudo-random bytes. Every byte depends only on the seed and its offset in
+/// the stream, so any range of the same seed's stream is always the same data.
+pub struct Random {
+    rng: Rng,
+    block: [u8; 8],
+    /// position of the next byte in block
+    pos: usize,
+}
+
+impl Random {
+    /// Start the stream for seed at offset
+    pub fn new(seed: u64, offset: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        rng.skip(offset / 8);
+        let block = rng.next_u64().to_le_bytes();
+        Self { rng, block, pos: (offset % 8) as usize }
+    }
+}
+
+impl Read for Random {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        for byte in buf.iter_mut() {
+            if self.pos == self.block.len() {
+                self.block = self.rng.next_u64().to_le_bytes();
+                self.pos = 0;
+            }
+            *byte = self.block[self.pos];
+            self.pos += 1;
+        }
+        Ok(buf.len())
+    }
+}
+
+/// Pick a seed for --random when one isn't given
+pub fn random_seed() -> u64 {
+    let time =
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+    let mut rng = Rng::new(time.as_nanos() as u64 ^ u64::from(std::process::id()));
+    rng.next_u64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_offsets() {
+        let mut whole = [0u8; 64];
+        Random::new(7, 0).read_exact(&mut whole).unwrap();
+        for offset in [1, 8, 13, 40] {
+            let mut part = [0u8; 20];
+            Random::new(7, offset).read_exact(&mut part).unwrap();
+            assert_eq!(part, whole[offset as usize..offset as usize + 20]);
+        }
+        let mut other = [0u8; 64];
+        Random::new(8, 0).read_exact(&mut other).unwrap();
+        assert_ne!(whole, other);
+    }
+}
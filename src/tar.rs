@@ -0,0 +1,257 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Size of tar headers and the unit that member data is padded to
+const BLOCK_SIZE: u64 = 512;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TarError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("invalid tar header at offset {0:#x}")]
+    BadHeader(u64),
+    #[error("no member named '{0}' in the archive")]
+    NotFound(String),
+    #[error("tar member '{0}' isn't a regular file")]
+    NotRegular(String),
+    #[error("tar metadata of {0} bytes is larger than the parser limit")]
+    TooLarge(u64),
+}
+
+type Result<T> = std::result::Result<T, TarError>;
+
+/// An input which can skip over data it doesn't need. Files seek past it, anything else (like a
+/// decompressor) has to read through it.
+pub trait Skip: Read {
+    fn skip(&mut self, len: u64) -> io::Result<()>;
+}
+
+impl Skip for File {
+    fn skip(&mut self, len: u64) -> io::Result<()> {
+        let len = len.try_into().map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        self.seek(SeekFrom::Current(len)).map(drop)
+    }
+}
+
+impl Skip for Box<dyn Read + Send> {
+    fn skip(&mut self, len: u64) -> io::Result<()> {
+        let skipped = io::copy(&mut self.take(len), &mut io::sink())?;
+        if skipped < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+}
+
+/// Parse a numeric header field, which is octal text or, for big values, GNU's base-256 encoding
+/// with the high bit of the first byte set.
+fn parse_number(field: &[u8]) -> Option<u64> {
+    if field.first()? & 0x80 != 0 {
+        let mut value = u64::from(field[0] & 0x7f);
+        for &b in &field[1..] {
+            value = value.checked_mul(256)?.checked_add(b.into())?;
+        }
+        return Some(value);
+    }
+    let text = std::str::from_utf8(field).ok()?;
+    let text = text.trim_matches(|c| c == ' ' || c == '\0');
+    if text.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(text, 8).ok()
+}
+
+/// Get a NUL-terminated string field
+fn parse_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Normalize a member path for comparison, so "./dir/file" matches "dir/file"
+fn normalize(path: &str) -> &str {
+    let path = path.trim_start_matches("./").trim_start_matches('/');
+    path.trim_end_matches('/')
+}
+
+/// Check a header block's checksum, which is the sum of its bytes with the checksum field itself
+/// counted as spaces
+fn checksum_ok(header: &[u8]) -> bool {
+    let Some(expected) = parse_number(&header[148..156]) else {
+        return false;
+    };
+    let sum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { u64::from(b' ') } else { u64::from(b) })
+        .sum();
+    sum == expected
+}
+
+/// Get the path from a pax extended header's records, which look like "LEN path=VALUE\n"
+fn pax_path(data: &[u8]) -> Option<String> {
+    let mut rest = data;
+    let mut path = None;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|&b| b == b' ')?;
+        let len: usize = std::str::from_utf8(&rest[..space]).ok()?.parse().ok()?;
+        if len <= space || len > rest.len() {
+            return None;
+        }
+        let record = &rest[space + 1..len];
+        let record = record.strip_suffix(b"\n").unwrap_or(record);
+        if let Some(value) = record.strip_prefix(b"path=") {
+            path = Some(String::from_utf8_lossy(value).into_owned());
+        }
+        rest = &rest[len..];
+    }
+    path
+}
+
+/// Find the member named path in a tar archive, leaving the input positioned at the start of its
+/// data and returning its size. GNU long names and pax paths are supported, and limit caps the
+/// memory they can use.
+pub fn find_member<R: Skip>(input: &mut R, path: &str, limit: u64) -> Result<u64> {
+    let want = normalize(path);
+    let mut offset = 0;
+    let mut long_name = None;
+    let mut header = [0u8; BLOCK_SIZE as usize];
+    loop {
+        match input.read_exact(&mut header) {
+            Ok(()) => (),
+            // some archives are truncated without the end of archive blocks
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        if !checksum_ok(&header) {
+            return Err(TarError::BadHeader(offset));
+        }
+        let size = parse_number(&header[124..136]).ok_or(TarError::BadHeader(offset))?;
+        // a base-256 size can be anything, and no real member is too big to seek past
+        let padded = size
+            .div_ceil(BLOCK_SIZE)
+            .checked_mul(BLOCK_SIZE)
+            .filter(|&padded| i64::try_from(padded).is_ok())
+            .ok_or(TarError::BadHeader(offset))?;
+        offset = offset.checked_add(BLOCK_SIZE).ok_or(TarError::BadHeader(offset))?;
+        let kind = header[156];
+
+        // GNU long names and pax headers describe the next member
+        if matches!(kind, b'L' | b'x') {
+            if size > limit {
+                return Err(TarError::TooLarge(size));
+            }
+            let mut data = vec![0u8; size as usize];
+            input.read_exact(&mut data)?;
+            input.skip(padded - size)?;
+            offset = offset.checked_add(padded).ok_or(TarError::BadHeader(offset))?;
+            long_name = match kind {
+                b'L' => Some(parse_string(&data)),
+                _ => pax_path(&data).or(long_name),
+            };
+            continue;
+        }
+
+        let name = long_name.take().unwrap_or_else(|| {
+            let name = parse_string(&header[..100]);
+            // POSIX ustar splits long paths into a prefix and name, old GNU headers have other
+            // fields there
+            let prefix = parse_string(&header[345..500]);
+            if &header[257..263] == b"ustar\0" && !prefix.is_empty() {
+                format!("{}/{}", prefix, name)
+            } else {
+                name
+            }
+        });
+        if normalize(&name) == want && kind != b'g' {
+            return match kind {
+                b'0' | b'\0' | b'7' => Ok(size),
+                _ => Err(TarError::NotRegular(path.to_owned())),
+            };
+        }
+        input.skip(padded)?;
+        offset = offset.checked_add(padded).ok_or(TarError::BadHeader(offset))?;
+    }
+    Err(TarError::NotFound(path.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    /// Build a tar header block for a member
+    fn header(name: &str, kind: u8, size: u64) -> Vec<u8> {
+        let mut block = vec![0u8; BLOCK_SIZE as usize];
+        block[..name.len()].copy_from_slice(name.as_bytes());
+        block[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+        block[156] = kind;
+        block[257..263].copy_from_slice(b"ustar\0");
+        block[148..156].fill(b' ');
+        let sum: u32 = block.iter().map(|&b| u32::from(b)).sum();
+        block[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        block
+    }
+
+    fn member(name: &str, kind: u8, data: &[u8]) -> Vec<u8> {
+        let mut out = header(name, kind, data.len() as u64);
+        out.extend_from_slice(data);
+        out.resize(out.len().div_ceil(512) * 512, 0);
+        out
+    }
+
+    #[test]
+    fn find() {
+        let mut archive = member("./dir/", b'5', b"");
+        archive.extend(member("dir/a.bin", b'0', &[1; 600]));
+        archive.extend(member("././@LongLink", b'L', b"dir/a-very-long-name.bin\0"));
+        archive.extend(member("dir/a-very-long", b'0', b"hello"));
+        archive.extend([0; 1024]);
+
+        let find = |archive: &[u8], path: &str| {
+            let mut input: Box<dyn Read + Send> = Box::new(io::Cursor::new(archive.to_vec()));
+            find_member(&mut input, path, 1024).map(|size| {
+                let mut data = Vec::new();
+                input.take(size).read_to_end(&mut data).unwrap();
+                data
+            })
+        };
+        assert_eq!(find(&archive, "dir/a.bin").unwrap(), [1; 600]);
+        assert_eq!(find(&archive, "./dir/a-very-long-name.bin").unwrap(), b"hello");
+        assert!(matches!(find(&archive, "dir"), Err(TarError::NotRegular(_))));
+        assert!(matches!(find(&archive, "dir/a-very-long"), Err(TarError::NotFound(_))));
+
+        archive[148] ^= 1;
+        assert!(matches!(find(&archive, "dir/a.bin"), Err(TarError::BadHeader(0))));
+    }
+
+    #[test]
+    fn huge_size() {
+        // a base-256 size which can't be padded to a whole block, or seeked past
+        for size in [u64::MAX, u64::MAX / 2] {
+            let mut block = header("big", b'0', 0);
+            block[124..136].fill(0);
+            block[124] = 0x80;
+            block[128..136].copy_from_slice(&size.to_be_bytes());
+            block[148..156].fill(b' ');
+            let sum: u32 = block.iter().map(|&b| u32::from(b)).sum();
+            block[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+
+            let path = TempPath::new("tar-huge");
+            std::fs::write(&path, &block).unwrap();
+            let mut input = File::open(&path).unwrap();
+            let res = find_member(&mut input, "other", 1024);
+            assert!(matches!(res, Err(TarError::BadHeader(0))), "{:?}", res);
+        }
+    }
+
+    #[test]
+    fn pax() {
+        assert_eq!(pax_path(b"18 path=some/file\n11 uid=100\n").as_deref(), Some("some/file"));
+        assert_eq!(pax_path(b"99 path=x\n"), None);
+        assert_eq!(parse_number(b"0000644\0"), Some(0o644));
+        assert_eq!(parse_number(&[0x80, 0, 0, 1, 0]), Some(256));
+    }
+}
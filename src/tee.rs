@@ -0,0 +1,59 @@
+use std::io::{self, Write};
+
+/// Writer which copies everything to several outputs
+pub struct Tee {
+    outputs: Vec<Box<dyn Write>>,
+}
+
+impl Tee {
+    pub fn new(outputs: Vec<Box<dyn Write>>) -> Self {
+        Self { outputs }
+    }
+}
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // every output has to get the whole buffer, or they'd fall out of step with each other
+        for output in &mut self.outputs {
+            output.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for output in &mut self.outputs {
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Vec writer which can still be looked at after being boxed into the Tee
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn copies() {
+        let (a, b) = (Shared::default(), Shared::default());
+        let mut tee = Tee::new(vec![Box::new(a.clone()), Box::new(b.clone())]);
+        tee.write_all(b"hello ").unwrap();
+        tee.write_all(b"world").unwrap();
+        assert_eq!(*a.0.lock().unwrap(), b"hello world");
+        assert_eq!(*b.0.lock().unwrap(), b"hello world");
+    }
+}
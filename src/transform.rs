@@ -0,0 +1,224 @@
+use std::fmt;
+use std::io::{self, Read};
+
+use crate::format::hex_string;
+use crate::records::decode_hex;
+
+/// Compressed stream formats which can be inflated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// raw deflate data with no header
+    Deflate,
+    Zlib,
+    Gzip,
+}
+
+/// An operation which turns the stored bytes of a region into the bytes it should contain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transform {
+    /// XOR with a repeating key
+    Xor(Vec<u8>),
+    /// Reverse the byte order of every word of this many bytes
+    Swap(usize),
+    /// Decompress
+    Inflate(Compression),
+}
+
+/// XOR data with a repeating key, starting at the beginning of the key
+pub fn xor(data: &mut [u8], key: &[u8]) {
+    for (b, k) in data.iter_mut().zip(key.iter().cycle()) {
+        *b ^= k;
+    }
+}
+
+/// Reverse the byte order of each width-byte word of data. A partial word at the end is left
+/// alone.
+pub fn byte_swap(data: &mut [u8], width: usize) {
+    for word in data.chunks_exact_mut(width) {
+        word.reverse();
+    }
+}
+
+/// Reader which applies transforms to a stream as it's read, in order. Only xor and swap work on
+/// a stream, since inflate needs all of the data at once. Words are counted from the start of the
+/// stream, and a partial word at the end isn't swapped.
+pub struct TransformReader<R> {
+    inner: R,
+    transforms: Vec<Transform>,
+    /// largest swap width, the buffer is only transformed in whole words of this size
+    width: usize,
+    buf: Vec<u8>,
+    /// transformed data which hasn't been read yet is buf[pos..ready], followed by any partial
+    /// word up to filled
+    pos: usize,
+    ready: usize,
+    filled: usize,
+    /// offset of the start of buf in the stream
+    offset: u64,
+}
+
+impl<R: Read> TransformReader<R> {
+    pub fn new(inner: R, transforms: Vec<Transform>) -> Self {
+        let width = transforms
+            .iter()
+            .map(|t| match t {
+                Transform::Swap(width) => *width,
+                Transform::Xor(_) => 1,
+                Transform::Inflate(_) => panic!("inflate can't be applied to a stream"),
+            })
+            .max()
+            .unwrap_or(1);
+        let buf = vec![0; 64 * 1024];
+        Self { inner, transforms, width, buf, pos: 0, ready: 0, filled: 0, offset: 0 }
+    }
+
+    /// Read at least one more whole word into the buffer, or whatever is left at EOF, and
+    /// transform it
+    fn fill(&mut self) -> io::Result<()> {
+        self.buf.copy_within(self.ready..self.filled, 0);
+        self.offset += self.ready as u64;
+        self.filled -= self.ready;
+        self.pos = 0;
+        self.ready = 0;
+        while self.ready == 0 {
+            let count = self.inner.read(&mut self.buf[self.filled..])?;
+            self.filled += count;
+            self.ready =
+                if count == 0 { self.filled } else { self.filled - self.filled % self.width };
+            if count == 0 {
+                break;
+            }
+        }
+
+        let data = &mut self.buf[..self.ready];
+        for transform in &self.transforms {
+            match transform {
+                Transform::Swap(width) => byte_swap(data, *width),
+                Transform::Xor(key) => {
+                    // pick up the key where the last part of the stream left off
+                    let skip = (self.offset % key.len() as u64) as usize;
+                    for (b, k) in data.iter_mut().zip(key.iter().cycle().skip(skip)) {
+                        *b ^= k;
+                    }
+                }
+                Transform::Inflate(_) => unreachable!(),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for TransformReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.ready {
+            self.fill()?;
+        }
+        let count = buf.len().min(self.ready - self.pos);
+        buf[..count].copy_from_slice(&self.buf[self.pos..self.pos + count]);
+        self.pos += count;
+        Ok(count)
+    }
+}
+
+/// Parse the word size for byte swapping
+pub fn parse_swap_width(value: &str) -> Result<usize, &'static str> {
+    match value {
+        "2" | "4" | "8" => Ok(value.parse().unwrap()),
+        _ => Err("swap width must be 2, 4, or 8"),
+    }
+}
+
+/// Decompress all of data
+pub fn inflate(data: &[u8], compression: Compression) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    match compression {
+        Compression::Deflate => flate2::read::DeflateDecoder::new(data).read_to_end(&mut out)?,
+        Compression::Zlib => flate2::read::ZlibDecoder::new(data).read_to_end(&mut out)?,
+        Compression::Gzip => flate2::read::GzDecoder::new(data).read_to_end(&mut out)?,
+    };
+    Ok(out)
+}
+
+impl Transform {
+    /// Parse a transform from its KEY=VALUE form, e.g. "xor=5a" or "swap=4"
+    pub fn parse(key: &str, value: &str) -> Result<Self, &'static str> {
+        match key {
+            "xor" => match decode_hex(value.strip_prefix("0x").unwrap_or(value)) {
+                Some(k) if !k.is_empty() => Ok(Self::Xor(k)),
+                _ => Err("xor key must be hex bytes"),
+            },
+            "swap" => parse_swap_width(value).map(Self::Swap),
+            "inflate" => match value {
+                "deflate" => Ok(Self::Inflate(Compression::Deflate)),
+                "zlib" => Ok(Self::Inflate(Compression::Zlib)),
+                "gzip" => Ok(Self::Inflate(Compression::Gzip)),
+                _ => Err("inflate format must be deflate, zlib, or gzip"),
+            },
+            _ => Err("unknown transform"),
+        }
+    }
+
+    /// Apply the transform to data
+    pub fn apply(&self, mut data: Vec<u8>) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Xor(key) => xor(&mut data, key),
+            Self::Swap(width) => byte_swap(&mut data, *width),
+            Self::Inflate(compression) => return inflate(&data, *compression),
+        }
+        Ok(data)
+    }
+}
+
+/// Format a transform in the KEY=VALUE form accepted by Transform::parse
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Xor(key) => write!(f, "xor={}", hex_string(key)),
+            Self::Swap(width) => write!(f, "swap={}", width),
+            Self::Inflate(Compression::Deflate) => write!(f, "inflate=deflate"),
+            Self::Inflate(Compression::Zlib) => write!(f, "inflate=zlib"),
+            Self::Inflate(Compression::Gzip) => write!(f, "inflate=gzip"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transforms() {
+        let xor = Transform::parse("xor", "0x0f01").unwrap();
+        assert_eq!(xor.apply(vec![0x00, 0x00, 0xff]).unwrap(), [0x0f, 0x01, 0xf0]);
+        assert_eq!(xor.to_string(), "xor=0f01");
+
+        let swap = Transform::parse("swap", "4").unwrap();
+        assert_eq!(swap.apply(b"abcdefghij".to_vec()).unwrap(), b"dcbahgfeij");
+
+        assert_eq!(Transform::parse("swap", "3"), Err("swap width must be 2, 4, or 8"));
+
+        // words which are split between reads still get swapped
+        let data: Vec<u8> = (0..100u8).collect();
+        let input = data[..13].chain(&data[13..50]).chain(&data[50..]);
+        let mut out = Vec::new();
+        TransformReader::new(input, vec![Transform::Swap(8)]).read_to_end(&mut out).unwrap();
+        let mut expected = data.clone();
+        byte_swap(&mut expected, 8);
+        assert_eq!(out, expected);
+        assert_eq!(&out[96..], [96, 97, 98, 99]);
+
+        // and keys carry on between reads
+        let input = data[..13].chain(&data[13..50]).chain(&data[50..]);
+        let transforms = vec![Transform::Swap(2), Transform::Xor(vec![0xff, 0x0f, 0x00])];
+        out.clear();
+        TransformReader::new(input, transforms.clone()).read_to_end(&mut out).unwrap();
+        let expected = transforms.iter().fold(data.clone(), |d, t| t.apply(d).unwrap());
+        assert_eq!(out, expected);
+        assert_eq!(Transform::parse("xor", ""), Err("xor key must be hex bytes"));
+        assert_eq!(Transform::parse("rot13", "1"), Err("unknown transform"));
+        assert_eq!(
+            Transform::parse("inflate", "gzip").unwrap(),
+            Transform::Inflate(Compression::Gzip)
+        );
+    }
+}
@@ -0,0 +1,246 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// End of central directory record signature, and the record's size without its comment
+const EOCD_SIG: u32 = 0x0605_4b50;
+const EOCD_SIZE: usize = 22;
+/// The EOCD is followed by a comment of up to 64k
+const MAX_COMMENT: usize = 0xffff;
+/// Zip64 end of central directory locator and record signatures
+const ZIP64_LOCATOR_SIG: u32 = 0x0706_4b50;
+const ZIP64_EOCD_SIG: u32 = 0x0606_4b50;
+/// Central directory file header and local file header signatures
+const CENTRAL_SIG: u32 = 0x0201_4b50;
+const LOCAL_SIG: u32 = 0x0403_4b50;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ZipError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("not a zip archive")]
+    NotZip,
+    #[error("corrupt zip archive: {0}")]
+    Corrupt(&'static str),
+    #[error("no member named '{0}' in the archive")]
+    NotFound(String),
+    #[error("zip member '{0}' is encrypted")]
+    Encrypted(String),
+    #[error("zip member '{0}' uses unsupported compression method {1}")]
+    Unsupported(String, u16),
+    #[error("zip central directory of {0} bytes is larger than the parser limit")]
+    TooLarge(u64),
+}
+
+type Result<T> = std::result::Result<T, ZipError>;
+
+/// How a member's data is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Stored,
+    Deflated,
+}
+
+/// Location of a member's data in the archive
+#[derive(Debug, PartialEq, Eq)]
+pub struct Member {
+    /// offset of the start of the (possibly compressed) data
+    pub offset: u64,
+    /// size of the data in the archive
+    pub compressed_size: u64,
+    /// size of the data once decompressed
+    pub size: u64,
+    pub method: Method,
+}
+
+fn le16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes(buf[off..off + 2].try_into().unwrap())
+}
+
+fn le32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+fn le64(buf: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(buf[off..off + 8].try_into().unwrap())
+}
+
+fn read_at<R: Read + Seek>(input: &mut R, offset: u64, len: usize) -> Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    input.seek(SeekFrom::Start(offset))?;
+    input.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Find the central directory, returning its offset and size
+fn central_directory<R: Read + Seek>(input: &mut R) -> Result<(u64, u64)> {
+    let file_size = input.seek(SeekFrom::End(0))?;
+    if file_size < EOCD_SIZE as u64 {
+        return Err(ZipError::NotZip);
+    }
+    // the EOCD is at the very end unless there's a comment, so search backwards for it
+    let tail_len = file_size.min((EOCD_SIZE + MAX_COMMENT) as u64);
+    let tail_start = file_size - tail_len;
+    let tail = read_at(input, tail_start, tail_len as usize)?;
+    let eocd = (0..=tail.len() - EOCD_SIZE)
+        .rev()
+        .find(|&i| le32(&tail, i) == EOCD_SIG)
+        .ok_or(ZipError::NotZip)?;
+
+    let size = le32(&tail, eocd + 12);
+    let offset = le32(&tail, eocd + 16);
+    if size != u32::MAX && offset != u32::MAX {
+        return Ok((offset.into(), size.into()));
+    }
+
+    // zip64, the locator just before the EOCD says where the real sizes are
+    let eocd_offset = tail_start + eocd as u64;
+    let locator = read_at(input, eocd_offset.checked_sub(20).ok_or(ZipError::NotZip)?, 20)?;
+    if le32(&locator, 0) != ZIP64_LOCATOR_SIG {
+        return Err(ZipError::Corrupt("missing zip64 locator"));
+    }
+    let record = read_at(input, le64(&locator, 8), 56)?;
+    if le32(&record, 0) != ZIP64_EOCD_SIG {
+        return Err(ZipError::Corrupt("bad zip64 end of central directory"));
+    }
+    Ok((le64(&record, 48), le64(&record, 40)))
+}
+
+/// Normalize a member path for comparison, so "./dir/file" matches "dir/file"
+fn normalize(path: &str) -> &str {
+    path.trim_start_matches("./").trim_start_matches('/')
+}
+
+/// Find the member named path using the archive's central directory, which may take up to limit
+/// bytes of memory.
+pub fn find_member<R: Read + Seek>(input: &mut R, path: &str, limit: u64) -> Result<Member> {
+    let (cd_offset, cd_size) = central_directory(input)?;
+    if cd_size > limit {
+        return Err(ZipError::TooLarge(cd_size));
+    }
+    let cd = read_at(input, cd_offset, cd_size as usize)?;
+    let want = normalize(path);
+
+    let mut pos = 0;
+    while pos + 46 <= cd.len() {
+        if le32(&cd, pos) != CENTRAL_SIG {
+            return Err(ZipError::Corrupt("bad central directory entry"));
+        }
+        let flags = le16(&cd, pos + 8);
+        let method = le16(&cd, pos + 10);
+        let mut compressed_size = u64::from(le32(&cd, pos + 20));
+        let mut size = u64::from(le32(&cd, pos + 24));
+        let name_len = le16(&cd, pos + 28) as usize;
+        let extra_len = le16(&cd, pos + 30) as usize;
+        let comment_len = le16(&cd, pos + 32) as usize;
+        let mut local_offset = u64::from(le32(&cd, pos + 42));
+        let name_start = pos + 46;
+        let extra_start = name_start + name_len;
+        let next = extra_start + extra_len + comment_len;
+        if next > cd.len() {
+            return Err(ZipError::Corrupt("central directory entry is truncated"));
+        }
+        pos = next;
+
+        let name = String::from_utf8_lossy(&cd[name_start..extra_start]);
+        if normalize(&name) != want {
+            continue;
+        }
+
+        // zip64 sizes and offsets are in an extra field, only for the ones which didn't fit
+        let mut extra = &cd[extra_start..extra_start + extra_len];
+        while extra.len() >= 4 {
+            let (id, len) = (le16(extra, 0), le16(extra, 2) as usize);
+            let data = extra.get(4..4 + len).ok_or(ZipError::Corrupt("bad extra field"))?;
+            if id == 0x0001 {
+                let mut values = data.chunks_exact(8).map(|c| le64(c, 0));
+                for field in [&mut size, &mut compressed_size, &mut local_offset] {
+                    if *field == u64::from(u32::MAX) {
+                        *field = values.next().ok_or(ZipError::Corrupt("bad zip64 field"))?;
+                    }
+                }
+            }
+            extra = &extra[4 + len..];
+        }
+
+        if flags & 1 != 0 {
+            return Err(ZipError::Encrypted(path.to_owned()));
+        }
+        let method = match method {
+            0 => Method::Stored,
+            8 => Method::Deflated,
+            _ => return Err(ZipError::Unsupported(path.to_owned(), method)),
+        };
+
+        // the data follows the local header, whose name and extra field can differ in length
+        // from the central directory's
+        let local = read_at(input, local_offset, 30)?;
+        if le32(&local, 0) != LOCAL_SIG {
+            return Err(ZipError::Corrupt("bad local file header"));
+        }
+        let offset = local_offset + 30 + u64::from(le16(&local, 26)) + u64::from(le16(&local, 28));
+        return Ok(Member { offset, compressed_size, size, method });
+    }
+    Err(ZipError::NotFound(path.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a zip archive of stored members
+    fn archive(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut cd = Vec::new();
+        for (name, data) in members {
+            let offset = out.len() as u32;
+            let mut common = Vec::new();
+            common.extend(20u16.to_le_bytes()); // version needed
+            common.extend(0u16.to_le_bytes()); // flags
+            common.extend(0u16.to_le_bytes()); // method
+            common.extend([0; 8]); // time, date, crc
+            common.extend((data.len() as u32).to_le_bytes());
+            common.extend((data.len() as u32).to_le_bytes());
+            common.extend((name.len() as u16).to_le_bytes());
+
+            out.extend(LOCAL_SIG.to_le_bytes());
+            out.extend(&common);
+            out.extend(4u16.to_le_bytes()); // extra length
+            out.extend(name.as_bytes());
+            out.extend([0; 4]);
+            out.extend(*data);
+
+            cd.extend(CENTRAL_SIG.to_le_bytes());
+            cd.extend(20u16.to_le_bytes()); // version made by
+            cd.extend(&common);
+            cd.extend([0; 12]); // extra, comment, disk, attributes
+            cd.extend(offset.to_le_bytes());
+            cd.extend(name.as_bytes());
+        }
+        let cd_offset = out.len() as u32;
+        out.extend(&cd);
+        out.extend(EOCD_SIG.to_le_bytes());
+        out.extend([0; 4]);
+        out.extend((members.len() as u16).to_le_bytes());
+        out.extend((members.len() as u16).to_le_bytes());
+        out.extend((cd.len() as u32).to_le_bytes());
+        out.extend(cd_offset.to_le_bytes());
+        out.extend(5u16.to_le_bytes());
+        out.extend(b"hello");
+        out
+    }
+
+    #[test]
+    fn find() {
+        let zip = archive(&[("a.txt", b"first"), ("dir/b.bin", b"second member")]);
+        let mut input = io::Cursor::new(&zip);
+        let member = find_member(&mut input, "./dir/b.bin", 1024).unwrap();
+        assert_eq!(member.method, Method::Stored);
+        assert_eq!(member.size, 13);
+        let start = member.offset as usize;
+        assert_eq!(&zip[start..start + 13], b"second member");
+
+        assert!(matches!(find_member(&mut input, "c", 1024), Err(ZipError::NotFound(_))));
+        assert!(matches!(find_member(&mut input, "a.txt", 10), Err(ZipError::TooLarge(_))));
+        let mut input = io::Cursor::new(b"not a zip file at all, really");
+        assert!(matches!(find_member(&mut input, "a.txt", 1024), Err(ZipError::NotZip)));
+    }
+}